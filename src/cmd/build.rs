@@ -1,4 +1,5 @@
 use crate::prelude::*;
+use crate::logger::{self, Event};
 use crate::starrod::StarRod;
 use structopt::StructOpt;
 use super::CommandContext;
@@ -10,10 +11,36 @@ pub struct Opt {
     /// Skip compilation via Star Rod.
     #[structopt(long)]
     no_compile: bool,
+
+    /// Build with strings translated to the given locale, e.g. `fr`. Strings without a
+    /// translation are left in the base language.
+    #[structopt(long)]
+    locale: Option<String>,
 }
 
 pub fn run(ctx: CommandContext, opt: Opt) -> Result<()> {
-    let mut package = ctx.package?;
+    let result = build(ctx, opt);
+
+    logger::event(Event::BuildFinished {
+        success: result.is_ok(),
+    });
+
+    result
+}
+
+fn build(ctx: CommandContext, opt: Opt) -> Result<()> {
+    let mut diagnostics = ctx.diagnostics;
+    let mut package = match ctx.package {
+        Ok(package) => package,
+        Err(err) => {
+            // Report the fatal error alongside whatever we found before it.
+            diagnostics.report(err);
+            return diagnostics.flush().map_err(Into::into);
+        },
+    };
+
+    // Don't bother assembling if any exports failed to load.
+    diagnostics.flush()?;
 
     trace!("assembling package:\n{:#?}", &package);
 
@@ -25,8 +52,19 @@ pub fn run(ctx: CommandContext, opt: Opt) -> Result<()> {
     }
 
     let start_time = Instant::now();
-    package.assemble(&build_dir)?;
-    info!("assembled {} in {}s", &package, start_time.elapsed().as_secs_f32());
+    if let Err(err) = package.assemble(&build_dir, opt.locale.as_deref(), &mut diagnostics) {
+        // Report the fatal error after any warnings found before it.
+        diagnostics.report(err);
+    }
+    diagnostics.flush()?;
+
+    let seconds = start_time.elapsed().as_secs_f32();
+    info!("assembled {} in {}s", &package, seconds);
+    logger::event(Event::Assembled {
+        package: package.name(),
+        build_dir: &build_dir,
+        seconds,
+    });
 
     if opt.no_compile {
         debug!("skipping compilation due to --no-compile flag");
@@ -0,0 +1,46 @@
+use crate::prelude::*;
+use structopt::StructOpt;
+use super::CommandContext;
+
+macro_rules! explanations {
+    ($($code:literal),* $(,)?) => {
+        /// Error code explanations. These are shared with the mdBook reference (`docs/reference/errors.md`).
+        const EXPLANATIONS: &[(&str, &str)] = &[
+            $(($code, include_str!(concat!("../../docs/reference/errors/", $code, ".md"))),)*
+        ];
+    };
+}
+
+explanations!(
+    "SP0001", "SP0002", "SP0003", "SP0004", "SP0005", "SP0006",
+    "SP0010", "SP0011", "SP0020", "SP0030", "SP0101", "SP0102",
+    "SP0103", "SP0104", "SP0105", "SP0106", "SP0107", "SP0108",
+    "SP0201", "SP0202", "SP0203", "SP0204", "SP0205", "SP0206",
+    "SP0207", "SP0208", "SP0209", "SP0210", "SP0301", "SP0302",
+    "SP0303", "SP0304", "SP0305", "SP0306", "SP0307", "SP0308",
+    "SP0309", "SP0310", "SP0311", "SP0312", "SP0313", "SP0314",
+    "SP0315", "SP0316", "SP0401", "SP0402", "SP0403", "SP0404",
+    "SP0405", "SP0406", "SP0407", "SP0408", "SP0409", "SP0410",
+    "SP0411", "SP0412", "SP0413", "SP0414", "SP0415", "SP0416",
+    "SP0417", "SP0418", "SP0419", "SP0420", "SP0421", "SP0501",
+    "SP0502", "SP0503", "SP0504", "SP0505", "SP0506", "SP0507",
+    "SP0508", "SP0509", "SP0510", "SP0511", "SP0512",
+);
+
+#[derive(StructOpt, Debug)]
+pub struct Opt {
+    /// The error code to explain, e.g. SP0202
+    code: String,
+}
+
+pub fn run(_ctx: CommandContext, opt: Opt) -> Result<()> {
+    let code = opt.code.to_uppercase();
+
+    match EXPLANATIONS.iter().find(|(c, _)| *c == code) {
+        Some((_, explanation)) => {
+            print!("{}", explanation);
+            Ok(())
+        },
+        None => Err(anyhow!("no such error code: {}", opt.code)),
+    }
+}
@@ -1,12 +1,19 @@
 pub mod new;
 pub mod build;
+pub mod explain;
+pub mod preview;
+pub mod strings;
 
 use crate::prelude::*;
-use crate::package::Package;
+use crate::package::{Package, LoadError, FindError};
+use crate::diagnostics::{Diagnostics, Diagnostic};
 
 #[derive(Debug)]
 pub struct CommandContext {
     package: Result<Package, TaggedError>,
+
+    /// Recoverable errors encountered whilst loading the package.
+    diagnostics: Diagnostics,
 }
 
 /// If the package could not be read, we also remember the path it was supposed to be at.
@@ -19,24 +26,41 @@ pub struct TaggedError {
     source: Error,
 }
 
+impl From<TaggedError> for Diagnostic {
+    fn from(error: TaggedError) -> Diagnostic {
+        // Package load errors may be able to point at the offending part of the manifest.
+        match error.source.downcast::<LoadError>() {
+            Ok(error) => error.into(),
+            Err(error) => match error.downcast::<FindError>() {
+                Ok(FindError::LoadError(error)) => error.into(),
+                Ok(error) => Error::from(error).into(),
+                Err(error) => error.into(),
+            },
+        }
+    }
+}
+
 impl CommandContext {
     pub fn new(package_dir: Option<PathBuf>) -> CommandContext {
+        let mut diagnostics = Diagnostics::new();
+
         CommandContext {
             package: match package_dir {
-                Some(path) => Package::load(&path)
+                Some(path) => Package::load(&path, &mut diagnostics)
                     .map_err(|err| TaggedError {
                         dir: path.to_owned(),
                         source: err.into(),
                     }),
                 None => {
                     let current_dir = std::env::current_dir().unwrap();
-                    Package::find(&current_dir)
+                    Package::find(&current_dir, &mut diagnostics)
                         .map_err(|err| TaggedError {
                             dir: current_dir,
                             source: err.into(),
                         })
                 },
             },
+            diagnostics,
         }
     }
 
@@ -0,0 +1,105 @@
+use crate::prelude::*;
+use crate::package::PreviewFormat;
+use structopt::StructOpt;
+use super::CommandContext;
+
+#[derive(StructOpt, Debug)]
+pub enum Opt {
+    /// Renders a sprite animation to an animated GIF or APNG
+    Sprite(SpriteOpt),
+}
+
+#[derive(StructOpt, Debug)]
+pub struct SpriteOpt {
+    /// The sprite to preview, e.g. fizzlit
+    id: String,
+
+    /// The animation to render [default: the sprite's first animation]
+    #[structopt(long)]
+    anim: Option<String>,
+
+    /// The palette to draw every raster with, overriding the animation's: a name, or an index in hex, e.g. A [default: the animation's]
+    #[structopt(long)]
+    palette: Option<String>,
+
+    /// Render every frame of every animation to a PNG, one row per animation
+    #[structopt(long, conflicts_with = "anim")]
+    contact_sheet: bool,
+
+    /// Scale the preview up by this factor
+    #[structopt(long, default_value = "1")]
+    scale: u32,
+
+    /// Where to write the preview; a .gif, .png or .apng file [default: .build/preview/<sprite>_<anim>.gif]
+    #[structopt(short, long, parse(from_os_str))]
+    output: Option<PathBuf>,
+}
+
+pub fn run(ctx: CommandContext, opt: Opt) -> Result<()> {
+    let mut diagnostics = ctx.diagnostics;
+    let package = match ctx.package {
+        Ok(package) => package,
+        Err(err) => {
+            diagnostics.report(err);
+            return diagnostics.flush().map_err(Into::into);
+        },
+    };
+    diagnostics.flush()?;
+
+    match opt {
+        Opt::Sprite(opt) => {
+            let sprite = package.sprite(&opt.id)?;
+
+            let palette = match &opt.palette {
+                None => None,
+                Some(name) => Some(sprite.palette_by_name(name)
+                    .or_else(|| usize::from_str_radix(name, 16).ok()
+                        .filter(|&idx| idx < sprite.palette_names().len()))
+                    .ok_or_else(|| anyhow!("sprite '{}' has no palette '{}' (it has: {})",
+                        opt.id, name, sprite.palette_names().join(", ")))?),
+            };
+
+            let (contents, default_name) = if opt.contact_sheet {
+                let contents = sprite.contact_sheet(palette, opt.scale)?;
+                (contents, format!("{}.png", sprite.name()))
+            } else {
+                let animation = match &opt.anim {
+                    Some(name) => sprite.animation_by_name(name)
+                        .ok_or_else(|| anyhow!("sprite '{}' has no animation '{}' (it has: {})",
+                            opt.id, name, sprite.animation_names().join(", ")))?,
+                    None if sprite.animation_names().is_empty() => {
+                        return Err(anyhow!("sprite '{}' has no animations", opt.id));
+                    },
+                    None => 0,
+                };
+
+                let path = opt.output.clone().unwrap_or_default();
+                let format = match path.extension().and_then(|ext| ext.to_str()) {
+                    None | Some("gif") => PreviewFormat::Gif,
+                    Some("png") | Some("apng") => PreviewFormat::Apng,
+                    Some(ext) => return Err(anyhow!("unable to preview as .{} (try .gif or .png)", ext)),
+                };
+
+                let contents = sprite.preview(animation, palette, opt.scale, format)?;
+                let name = format!("{}_{}.gif", sprite.name(), sprite.animation_names()[animation]);
+                (contents, name)
+            };
+
+            let path = match opt.output {
+                Some(path) => path,
+                None => {
+                    let dir = package.dir.join(".build/preview");
+                    fs::create_dir_all(&dir)
+                        .with_context(|| format!("unable to create {}", dir.display()))?;
+                    dir.join(default_name)
+                },
+            };
+
+            fs::write(&path, contents)
+                .with_context(|| format!("unable to write {}", path.display()))?;
+            info!("wrote preview to {}", path.display());
+
+            Ok(())
+        },
+    }
+}
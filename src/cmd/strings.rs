@@ -0,0 +1,84 @@
+use crate::prelude::*;
+use crate::package::{StringFormat, write_entries};
+use structopt::StructOpt;
+use super::CommandContext;
+
+#[derive(StructOpt, Debug)]
+pub enum Opt {
+    /// Writes the package's strings to a PO or CSV file for translators
+    Export(ExportOpt),
+
+    /// Reads translated strings from a PO or CSV file into a locale's string files
+    Import(ImportOpt),
+}
+
+#[derive(StructOpt, Debug)]
+pub struct ExportOpt {
+    /// The format to write
+    #[structopt(long, default_value = "po", possible_values = &["po", "csv"])]
+    format: StringFormat,
+
+    /// Include existing translations into this locale, e.g. fr
+    #[structopt(long)]
+    locale: Option<String>,
+
+    /// Where to write the strings [default: stdout]
+    #[structopt(short, long, parse(from_os_str))]
+    output: Option<PathBuf>,
+}
+
+#[derive(StructOpt, Debug)]
+pub struct ImportOpt {
+    /// The PO or CSV file to read
+    #[structopt(parse(from_os_str))]
+    file: PathBuf,
+
+    /// The locale the strings are translated into, e.g. fr
+    #[structopt(long)]
+    locale: String,
+
+    /// The format to read [default: from the file's extension]
+    #[structopt(long, possible_values = &["po", "csv"])]
+    format: Option<StringFormat>,
+}
+
+pub fn run(ctx: CommandContext, opt: Opt) -> Result<()> {
+    let mut diagnostics = ctx.diagnostics;
+    let package = match ctx.package {
+        Ok(package) => package,
+        Err(err) => {
+            diagnostics.report(err);
+            return diagnostics.flush().map_err(Into::into);
+        },
+    };
+    diagnostics.flush()?;
+
+    match opt {
+        Opt::Export(opt) => {
+            let entries = package.string_entries(opt.locale.as_deref());
+            let exported = write_entries(opt.format, &entries, opt.locale.as_deref());
+
+            match &opt.output {
+                Some(path) => {
+                    fs::write(path, exported)
+                        .with_context(|| format!("unable to write {}", path.display()))?;
+                    info!("exported {} strings to {}", entries.len(), path.display());
+                },
+                None => print!("{}", exported),
+            }
+        },
+        Opt::Import(opt) => {
+            let format = match opt.format {
+                Some(format) => format,
+                None => StringFormat::from_path(&opt.file)
+                    .ok_or_else(|| anyhow!("unable to tell the format of {} (use --format)", opt.file.display()))?,
+            };
+
+            let count = package.import_strings(&opt.locale, &opt.file, format, &mut diagnostics)?;
+            diagnostics.flush()?;
+            info!("imported {} translations into locale '{}'", count, opt.locale);
+        },
+    }
+
+    Ok(())
+}
@@ -0,0 +1,484 @@
+use crate::prelude::*;
+use crate::logger;
+use regex::Regex;
+
+/// Collects recoverable errors (bad names, unknown identifiers, missing animations, etc.) and
+/// warnings so that as many problems as possible can be reported in one go, rather than stopping at
+/// the first.
+#[derive(Debug, Default)]
+pub struct Diagnostics {
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Diagnostics {
+    pub fn new() -> Diagnostics {
+        Diagnostics::default()
+    }
+
+    /// Records a diagnostic, such as an error that knows which source code caused it.
+    pub fn report<D: Into<Diagnostic>>(&mut self, diagnostic: D) {
+        self.diagnostics.push(diagnostic.into());
+    }
+
+    /// Records a recoverable error.
+    pub fn error<E: Into<Error>>(&mut self, error: E) {
+        self.report(error.into());
+    }
+
+    /// Converts `result` into an Option, recording its error if it has one.
+    pub fn ok<T, E: Into<Error>>(&mut self, result: Result<T, E>) -> Option<T> {
+        match result {
+            Ok(value) => Some(value),
+            Err(error) => {
+                self.error(error);
+                None
+            },
+        }
+    }
+
+    /// Records a warning, which is printed but doesn't stop the build.
+    pub fn warn(&mut self, diagnostic: Diagnostic) {
+        self.report(diagnostic.with_severity(Severity::Warning));
+    }
+
+    pub fn error_count(&self) -> usize {
+        self.diagnostics.iter()
+            .filter(|diagnostic| diagnostic.severity == Severity::Error)
+            .count()
+    }
+
+    /// Prints every diagnostic collected so far, then forgets them. Fails with a summary if there
+    /// were any errors to print.
+    pub fn flush(&mut self) -> Result<(), Aborted> {
+        let count = self.error_count();
+
+        for diagnostic in self.diagnostics.drain(..) {
+            diagnostic.emit();
+        }
+
+        if count == 0 {
+            Ok(())
+        } else {
+            Err(Aborted { count })
+        }
+    }
+}
+
+/// An error with a stable code, such as `SP0202`, which `starpkg explain` can tell you more about.
+/// Variants that wrap another error should use the code of the wrapped error.
+pub trait Code {
+    fn code(&self) -> Option<&'static str>;
+}
+
+/// Finds the code of `error`, if it is one of ours.
+fn code_of(error: &(dyn std::error::Error + 'static)) -> Option<&'static str> {
+    use crate::sanitize::*;
+    use crate::package::*;
+
+    macro_rules! try_downcast {
+        ($($t:ty),*) => {
+            $(if let Some(error) = error.downcast_ref::<$t>() {
+                return error.code();
+            })*
+        };
+    }
+
+    try_downcast!(
+        GenericNameError, PackageNameError, ExportNameError, DependencyNameError, LocaleError,
+        LoadError, FindError, ResolveError, TextLoadError, SpriteLoadError, ImportError,
+        ActorLoadError
+    );
+
+    error.downcast_ref::<Diagnostic>().and_then(|diagnostic| diagnostic.code)
+}
+
+#[derive(Error, Debug)]
+#[error("aborting due to {count} previous error{}", if *count == 1 { "" } else { "s" })]
+pub struct Aborted {
+    count: usize,
+}
+
+/// How bad a [Diagnostic] is.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// Stops the build.
+    Error,
+
+    /// Something that is probably a mistake, but can still be built.
+    Warning,
+}
+
+/// A compiler-style error or warning message, optionally pointing at the source code that caused
+/// it.
+///
+/// Diagnostics can travel through `anyhow::Error` like any other error; converting that error
+/// back into a `Diagnostic` recovers the labels and notes.
+#[derive(Error, Debug, Clone)]
+#[error("{message}")]
+pub struct Diagnostic {
+    severity: Severity,
+    code: Option<&'static str>,
+    message: String,
+
+    /// The first label is the primary one; it's where the diagnostic is reported to be.
+    labels: Vec<Label>,
+
+    notes: Vec<Note>,
+
+    /// Underlying causes, printed in trace mode only.
+    causes: Vec<String>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+struct Label {
+    #[serde(flatten)]
+    span: Span,
+    message: String,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(tag = "kind", content = "message", rename_all = "lowercase")]
+enum Note {
+    Help(String),
+    Note(String),
+}
+
+impl Diagnostic {
+    pub fn error<S: fmt::Display>(message: S) -> Diagnostic {
+        Diagnostic {
+            severity: Severity::Error,
+            code: None,
+            message: message.to_string(),
+            labels: Vec::new(),
+            notes: Vec::new(),
+            causes: Vec::new(),
+        }
+    }
+
+    pub fn warning<S: fmt::Display>(message: S) -> Diagnostic {
+        Diagnostic::error(message).with_severity(Severity::Warning)
+    }
+
+    pub fn with_severity(mut self, severity: Severity) -> Diagnostic {
+        self.severity = severity;
+        self
+    }
+
+    /// Sets the error code, usually from a [Code] error.
+    pub fn with_code(mut self, code: Option<&'static str>) -> Diagnostic {
+        self.code = code.or(self.code);
+        self
+    }
+
+    /// Points at some source code, explaining what is wrong with it. Spanless labels (`None`)
+    /// are ignored, so lookups such as [Span::toml_value] can be passed straight in.
+    pub fn with_label<S: fmt::Display>(mut self, span: Option<Span>, message: S) -> Diagnostic {
+        if let Some(span) = span {
+            self.labels.push(Label {
+                span,
+                message: message.to_string(),
+            });
+        }
+        self
+    }
+
+    /// Suggests a fix.
+    pub fn with_help<S: fmt::Display>(mut self, help: S) -> Diagnostic {
+        self.notes.push(Note::Help(help.to_string()));
+        self
+    }
+
+    /// Adds some extra context.
+    pub fn with_note<S: fmt::Display>(mut self, note: S) -> Diagnostic {
+        self.notes.push(Note::Note(note.to_string()));
+        self
+    }
+
+    /// Converts a TOML deserialization error in the given file.
+    pub fn toml(file: &Path, error: &toml::de::Error) -> Diagnostic {
+        lazy_static! {
+            static ref LOCATION: Regex = Regex::new(r" at line \d+ column \d+$").unwrap();
+            static ref FOR_KEY: Regex = Regex::new(r" for key `([^`]*)`").unwrap();
+        }
+
+        let message = error.to_string();
+        let message = LOCATION.replace(&message, "");
+
+        // Point at the error position if we have it, or else the key it happened at. Missing
+        // fields are reported at the very start of the document, which isn't helpful.
+        let span = match error.line_col() {
+            Some(_) if message.starts_with("missing field") => None,
+            Some((line, col)) => Some(Span::new(file, line + 1, col + 1, 1)),
+            None => FOR_KEY.captures(&message)
+                .and_then(|g| {
+                    let key = g.get(1).unwrap().as_str();
+                    let key = key.rsplit('.').next().unwrap();
+
+                    fs::read_to_string(file).ok()
+                        .and_then(|source| Span::toml_key(file, &source, key))
+                }),
+        };
+
+        match span {
+            Some(span) => Diagnostic::error(format!("malformed {}", span.file_name()))
+                .with_label(Some(span), FOR_KEY.replace(&message, "")),
+            None => Diagnostic::error(format!("malformed {}: {}", file.display(), message)),
+        }
+    }
+
+    /// Prints this diagnostic to stderr, or as a JSON event to stdout.
+    pub fn emit(&self) {
+        if logger::is_json() {
+            return logger::event(logger::Event::Diagnostic(self));
+        }
+
+        eprint!("{}", self.render());
+
+        for cause in &self.causes {
+            trace!("{}", logger::style(Color::Fixed(8)).paint(cause.as_str()));
+        }
+    }
+
+    /// Renders this diagnostic like so:
+    ///
+    /// ```text
+    /// error[SP0203]: sprite {Sprite:fizzlit/fizzlit} has no animation 'walk'
+    ///   --> src/actor/fizzlit/fizzlit.bscr:54:35
+    ///    |
+    /// 54 | .Status:Normal    {Sprite:fizzlit:walk}
+    ///    |                                   ^^^^ unknown animation
+    ///    |
+    ///    = help: sprite {Sprite:fizzlit/fizzlit} has animations: stop, idle, sleep
+    /// ```
+    ///
+    /// Warnings are the same, but headed `warning` and marked in yellow.
+    pub fn render(&self) -> String {
+        let (severity, primary_color) = match self.severity {
+            Severity::Error => ("error", Color::Fixed(9)),
+            Severity::Warning => ("warning", Color::Fixed(3)),
+        };
+
+        let gutter_style = logger::style(Color::Fixed(12).bold());
+        let primary_style = logger::style(primary_color.bold());
+        let secondary_style = gutter_style;
+
+        let gutter_width = self.labels.iter()
+            .map(|label| label.span.line.to_string().len())
+            .max()
+            .unwrap_or(0);
+        let gutter = |s: &str| format!("{}{}",
+            " ".repeat(gutter_width + 1),
+            gutter_style.paint(s),
+        );
+
+        let mut out = String::new();
+
+        let header = match self.code {
+            Some(code) => format!("{}[{}]:", severity, code),
+            None => format!("{}:", severity),
+        };
+        let _ = writeln!(out, "{} {}", primary_style.paint(header), self.message);
+
+        let mut previous_file: Option<&Path> = None;
+        for (i, label) in self.labels.iter().enumerate() {
+            let span = &label.span;
+
+            if previous_file != Some(&span.file) {
+                let arrow = if i == 0 { "-->" } else { ":::" };
+                let _ = writeln!(out, "{}{} {}",
+                    " ".repeat(gutter_width),
+                    gutter_style.paint(arrow),
+                    span,
+                );
+                previous_file = Some(&span.file);
+            }
+
+            let _ = writeln!(out, "{}", gutter("|"));
+
+            let style = if i == 0 { primary_style } else { secondary_style };
+            let marker = if i == 0 { "^" } else { "-" };
+
+            match span.source_line() {
+                Some(source_line) => {
+                    // Keep tabs so that the marker lines up with the source line.
+                    let indent: String = source_line
+                        .chars()
+                        .take(span.column.saturating_sub(1))
+                        .map(|ch| if ch == '\t' { '\t' } else { ' ' })
+                        .collect();
+
+                    let _ = writeln!(out, "{} {}",
+                        gutter_style.paint(format!("{:>width$} |", span.line, width = gutter_width)),
+                        source_line,
+                    );
+                    let _ = writeln!(out, "{} {}{} {}",
+                        gutter("|"),
+                        indent,
+                        style.paint(marker.repeat(span.length.max(1))),
+                        style.paint(label.message.as_str()),
+                    );
+                },
+                None => {
+                    let _ = writeln!(out, "{} {}", gutter("="), label.message);
+                },
+            }
+        }
+
+        if !self.notes.is_empty() && !self.labels.is_empty() {
+            let _ = writeln!(out, "{}", gutter("|"));
+        }
+
+        for note in &self.notes {
+            let (kind, text) = match note {
+                Note::Help(text) => ("help", text),
+                Note::Note(text) => ("note", text),
+            };
+
+            let _ = writeln!(out, "{} {}: {}",
+                gutter("="),
+                logger::style(Color::White.bold()).paint(kind),
+                text,
+            );
+        }
+
+        out.push('\n');
+        out
+    }
+}
+
+/// The JSON representation; see [logger::Event]. The location of the primary label is repeated at
+/// the top level for convenience.
+impl Serialize for Diagnostic {
+    fn serialize<S: ser::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use ser::SerializeStruct;
+
+        let primary = self.labels.first().map(|label| &label.span);
+
+        let mut s = serializer.serialize_struct("Diagnostic", 8)?;
+        s.serialize_field("severity", &self.severity)?;
+        s.serialize_field("code", &self.code)?;
+        s.serialize_field("message", &self.message)?;
+        s.serialize_field("file", &primary.map(|span| &span.file))?;
+        s.serialize_field("line", &primary.map(|span| span.line))?;
+        s.serialize_field("column", &primary.map(|span| span.column))?;
+        s.serialize_field("labels", &self.labels)?;
+        s.serialize_field("notes", &self.notes)?;
+        s.end()
+    }
+}
+
+impl From<Error> for Diagnostic {
+    fn from(error: Error) -> Diagnostic {
+        match error.downcast::<Diagnostic>() {
+            Ok(diagnostic) => diagnostic,
+            Err(error) => {
+                let mut chain = error.chain();
+                let mut diagnostic = Diagnostic::error(chain.next().unwrap());
+                diagnostic.code = error.chain().find_map(code_of);
+                diagnostic.causes = chain.map(ToString::to_string).collect();
+                diagnostic
+            },
+        }
+    }
+}
+
+/// A location in a source file. Lines and columns count from 1.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Span {
+    pub file: PathBuf,
+    pub line: usize,
+    pub column: usize,
+    pub length: usize,
+}
+
+impl Span {
+    pub fn new(file: &Path, line: usize, column: usize, length: usize) -> Span {
+        Span {
+            file: file.to_owned(),
+            line,
+            column,
+            length,
+        }
+    }
+
+    /// Finds the first occurrence of `needle` on the given line of `source`.
+    pub fn find(file: &Path, source: &str, line: usize, needle: &str) -> Option<Span> {
+        let source_line = source.lines().nth(line.checked_sub(1)?)?;
+        let byte_idx = source_line.find(needle)?;
+
+        Some(Span::new(
+            file,
+            line,
+            source_line[..byte_idx].chars().count() + 1,
+            needle.chars().count(),
+        ))
+    }
+
+    /// Finds `part`, at a byte offset into `text`, which is somewhere on the given line of `source`.
+    /// Falls back to the first occurrence of `part` on the line if `text` isn't there.
+    pub fn find_in(file: &Path, source: &str, line: usize, text: &str, offset: usize, part: &str) -> Option<Span> {
+        let source_line = source.lines().nth(line.checked_sub(1)?)?;
+
+        match source_line.find(text) {
+            Some(text_start) => Some(Span::new(
+                file,
+                line,
+                source_line[..text_start + offset].chars().count() + 1,
+                part.chars().count().max(1),
+            )),
+            None => Span::find(file, source, line, part),
+        }
+    }
+
+    /// Finds the first occurrence of `needle` anywhere in `source`.
+    pub fn find_first(file: &Path, source: &str, needle: &str) -> Option<Span> {
+        let line_idx = source.lines().position(|line| line.contains(needle))?;
+        Span::find(file, source, line_idx + 1, needle)
+    }
+
+    /// Finds `key = ...` in a TOML document, returning the span of the key.
+    pub fn toml_key(file: &Path, source: &str, key: &str) -> Option<Span> {
+        let (line, _) = toml_entry(source, key)?;
+        Span::find(file, source, line, key)
+    }
+
+    /// Finds `key = value` in a TOML document, returning the span of the value.
+    pub fn toml_value(file: &Path, source: &str, key: &str) -> Option<Span> {
+        let (line, value) = toml_entry(source, key)?;
+        Span::find(file, source, line, &value)
+    }
+
+    pub fn file_name(&self) -> String {
+        self.file
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    }
+
+    /// Reads the line this span is on from disk, if it is still there.
+    fn source_line(&self) -> Option<String> {
+        let source = fs::read_to_string(&self.file).ok()?;
+        let line = source.lines().nth(self.line.checked_sub(1)?)?;
+        Some(line.trim_end().to_owned())
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.file.display(), self.line, self.column)
+    }
+}
+
+/// Finds the line number and (trimmed) value text of `key = value` in a TOML document.
+fn toml_entry(source: &str, key: &str) -> Option<(usize, String)> {
+    let entry = Regex::new(&format!(
+        r#"^\s*"?{}"?\s*=\s*(.*?)\s*(#.*)?$"#,
+        regex::escape(key),
+    )).unwrap();
+
+    source.lines()
+        .enumerate()
+        .find_map(|(idx, line)| entry.captures(line)
+            .map(|g| (idx + 1, g.get(1).unwrap().as_str().to_owned())))
+}
@@ -1,5 +1,82 @@
 use crate::prelude::*;
 use log::{Record, Level, LevelFilter, Metadata, SetLoggerError};
+use crate::diagnostics::Diagnostic;
+use ansi_term::Style;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::str::FromStr;
+
+/// Whether to print in colour. Like clap's ColorAuto, we only do so when stderr is a terminal.
+static COLOR: AtomicBool = AtomicBool::new(false);
+
+/// Whether to print JSON events to stdout rather than human-readable text to stderr.
+static JSON: AtomicBool = AtomicBool::new(false);
+
+/// How to print diagnostics and build events.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageFormat {
+    /// Coloured (if possible) text on stderr.
+    Human,
+
+    /// One JSON object per line on stdout, for editors and CI to consume.
+    Json,
+}
+
+impl FromStr for MessageFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "human" => Ok(MessageFormat::Human),
+            "json" => Ok(MessageFormat::Json),
+            _ => Err(format!("unknown message format '{}' (expected human or json)", s)),
+        }
+    }
+}
+
+/// A machine-readable message. Only printed in JSON mode.
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum Event<'a> {
+    /// A log message that isn't a diagnostic, e.g. `info: loaded {Sprite:x/y}`.
+    Log {
+        level: String,
+        message: String,
+    },
+
+    Diagnostic(&'a Diagnostic),
+
+    /// A package was assembled into a mod directory.
+    Assembled {
+        package: &'a str,
+        build_dir: &'a Path,
+        seconds: f32,
+    },
+
+    /// The build is over, successfully or not.
+    BuildFinished {
+        success: bool,
+    },
+}
+
+/// Prints the given event, if we are in JSON mode.
+pub fn event(event: Event) {
+    if is_json() {
+        println!("{}", serde_json::to_string(&event).unwrap());
+    }
+}
+
+pub fn is_json() -> bool {
+    JSON.load(Ordering::Relaxed)
+}
+
+/// Returns `style` (or colour), or a plain style if colour output is disabled.
+pub fn style<S: Into<Style>>(style: S) -> Style {
+    if COLOR.load(Ordering::Relaxed) {
+        style.into()
+    } else {
+        Style::new()
+    }
+}
 
 struct Logger;
 
@@ -10,11 +87,18 @@ impl log::Log for Logger {
 
     fn log(&self, record: &Record) {
         if self.enabled(record.metadata()) {
+            if is_json() {
+                return event(Event::Log {
+                    level: record.level().to_string().to_lowercase(),
+                    message: record.args().to_string(),
+                });
+            }
+
             // Print file:line information in debug mode
             #[cfg(debug_assertions)] {
                 if let Some(file) = record.file() {
                     if let Some(line) = record.line() {
-                        eprint!("{} ", Color::Fixed(8).normal().paint(
+                        eprint!("{} ", style(Color::Fixed(8)).paint(
                             format!("{:>16}:{:<3}", &file[4..], line)
                         ));
                     }
@@ -22,11 +106,11 @@ impl log::Log for Logger {
             }
 
             eprintln!("{} {}", match record.level() {
-                Level::Error => Color::Fixed(9).normal().paint("error:"),
-                Level::Warn  => Color::Fixed(3).normal().paint("warn: "),
-                Level::Info  => Color::Fixed(10).normal().paint("info: "),
-                Level::Debug => Color::Fixed(14).normal().paint("debug:"),
-                Level::Trace => Color::Fixed(8).normal().paint("trace:"),
+                Level::Error => style(Color::Fixed(9)).paint("error:"),
+                Level::Warn  => style(Color::Fixed(3)).paint("warn: "),
+                Level::Info  => style(Color::Fixed(10)).paint("info: "),
+                Level::Debug => style(Color::Fixed(14)).paint("debug:"),
+                Level::Trace => style(Color::Fixed(8)).paint("trace:"),
             }, record.args());
         }
     }
@@ -36,10 +120,14 @@ impl log::Log for Logger {
 
 static LOGGER: Logger = Logger;
 
-pub fn init(verbosity: usize) -> Result<(), SetLoggerError> {
+pub fn init(verbosity: usize, format: MessageFormat) -> Result<(), SetLoggerError> {
     #[cfg(windows)]
     let _ = ansi_term::enable_ansi_support();
 
+    let json = format == MessageFormat::Json;
+    JSON.store(json, Ordering::Relaxed);
+    COLOR.store(!json && atty::is(atty::Stream::Stderr), Ordering::Relaxed);
+
     log::set_logger(&LOGGER)
         .map(|()| log::set_max_level(match verbosity {
             0 => LevelFilter::Info,
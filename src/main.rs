@@ -6,7 +6,7 @@ mod prelude {
     pub use lazy_static::lazy_static;
 
     // Logging
-    pub use log::{error, warn, info, debug, trace};
+    pub use log::{warn, info, debug, trace};
     pub use ansi_term::{ANSIString, Color};
 
     // Errors
@@ -22,6 +22,7 @@ mod prelude {
 }
 
 mod logger;
+mod diagnostics;
 mod cmd;
 mod package;
 mod sanitize;
@@ -54,6 +55,15 @@ struct Opt {
     /// Verbosity level (-v: debug, -vv: trace).
     #[structopt(short = "v", parse(from_occurrences))]
     verbosity: usize,
+
+    /// Output format for diagnostics and build events.
+    #[structopt(
+        long,
+        global = true,
+        default_value = "human",
+        possible_values = &["human", "json"],
+    )]
+    message_format: logger::MessageFormat,
 }
 
 #[derive(StructOpt, Debug)]
@@ -64,18 +74,20 @@ enum Command {
     /// Assembles dependencies
     // TODO: also compile mod
     Build(cmd::build::Opt),
-}
 
-fn main() {
-    if let Err(err) = try_main() {
-        let mut chain = err.chain();
+    /// Explains an error code in detail
+    Explain(cmd::explain::Opt),
 
-        error!("{}", chain.next().unwrap());
+    /// Renders previews of exports
+    Preview(cmd::preview::Opt),
 
-        for cause in chain {
-            trace!("{}", Color::Fixed(8).normal().paint(format!("{}", cause)));
-        }
+    /// Exports and imports strings for translation
+    Strings(cmd::strings::Opt),
+}
 
+fn main() {
+    if let Err(err) = try_main() {
+        diagnostics::Diagnostic::from(err).emit();
         std::process::exit(1);
     }
 }
@@ -83,7 +95,7 @@ fn main() {
 fn try_main() -> Result<()> {
     let opt = Opt::from_args();
 
-    logger::init(opt.verbosity)?;
+    logger::init(opt.verbosity, opt.message_format)?;
     if opt.verbosity > 2 {
         warn!("superfluous verbosity (-vv is max)");
     }
@@ -93,5 +105,8 @@ fn try_main() -> Result<()> {
     match opt.cmd {
         Command::New(cmd_opt) => cmd::new::run(ctx, cmd_opt),
         Command::Build(cmd_opt) => cmd::build::run(ctx, cmd_opt),
+        Command::Explain(cmd_opt) => cmd::explain::run(ctx, cmd_opt),
+        Command::Preview(cmd_opt) => cmd::preview::run(ctx, cmd_opt),
+        Command::Strings(cmd_opt) => cmd::strings::run(ctx, cmd_opt),
     }
 }
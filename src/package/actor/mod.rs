@@ -0,0 +1,453 @@
+use crate::prelude::*;
+use crate::logger;
+use crate::sanitize;
+use crate::diagnostics::{Diagnostic, Span, Code};
+use super::Package;
+use super::id::{Identify, Identifier};
+use super::script::{Script, Block};
+use super::text::TextId;
+
+mod tables;
+mod validate;
+use tables::*;
+
+pub type ActorMap = std::collections::HashMap<ActorId, Actor>;
+
+#[derive(Hash, Clone, PartialEq, Eq)]
+pub struct ActorId(Identifier);
+
+impl Identify for ActorId {
+    type T = Actor;
+
+    fn new(pkg_name: &str, actor_name: &str) -> Self {
+        Self(Identifier::new(pkg_name, actor_name))
+    }
+
+    fn identify(pkg: &Package, actor: &Actor) -> Self {
+        Self(Identifier::from_package(pkg, &actor.name()))
+    }
+}
+
+impl fmt::Debug for ActorId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", logger::style(Color::Fixed(12)).paint(format!("{{Actor:{:?}}}", self.0)))
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Actor {
+    dir: PathBuf,
+    src_pkg_name: String,
+
+    pub name: TextId,
+    pub tattle: TextId,
+
+    /// If given, the `$Actor` struct is generated from these rather than written in the script.
+    stats: Option<Stats>,
+
+    /// If given, `$StatusTable` and `$DefenseTable` are generated from these.
+    status: Option<StatusTable>,
+    defense: Option<DefenseTable>,
+
+    offsets: Offsets,
+
+    /// If given, `$SpriteTable` and the structs it points to are generated from these.
+    parts: Vec<Part>,
+
+    assembled_index: Option<u8>,
+}
+
+impl Actor {
+    pub fn load(src_pkg_name: &str, dir: PathBuf) -> Result<Actor> {
+        #[derive(Deserialize)]
+        struct Manifest {
+            name: String,
+            tattle: String,
+
+            stats: Option<StatsTable>,
+            effectiveness: Option<EffectivenessTable>,
+            ui: Option<UiTable>,
+            status: Option<StatusTable>,
+            defense: Option<DefenseTable>,
+
+            #[serde(default)]
+            parts: Vec<Part>,
+
+            #[serde(flatten)]
+            offsets: Offsets,
+        }
+
+        let name = dir.file_name().unwrap().to_str().unwrap();
+        let toml_path = dir.join(format!("{}.toml", name));
+        let toml = fs::read_to_string(&toml_path)
+            .with_context(|| format!("toml file for actor '{}' not found", name))?;
+        let manifest: Manifest = toml::from_str(&toml)
+            .map_err(|error| Diagnostic::toml(&toml_path, &error))?;
+
+        let stats_required = |table: &'static str| Diagnostic::from(ActorLoadError::StatsRequired {
+            span: Span::find(&toml_path, &toml, toml_table_line(&toml, table), table),
+            table,
+        });
+
+        let parse_text_id = |key: &str, id: &str| TextId::parse(id, src_pkg_name)
+            .map_err(|error| Diagnostic::error(format!("invalid actor {}: {}", key, error))
+                .with_label(Span::toml_value(&toml_path, &toml, key), "bad string identifier"));
+
+        let stats = match (manifest.stats, manifest.effectiveness, manifest.ui) {
+            (Some(stats), effectiveness, ui) => Some(Stats {
+                stats,
+                effectiveness: effectiveness.unwrap_or_default(),
+                ui: ui.unwrap_or_default(),
+            }),
+            (None, Some(_), _) => return Err(stats_required("effectiveness").into()),
+            (None, _, Some(_)) => return Err(stats_required("ui").into()),
+            (None, None, None) => None,
+        };
+
+        if let Some(stats) = &stats {
+            stats.check(&toml_path, &toml).map_err(Diagnostic::from)?;
+        }
+        manifest.offsets.check(&toml_path, &toml).map_err(Diagnostic::from)?;
+        Part::check_all(&manifest.parts, &toml_path, &toml).map_err(Diagnostic::from)?;
+        if let Some(status) = &manifest.status {
+            status.check(&toml_path, &toml).map_err(Diagnostic::from)?;
+        }
+        if let Some(defense) = &manifest.defense {
+            defense.check(&toml_path, &toml).map_err(Diagnostic::from)?;
+        }
+
+        let actor = Actor {
+            name: parse_text_id("name", &manifest.name)?,
+            tattle: parse_text_id("tattle", &manifest.tattle)?,
+            stats,
+            status: manifest.status,
+            defense: manifest.defense,
+            offsets: manifest.offsets,
+            parts: manifest.parts,
+
+            dir,
+            src_pkg_name: src_pkg_name.to_owned(),
+            assembled_index: None,
+        };
+
+        sanitize::export_name(&actor.name())?;
+
+        // Fails if the script doesn't parse, or defines a struct the TOML generates.
+        actor.script()?;
+
+        Ok(actor)
+    }
+
+    pub fn name(&self) -> String {
+        self.dir
+            .file_name()
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .to_string()
+    }
+
+    /// Attributes of the actor's type besides its index and strings, for `ActorTypes.xml`.
+    pub fn type_attributes(&self) -> String {
+        self.offsets.xml_attributes()
+    }
+
+    /// The index of a part declared in the actor's TOML, from 1.
+    pub fn part_index(&self, part: &str) -> Option<usize> {
+        self.parts.iter().position(|p| p.name == part).map(|idx| idx + 1)
+    }
+
+    pub fn part_names(&self) -> Vec<String> {
+        self.parts.iter().map(|part| part.name.clone()).collect()
+    }
+
+    pub fn assembled_index(&self) -> Option<u8> {
+        self.assembled_index
+    }
+
+    pub fn assemble(&mut self, actors_dir: &Path, index: u8) -> Result<Script> {
+        let mut script = self.script()
+            .with_context(|| format!("error parsing script for actor: {}", self.name()))?;
+
+        script.path = actors_dir.join(format!("{:02X}_{}.bpat", index, self.name()));
+
+        self.assembled_index = Some(index);
+
+        Ok(script)
+    }
+
+    /// The fields of the actor's `$Actor` struct, e.g. `("MaxHP", "3")`, in order. Numbers are
+    /// given in decimal, whether they were written in decimal or hex.
+    pub fn fields(&self) -> Result<Vec<(String, String)>> {
+        let script = self.script()?;
+        let block = script.blocks.iter()
+            .find(|block| block.lines[0].1.starts_with("#new:Actor"))
+            .ok_or_else(|| anyhow!("actor '{}' has no $Actor struct", self.name()))?;
+
+        Ok(block.lines[1..].iter()
+            .filter_map(|(_, line)| {
+                let line = line.strip_prefix('[')?;
+                let (field, value) = line.split_at(line.find(']')?);
+                let value = value[1..].split_whitespace()
+                    .map(|word| match word.find('`') {
+                        Some(idx) => word[..idx].to_owned(),
+                        None => i64::from_str_radix(word, 16)
+                            .map(|n| n.to_string())
+                            .unwrap_or_else(|_| word.to_owned()),
+                    })
+                    .collect::<Vec<_>>()
+                    .join(" ");
+
+                Some((field.to_owned(), value))
+            })
+            .collect())
+    }
+
+    /// Loads the actor's script, with the structs its TOML describes generated.
+    fn script(&self) -> Result<Script> {
+        let mut script = Script::load(&self.src_pkg_name, self.dir.join(format!("{}.bscr", self.name())))?;
+
+        let mut generated = Vec::new();
+        if let Some(defense) = &self.defense {
+            generated.push(("defense", "$DefenseTable".to_owned(), defense.table_struct("$DefenseTable")));
+        }
+        if let Some(status) = &self.status {
+            generated.push(("status", "$StatusTable".to_owned(), status.table_struct()));
+        }
+        for part in self.parts.iter().rev() {
+            for (pointer, lines) in part.structs().into_iter().rev() {
+                generated.push(("parts", pointer, lines));
+            }
+        }
+        if !self.parts.is_empty() {
+            generated.push(("parts", "$SpriteTable".to_owned(), Part::sprite_table(&self.parts)));
+        }
+
+        // `$Actor` comes first, and needs to know how many parts `$SpriteTable` has.
+        for (table, pointer, lines) in generated {
+            self.insert_generated(&mut script, table, &pointer, lines)?;
+        }
+
+        if let Some(stats) = &self.stats {
+            let sprite_count = script.blocks.iter()
+                .find(|block| defines(block, "$SpriteTable"))
+                .map(|block| block.lines.len() - 1)
+                .unwrap_or(0);
+
+            self.insert_generated(&mut script, "stats", "$Actor", stats.actor_struct(&self.name(), sprite_count))?;
+        }
+
+        Ok(script)
+    }
+
+    /// Inserts a generated struct at the start of the script, which mustn't define it too.
+    fn insert_generated(&self, script: &mut Script, table: &'static str, pointer: &str, lines: Vec<String>) -> Result<()> {
+        if let Some(block) = script.blocks.iter().find(|block| defines(block, pointer)) {
+            let (line_no, header) = &block.lines[0];
+
+            return Err(Diagnostic::from(ActorLoadError::StructClash {
+                span: script.span(*line_no, header),
+                actor: self.name(),
+                table,
+                pointer: pointer.to_owned(),
+            }).into());
+        }
+
+        // Generated lines aren't from the source, so have no line number.
+        let lines = lines.into_iter().map(|line| (0, line)).collect();
+        script.blocks.insert(0, Block::new(lines)?);
+
+        Ok(())
+    }
+
+    /// Checks that the status and defense tables the script uses are defined, by it or the TOML.
+    fn check_tables(&self, script: &Script) -> Result<(), ActorLoadError> {
+        for (table, pointer) in [("status", "$StatusTable"), ("defense", "$DefenseTable")] {
+            if script.blocks.iter().any(|block| defines(block, pointer)) {
+                continue;
+            }
+
+            let usage = script.blocks.iter()
+                .flat_map(|block| &block.lines)
+                .find(|(_, line)| line.split_whitespace().any(|word| word == pointer));
+
+            if let Some((line_no, _)) = usage {
+                return Err(ActorLoadError::UndefinedTable {
+                    span: script.span(*line_no, pointer),
+                    actor: self.name(),
+                    table,
+                    pointer,
+                });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Whether a block is the struct `pointer`, e.g. `#new:StatusTable $StatusTable`.
+fn defines(block: &Block, pointer: &str) -> bool {
+    let mut header = block.lines[0].1.split_whitespace();
+    header.next().is_some_and(|kind| kind.starts_with("#new:")) && header.next() == Some(pointer)
+}
+
+/// The line a `[table]` header is on, or 0 if there isn't one.
+fn toml_table_line(toml: &str, table: &str) -> usize {
+    toml.lines()
+        .position(|line| line.trim() == format!("[{}]", table))
+        .map(|idx| idx + 1)
+        .unwrap_or(0)
+}
+
+#[derive(Error, Debug)]
+pub enum ActorLoadError {
+    #[error("actor {key} is {value}, but must be from {min} to {max}")]
+    OutOfRange {
+        span: Option<Span>,
+        key: String,
+        value: i64,
+        min: i64,
+        max: i64,
+    },
+
+    #[error("actor '{actor}' has [{table}], but its script also defines {pointer}")]
+    StructClash {
+        span: Option<Span>,
+        actor: String,
+        table: &'static str,
+        pointer: String,
+    },
+
+    #[error("actor has [{table}], but no [stats]")]
+    StatsRequired {
+        span: Option<Span>,
+        table: &'static str,
+    },
+
+    #[error("actor '{actor}' uses {pointer}, but doesn't define it")]
+    UndefinedTable {
+        span: Option<Span>,
+        actor: String,
+        table: &'static str,
+        pointer: &'static str,
+    },
+
+    #[error("unknown {table} '{key}'")]
+    UnknownKey {
+        span: Option<Span>,
+        table: &'static str,
+        key: String,
+        expected: Vec<String>,
+    },
+
+    #[error("actor '{actor}' has no $Actor struct")]
+    MissingStruct {
+        actor: String,
+    },
+
+    #[error("actor '{actor}' is missing the [{field}] field")]
+    MissingField {
+        span: Option<Span>,
+        actor: String,
+        field: &'static str,
+    },
+
+    #[error("[Index] of actor '{actor}' is `{index}`, which isn't the actor itself")]
+    WrongIndex {
+        span: Option<Span>,
+        actor: String,
+        index: String,
+    },
+
+    #[error("bad actor field [{field}]: {reason}")]
+    BadField {
+        span: Option<Span>,
+        field: String,
+        reason: String,
+    },
+
+    #[error("actor '{actor}' uses {pointer}, but its script doesn't define it")]
+    UndefinedPointer {
+        span: Option<Span>,
+        actor: String,
+        pointer: String,
+    },
+
+    #[error("actor '{actor}' defines {pointer} more than once")]
+    DuplicatePointer {
+        span: Option<Span>,
+        actor: String,
+        pointer: String,
+    },
+
+    #[error("bad actor part '{part}': {reason}")]
+    BadPart {
+        span: Option<Span>,
+        part: String,
+        reason: String,
+    },
+}
+
+impl Code for ActorLoadError {
+    fn code(&self) -> Option<&'static str> {
+        match self {
+            ActorLoadError::OutOfRange { .. } => Some("SP0501"),
+            ActorLoadError::StructClash { .. } => Some("SP0502"),
+            ActorLoadError::StatsRequired { .. } => Some("SP0503"),
+            ActorLoadError::UndefinedTable { .. } => Some("SP0504"),
+            ActorLoadError::UnknownKey { .. } => Some("SP0505"),
+            ActorLoadError::BadPart { .. } => Some("SP0506"),
+            ActorLoadError::MissingStruct { .. } => Some("SP0507"),
+            ActorLoadError::MissingField { .. } => Some("SP0508"),
+            ActorLoadError::WrongIndex { .. } => Some("SP0509"),
+            ActorLoadError::BadField { .. } => Some("SP0510"),
+            ActorLoadError::UndefinedPointer { .. } => Some("SP0511"),
+            ActorLoadError::DuplicatePointer { .. } => Some("SP0512"),
+        }
+    }
+}
+
+impl From<ActorLoadError> for Diagnostic {
+    fn from(error: ActorLoadError) -> Diagnostic {
+        let diagnostic = Diagnostic::error(&error).with_code(error.code());
+
+        match error {
+            ActorLoadError::OutOfRange { span, .. } => diagnostic
+                .with_label(span, "out of range"),
+            ActorLoadError::StructClash { span, table, .. } => diagnostic
+                .with_label(span, "defined here")
+                .with_help(format!("remove this block; starpkg generates it from [{}]", table)),
+            ActorLoadError::StatsRequired { span, .. } => diagnostic
+                .with_label(span, "needs [stats]")
+                .with_help("add a [stats] table with at least `max_hp`"),
+            ActorLoadError::UndefinedTable { span, actor, table, pointer } => diagnostic
+                .with_label(span, "used here")
+                .with_help(format!(
+                    "add a [{}] table to {}.toml, or a `#new:{} {}` block to its script",
+                    table, actor, &pointer[1..], pointer,
+                )),
+            ActorLoadError::UnknownKey { span, table, expected, .. } => diagnostic
+                .with_label(span, format!("unknown {}", table))
+                .with_help(format!("expected one of: {}", expected.join(", "))),
+            ActorLoadError::BadPart { span, .. } => diagnostic
+                .with_label(span, "this part"),
+            ActorLoadError::MissingStruct { actor } => diagnostic
+                .with_help(format!(
+                    "add [stats] to {}.toml, or a `#new:Actor $Actor` block to its script", actor,
+                )),
+            ActorLoadError::MissingField { span, .. } => diagnostic
+                .with_label(span, "in this struct"),
+            ActorLoadError::WrongIndex { span, actor, .. } => diagnostic
+                .with_label(span, "not this actor")
+                .with_help(format!("use `{{Actor:{}}}`", actor)),
+            ActorLoadError::BadField { span, .. } => diagnostic
+                .with_label(span, "this field"),
+            ActorLoadError::UndefinedPointer { span, .. } => diagnostic
+                .with_label(span, "used here")
+                .with_help("define it with a block such as `#new:Script $Name`"),
+            ActorLoadError::DuplicatePointer { span, .. } => diagnostic
+                .with_label(span, "defined again here")
+                .with_help("rename one of the blocks, or remove it"),
+        }
+    }
+}
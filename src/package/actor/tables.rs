@@ -0,0 +1,546 @@
+//! Tables in an actor's TOML that starpkg generates battle structs and `ActorTypes.xml` from, so
+//! their scripts don't have to.
+
+use crate::prelude::*;
+use crate::diagnostics::Span;
+use std::collections::BTreeMap;
+use super::ActorLoadError;
+
+pub(super) const BYTE: (i64, i64) = (0, 255);
+pub(super) const SIGNED_BYTE: (i64, i64) = (-128, 127);
+pub(super) const PERCENT: (i64, i64) = (0, 100);
+
+/// Fails if `value` isn't from `min` to `max`, pointing at `key` in the TOML.
+fn check_range(
+    toml_path: &Path,
+    toml: &str,
+    key: &str,
+    value: i64,
+    (min, max): (i64, i64),
+) -> Result<(), ActorLoadError> {
+    if (min..=max).contains(&value) {
+        Ok(())
+    } else {
+        Err(ActorLoadError::OutOfRange {
+            span: Span::toml_value(toml_path, toml, key),
+            key: key.to_owned(),
+            value,
+            min,
+            max,
+        })
+    }
+}
+
+/// Fails if `key` isn't one of `expected`, pointing at it in the TOML.
+fn check_key(
+    toml_path: &Path,
+    toml: &str,
+    table: &'static str,
+    key: &str,
+    expected: &[String],
+) -> Result<(), ActorLoadError> {
+    if expected.iter().any(|name| name == key) {
+        Ok(())
+    } else {
+        Err(ActorLoadError::UnknownKey {
+            span: Span::toml_key(toml_path, toml, key),
+            table,
+            key: key.to_owned(),
+            expected: expected.to_vec(),
+        })
+    }
+}
+
+/// The `[stats]`, `[effectiveness]` and `[ui]` tables of an actor's TOML.
+#[derive(Clone, Debug)]
+pub struct Stats {
+    pub stats: StatsTable,
+    pub effectiveness: EffectivenessTable,
+    pub ui: UiTable,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct StatsTable {
+    #[serde(default)]
+    level: i64,
+    max_hp: i64,
+    #[serde(default)]
+    coins: i64,
+    #[serde(default)]
+    flags: u32,
+}
+
+/// Chances, as percentages, of moves working against the actor.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct EffectivenessTable {
+    escape: i64,
+    item: i64,
+    air_lift: i64,
+    hurricane: i64,
+    up_and_away: i64,
+    power_bounce: i64,
+
+    /// The weight Spin Smash has to beat to knock the actor back, rather than a percentage.
+    spin_smash: i64,
+}
+
+impl Default for EffectivenessTable {
+    fn default() -> Self {
+        EffectivenessTable {
+            escape: 100,
+            item: 100,
+            air_lift: 100,
+            hurricane: 100,
+            up_and_away: 100,
+            power_bounce: 100,
+            spin_smash: 0,
+        }
+    }
+}
+
+/// Positions of UI around the actor, in pixels.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct UiTable {
+    size: [i64; 2],
+    health_bar: [i64; 2],
+    status_turn: [i64; 2],
+    status_icon: [i64; 2],
+}
+
+impl Default for UiTable {
+    fn default() -> Self {
+        UiTable {
+            size: [24, 24],
+            health_bar: [0, 0],
+            status_turn: [-10, 20],
+            status_icon: [10, 20],
+        }
+    }
+}
+
+impl Stats {
+    /// Checks every value fits the field of the `$Actor` struct it goes in.
+    pub fn check(&self, toml_path: &Path, toml: &str) -> Result<(), ActorLoadError> {
+        let check = |key: &str, value: i64, range: (i64, i64)| check_range(toml_path, toml, key, value, range);
+
+        let StatsTable { level, max_hp, coins, .. } = self.stats;
+        check("level", level, BYTE)?;
+        check("max_hp", max_hp, (1, 255))?;
+        check("coins", coins, BYTE)?;
+
+        let e = &self.effectiveness;
+        check("escape", e.escape, PERCENT)?;
+        check("item", e.item, PERCENT)?;
+        check("air_lift", e.air_lift, PERCENT)?;
+        check("hurricane", e.hurricane, PERCENT)?;
+        check("up_and_away", e.up_and_away, PERCENT)?;
+        check("power_bounce", e.power_bounce, PERCENT)?;
+        check("spin_smash", e.spin_smash, BYTE)?;
+
+        let ui = &self.ui;
+        for &value in &ui.size {
+            check("size", value, BYTE)?;
+        }
+        for (key, position) in [
+            ("health_bar", ui.health_bar),
+            ("status_turn", ui.status_turn),
+            ("status_icon", ui.status_icon),
+        ] {
+            for value in position {
+                check(key, value, SIGNED_BYTE)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes the `$Actor` struct. Its AI fields point at `$StatusTable`, `$SpriteTable` and
+    /// `$Script_Init`, which the actor's script or TOML defines.
+    pub fn actor_struct(&self, actor_name: &str, sprite_count: usize) -> Vec<String> {
+        let byte = |value: i64| format!("{}`b", value);
+        let pair = |[x, y]: [i64; 2]| format!("{}`b {}`b", x, y);
+        let (stats, e, ui) = (&self.stats, &self.effectiveness, &self.ui);
+
+        vec![
+            "#new:Actor $Actor".to_owned(),
+            format!("[Index] {{Actor:{}}}", actor_name),
+            format!("[Level] {}", byte(stats.level)),
+            format!("[MaxHP] {}", byte(stats.max_hp)),
+            format!("[Coins] {}", byte(stats.coins)),
+            format!("[Flags] {:08X}", stats.flags),
+            "[StatusTable] $StatusTable".to_owned(),
+            format!("[SpriteCount] {}`s", sprite_count),
+            "[SpriteTable] $SpriteTable".to_owned(),
+            "[Script] $Script_Init".to_owned(),
+            format!("[Escape] {}", byte(e.escape)),
+            format!("[Item] {}", byte(e.item)),
+            format!("[AirLift] {}", byte(e.air_lift)),
+            format!("[Hurricane] {}", byte(e.hurricane)),
+            format!("[UpAndAway] {}", byte(e.up_and_away)),
+            format!("[PowerBounce] {}", byte(e.power_bounce)),
+            format!("[SpinSmash] {}", byte(e.spin_smash)),
+            format!("[Size] {}", pair(ui.size)),
+            format!("[HealthBar] {}", pair(ui.health_bar)),
+            format!("[StatusTurn] {}", pair(ui.status_turn)),
+            format!("[StatusIcon] {}", pair(ui.status_icon)),
+        ]
+    }
+}
+
+/// Offsets for the actor's type, given at the top level of its TOML and written to
+/// `ActorTypes.xml`. Star Rod's defaults are used for those not given. Besides the name and tattle
+/// strings, these are the only attributes `ActorTypes.xml` has.
+#[derive(Deserialize, Clone, Debug, Default)]
+pub struct Offsets {
+    /// Where the camera points when tattling the actor, relative to its position.
+    tattle_cam_offset: Option<[i64; 3]>,
+
+    /// How far the actor's shadow is moved.
+    shadow_offset: Option<i64>,
+}
+
+impl Offsets {
+    pub fn check(&self, toml_path: &Path, toml: &str) -> Result<(), ActorLoadError> {
+        for &value in self.tattle_cam_offset.iter().flatten() {
+            check_range(toml_path, toml, "tattle_cam_offset", value, SIGNED_BYTE)?;
+        }
+        if let Some(value) = self.shadow_offset {
+            check_range(toml_path, toml, "shadow_offset", value, SIGNED_BYTE)?;
+        }
+
+        Ok(())
+    }
+
+    /// Attributes for the actor's `<Actor>` element, each with a leading space.
+    pub fn xml_attributes(&self) -> String {
+        let mut attributes = String::new();
+
+        if let Some([x, y, z]) = self.tattle_cam_offset {
+            attributes.push_str(&format!(r#" tattleCamOffset="{},{},{}""#, x, y, z));
+        }
+        if let Some(shadow) = self.shadow_offset {
+            attributes.push_str(&format!(r#" shadowOffset="{}""#, shadow));
+        }
+
+        attributes
+    }
+}
+
+/// Statuses in the order of a `StatusTable`, as TOML keys and Star Rod names. Those after
+/// `default` also have turn modifiers.
+const STATUSES: [(&str, &str); 11] = [
+    ("normal", "Normal"),
+    ("default", "Default"),
+    ("sleep", "Sleep"),
+    ("poison", "Poison"),
+    ("frozen", "Frozen"),
+    ("dizzy", "Dizzy"),
+    ("fear", "Fear"),
+    ("static", "Static"),
+    ("paralyze", "Paralyze"),
+    ("shrink", "Shrink"),
+    ("stop", "Stop"),
+];
+
+/// The `[status]` table of an actor's TOML: the chance, as a percentage, of each status being
+/// inflicted, and `<status>_turns` to lengthen or shorten it.
+#[derive(Deserialize, Clone, Debug)]
+pub struct StatusTable {
+    /// Applied in order, before the values given alongside them.
+    #[serde(default)]
+    presets: Vec<String>,
+
+    #[serde(flatten)]
+    values: BTreeMap<String, i64>,
+}
+
+impl StatusTable {
+    /// The presets, by name, and the statuses each makes the actor immune to.
+    fn presets() -> Vec<(String, Vec<&'static str>)> {
+        let inflicted = || STATUSES[2..].iter().map(|&(key, _)| key);
+
+        std::iter::once(("immune_to_all".to_owned(), inflicted().collect()))
+            .chain(inflicted().map(|key| (format!("immune_to_{}", key), vec![key])))
+            .collect()
+    }
+
+    fn keys() -> Vec<String> {
+        STATUSES.iter()
+            .map(|&(key, _)| key.to_owned())
+            .chain(STATUSES[1..].iter().map(|&(key, _)| format!("{}_turns", key)))
+            .collect()
+    }
+
+    /// Chances and turn modifiers by key, with presets applied.
+    fn resolve(&self) -> BTreeMap<String, i64> {
+        let mut values: BTreeMap<String, i64> = STATUSES.iter()
+            .map(|&(key, _)| (key.to_owned(), if key == "normal" || key == "default" { 0 } else { 100 }))
+            .collect();
+
+        let presets = StatusTable::presets();
+        for preset in &self.presets {
+            if let Some((_, immune)) = presets.iter().find(|(name, _)| name == preset) {
+                for &key in immune {
+                    values.insert(key.to_owned(), 0);
+                }
+            }
+        }
+
+        values.extend(self.values.iter().map(|(key, &value)| (key.clone(), value)));
+        values
+    }
+
+    pub fn check(&self, toml_path: &Path, toml: &str) -> Result<(), ActorLoadError> {
+        let presets: Vec<String> = StatusTable::presets().into_iter().map(|(name, _)| name).collect();
+        for preset in &self.presets {
+            if !presets.contains(preset) {
+                return Err(ActorLoadError::UnknownKey {
+                    span: Span::find(toml_path, toml, toml_line(toml, preset), preset),
+                    table: "status preset",
+                    key: preset.clone(),
+                    expected: presets,
+                });
+            }
+        }
+
+        let keys = StatusTable::keys();
+        for (key, &value) in &self.values {
+            check_key(toml_path, toml, "status", key, &keys)?;
+
+            let range = if key.ends_with("_turns") { SIGNED_BYTE } else { PERCENT };
+            check_range(toml_path, toml, key, value, range)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn table_struct(&self) -> Vec<String> {
+        let values = self.resolve();
+        let turns = |key: &str| values.get(&format!("{}_turns", key)).copied().unwrap_or(0);
+
+        std::iter::once("#new:StatusTable $StatusTable".to_owned())
+            .chain(STATUSES.iter().map(|&(key, name)| format!(".Status:{} {}`", name, values[key])))
+            .chain(STATUSES[1..].iter().map(|&(key, name)| format!(".Status:{}TurnMod {}`", name, turns(key))))
+            .chain(std::iter::once(".Status:End".to_owned()))
+            .collect()
+    }
+}
+
+/// Elements, as TOML keys and Star Rod names.
+const ELEMENTS: [(&str, &str); 13] = [
+    ("normal", "Normal"),
+    ("fire", "Fire"),
+    ("water", "Water"),
+    ("ice", "Ice"),
+    ("mystery", "Mystery"),
+    ("magic", "Magic"),
+    ("smash", "Smash"),
+    ("jump", "Jump"),
+    ("cosmic", "Cosmic"),
+    ("blast", "Blast"),
+    ("shock", "Shock"),
+    ("quake", "Quake"),
+    ("throw", "Throw"),
+];
+
+/// The `[defense]` table of an actor's TOML: how much damage of each element it blocks. 99 makes
+/// the actor immune.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(transparent)]
+pub struct DefenseTable {
+    values: BTreeMap<String, i64>,
+}
+
+impl DefenseTable {
+    pub fn check(&self, toml_path: &Path, toml: &str) -> Result<(), ActorLoadError> {
+        let keys: Vec<String> = ELEMENTS.iter().map(|&(key, _)| key.to_owned()).collect();
+
+        for (key, &value) in &self.values {
+            check_key(toml_path, toml, "element", key, &keys)?;
+            check_range(toml_path, toml, key, value, (0, 99))?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes the struct, named `pointer`. `Normal` is always given; other elements only if the
+    /// TOML does.
+    pub fn table_struct(&self, pointer: &str) -> Vec<String> {
+        std::iter::once(format!("#new:DefenseTable {}", pointer))
+            .chain(ELEMENTS.iter()
+                .filter(|&&(key, _)| key == "normal" || self.values.contains_key(key))
+                .map(|&(key, name)| format!(".Element:{} {}`", name, self.values.get(key).copied().unwrap_or(0))))
+            .chain(std::iter::once(".Element:End".to_owned()))
+            .collect()
+    }
+}
+
+/// Statuses an idle animation can be given for, as TOML keys and Star Rod names.
+const IDLE_STATUSES: [(&str, &str); 11] = [
+    ("normal", "Normal"),
+    ("stone", "Stone"),
+    ("sleep", "Sleep"),
+    ("poison", "Poison"),
+    ("frozen", "Frozen"),
+    ("dizzy", "Dizzy"),
+    ("fear", "Fear"),
+    ("static", "Static"),
+    ("paralyze", "Paralyze"),
+    ("shrink", "Shrink"),
+    ("stop", "Stop"),
+];
+
+/// A `[[parts]]` entry of an actor's TOML, one row of its `$SpriteTable`.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Part {
+    /// Used by `{ActorPart:actor:name}`, and to name the part's structs.
+    pub name: String,
+
+    /// Defaults to being the primary target (`00800000`) for the first part, and none for others.
+    flags: Option<u32>,
+
+    /// Position relative to the actor.
+    #[serde(default)]
+    offset: [i64; 3],
+
+    /// Where attacks aim, relative to the part.
+    #[serde(default)]
+    target_offset: [i64; 2],
+
+    /// Where projectiles aim, relative to the part.
+    #[serde(default)]
+    projectile_target_offset: [i64; 2],
+
+    #[serde(default = "Part::default_opacity")]
+    opacity: i64,
+
+    /// Sprite animations, as `sprite:animation`, by status. `normal` is required.
+    idle: BTreeMap<String, String>,
+
+    /// If not given, the part uses the actor's `$DefenseTable`.
+    defense: Option<DefenseTable>,
+
+    #[serde(default)]
+    event_flags: u32,
+
+    #[serde(default)]
+    immunity_flags: u32,
+}
+
+impl Part {
+    fn default_opacity() -> i64 {
+        255
+    }
+
+    /// Checks the parts' names are unique and each of their values fits.
+    pub fn check_all(parts: &[Part], toml_path: &Path, toml: &str) -> Result<(), ActorLoadError> {
+        let check = |key: &str, value: i64, range: (i64, i64)| check_range(toml_path, toml, key, value, range);
+        let bad_part = |part: &Part, reason: &str| ActorLoadError::BadPart {
+            span: Span::find(toml_path, toml, toml_line(toml, &part.name), &part.name),
+            part: part.name.clone(),
+            reason: reason.to_owned(),
+        };
+
+        let idle_keys: Vec<String> = IDLE_STATUSES.iter().map(|&(key, _)| key.to_owned()).collect();
+
+        for (idx, part) in parts.iter().enumerate() {
+            if parts[..idx].iter().any(|other| other.name == part.name) {
+                return Err(bad_part(part, "another part has the same name"));
+            }
+            if crate::sanitize::export_name(&part.name).is_err() {
+                return Err(bad_part(part, "part names may only contain lowercase letters, digits and underscores"));
+            }
+            if !part.idle.contains_key("normal") {
+                return Err(bad_part(part, "the part has no `normal` idle animation"));
+            }
+
+            for &value in &part.offset {
+                check("offset", value, SIGNED_BYTE)?;
+            }
+            for &value in part.target_offset.iter().chain(&part.projectile_target_offset) {
+                check("target_offset", value, SIGNED_BYTE)?;
+            }
+            check("opacity", part.opacity, BYTE)?;
+
+            for key in part.idle.keys() {
+                check_key(toml_path, toml, "idle animation status", key, &idle_keys)?;
+            }
+            if let Some(defense) = &part.defense {
+                defense.check(toml_path, toml)?;
+            }
+        }
+
+        if parts.len() > 255 {
+            return Err(bad_part(&parts[255], "actors can have at most 255 parts"));
+        }
+
+        Ok(())
+    }
+
+    fn idle_pointer(&self) -> String {
+        format!("$IdleAnimations_{}", self.name)
+    }
+
+    fn defense_pointer(&self) -> String {
+        match self.defense {
+            Some(_) => format!("$DefenseTable_{}", self.name),
+            None => "$DefenseTable".to_owned(),
+        }
+    }
+
+    /// Writes `$SpriteTable`, with a row for each part.
+    pub fn sprite_table(parts: &[Part]) -> Vec<String> {
+        // Signed bytes are written as their two's complement.
+        let bytes = |values: &[i64]| values.iter().map(|&value| format!("{:02X}", value as i8 as u8)).collect::<String>();
+
+        std::iter::once("#new:SpriteTable $SpriteTable".to_owned())
+            .chain(parts.iter().enumerate().map(|(idx, part)| {
+                let flags = part.flags.unwrap_or(if idx == 0 { 0x0080_0000 } else { 0 });
+
+                format!(
+                    "{:08X} {:02X}{} {}{:04X} {} {} {:08X} {:08X} {}0000 00000000",
+                    flags,
+                    idx + 1,
+                    bytes(&part.offset),
+                    bytes(&part.target_offset),
+                    part.opacity,
+                    part.idle_pointer(),
+                    part.defense_pointer(),
+                    part.event_flags,
+                    part.immunity_flags,
+                    bytes(&part.projectile_target_offset),
+                )
+            }))
+            .collect()
+    }
+
+    /// Writes the structs a part points to, each with its pointer.
+    pub fn structs(&self) -> Vec<(String, Vec<String>)> {
+        let idle = std::iter::once(format!("#new:IdleAnimations {}", self.idle_pointer()))
+            .chain(IDLE_STATUSES.iter()
+                .filter_map(|&(key, name)| self.idle.get(key)
+                    .map(|animation| format!(".Status:{} {{Sprite:{}}}", name, animation))))
+            .chain(std::iter::once(".Status:End".to_owned()))
+            .collect();
+
+        let mut structs = vec![(self.idle_pointer(), idle)];
+        if let Some(defense) = &self.defense {
+            structs.push((self.defense_pointer(), defense.table_struct(&self.defense_pointer())));
+        }
+        structs
+    }
+}
+
+/// The line a TOML string value, e.g. a preset name, is first on, or 0 if it isn't.
+fn toml_line(toml: &str, value: &str) -> usize {
+    let quoted = format!("\"{}\"", value);
+
+    toml.lines()
+        .position(|line| line.contains(&quoted))
+        .map(|idx| idx + 1)
+        .unwrap_or(0)
+}
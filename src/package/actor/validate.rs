@@ -0,0 +1,243 @@
+use super::{Actor, ActorId, ActorLoadError, defines};
+use super::tables::{BYTE, SIGNED_BYTE, PERCENT};
+use super::super::id::Identify;
+use super::super::script::Script;
+
+use std::collections::HashSet;
+
+/// What a field of the `$Actor` struct holds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    /// `{Actor:<this actor>}`.
+    Index,
+    /// A byte from `min` to `max`.
+    Byte((i64, i64)),
+    /// Two bytes from `min` to `max`, e.g. a size or position.
+    Pair((i64, i64)),
+    Number,
+    Pointer,
+}
+
+impl Kind {
+    fn expected(self) -> String {
+        match self {
+            Kind::Index => "`{Actor:...}`".to_owned(),
+            Kind::Byte((min, max)) => format!("a byte from {} to {}, e.g. `{}`b`", min, max, max.min(9)),
+            Kind::Pair((min, max)) => format!("two bytes from {} to {}, e.g. `{2}`b {2}`b`", min, max, max.min(24)),
+            Kind::Number => "a number, e.g. `00000000` or `1`s`".to_owned(),
+            Kind::Pointer => "a pointer, e.g. `$SpriteTable`".to_owned(),
+        }
+    }
+}
+
+/// The fields of Star Rod's Actor struct, what they hold, and whether they are required. The game
+/// reads the required ones when the actor enters battle. Ranges match the TOML's `[stats]`.
+const FIELDS: [(&str, Kind, bool); 20] = [
+    ("Index", Kind::Index, true),
+    ("Level", Kind::Byte(BYTE), false),
+    ("MaxHP", Kind::Byte((1, 255)), true),
+    ("Coins", Kind::Byte(BYTE), false),
+    ("Flags", Kind::Number, false),
+    ("StatusTable", Kind::Pointer, true),
+    ("SpriteCount", Kind::Number, true),
+    ("SpriteTable", Kind::Pointer, true),
+    ("Script", Kind::Pointer, true),
+    ("Escape", Kind::Byte(PERCENT), false),
+    ("Item", Kind::Byte(PERCENT), false),
+    ("AirLift", Kind::Byte(PERCENT), false),
+    ("Hurricane", Kind::Byte(PERCENT), false),
+    ("UpAndAway", Kind::Byte(PERCENT), false),
+    ("PowerBounce", Kind::Byte(PERCENT), false),
+    ("SpinSmash", Kind::Byte(BYTE), false),
+    ("Size", Kind::Pair(BYTE), false),
+    ("HealthBar", Kind::Pair(SIGNED_BYTE), false),
+    ("StatusTurn", Kind::Pair(SIGNED_BYTE), false),
+    ("StatusIcon", Kind::Pair(SIGNED_BYTE), false),
+];
+
+/// Parses a number as Star Rod writes them: decimal with a type suffix (`9`b`, `1`s`, `0``), or
+/// hex without one.
+fn number(word: &str) -> Option<i64> {
+    match word.find('`') {
+        Some(idx) if matches!(&word[idx + 1..], "" | "b" | "s") => word[..idx].parse().ok(),
+        Some(_) => None,
+        None => i64::from_str_radix(word, 16).ok(),
+    }
+}
+
+/// Whether a byte is from `min` to `max`. Hex gives the byte's bits, so is read as signed if the
+/// range is.
+fn is_byte(word: &str, (min, max): (i64, i64)) -> bool {
+    let value = match number(word) {
+        Some(value) if min < 0 && !word.contains('`') && (0x80..=0xFF).contains(&value) => value - 0x100,
+        Some(value) => value,
+        None => return false,
+    };
+
+    (min..=max).contains(&value)
+}
+
+impl Actor {
+    /// Checks the actor's script against the `$Actor` struct Star Rod expects, returning all
+    /// problems found. Scripts that fail to load are skipped; loading reports those.
+    pub fn validate(&self) -> Vec<ActorLoadError> {
+        let script = match self.script() {
+            Ok(script) => script,
+            Err(_) => return Vec::new(),
+        };
+
+        let mut errors = Vec::new();
+
+        if let Err(error) = self.check_tables(&script) {
+            errors.push(error);
+        }
+
+        let defined = self.check_definitions(&script, &mut errors);
+        self.check_struct(&script, &mut errors);
+        self.check_pointers(&script, &defined, &mut errors);
+
+        errors
+    }
+
+    fn check_struct(&self, script: &Script, errors: &mut Vec<ActorLoadError>) {
+        let block = match script.blocks.iter().find(|block| defines(block, "$Actor")) {
+            Some(block) => block,
+            None => {
+                errors.push(ActorLoadError::MissingStruct { actor: self.name() });
+                return;
+            },
+        };
+
+        let (header_line, header) = &block.lines[0];
+        let mut seen = HashSet::new();
+
+        for (line_no, line) in &block.lines[1..] {
+            let (field, value) = match line.strip_prefix('[').and_then(|line| line.split_once(']')) {
+                Some((field, value)) => (field, value.trim()),
+                None => continue,
+            };
+            let bad_field = |reason: String| ActorLoadError::BadField {
+                span: script.span(*line_no, field),
+                field: field.to_owned(),
+                reason,
+            };
+
+            let kind = match FIELDS.iter().find(|(name, ..)| *name == field) {
+                Some(&(_, kind, _)) => kind,
+                None => {
+                    let fields: Vec<&str> = FIELDS.iter().map(|(name, ..)| *name).collect();
+                    errors.push(bad_field(format!("no such field; expected one of {}", fields.join(", "))));
+                    continue;
+                },
+            };
+
+            if !seen.insert(field) {
+                errors.push(bad_field("given more than once".to_owned()));
+                continue;
+            }
+
+            let words: Vec<&str> = value.split_whitespace().collect();
+            let ok = match (kind, words.as_slice()) {
+                (Kind::Index, [index]) => {
+                    if let Some(error) = self.check_index(script, *line_no, index) {
+                        errors.push(error);
+                    }
+                    true
+                },
+                (Kind::Byte(range), [word]) => is_byte(word, range),
+                (Kind::Pair(range), [x, y]) => is_byte(x, range) && is_byte(y, range),
+                (Kind::Number, [word]) => number(word).is_some(),
+                (Kind::Pointer, [word]) => word.starts_with('$') && word.len() > 1,
+                _ => false,
+            };
+
+            if !ok {
+                errors.push(bad_field(format!("expected {}, found `{}`", kind.expected(), value)));
+            }
+        }
+
+        for &(field, ..) in FIELDS.iter().filter(|(_, _, required)| *required) {
+            if !seen.contains(field) {
+                errors.push(ActorLoadError::MissingField {
+                    span: script.span(*header_line, header),
+                    actor: self.name(),
+                    field,
+                });
+            }
+        }
+    }
+
+    /// Checks `[Index]` is `{Actor:<this actor>}`, so the struct is this actor's.
+    fn check_index(&self, script: &Script, line_no: usize, index: &str) -> Option<ActorLoadError> {
+        let own_id = ActorId::new(&self.src_pkg_name, &self.name());
+        let is_own = index.strip_prefix("{Actor:")
+            .and_then(|index| index.strip_suffix('}'))
+            .and_then(|id| ActorId::parse(id, &self.src_pkg_name).ok())
+            .is_some_and(|id| id == own_id);
+
+        if is_own {
+            None
+        } else {
+            Some(ActorLoadError::WrongIndex {
+                span: script.span(line_no, index),
+                actor: self.name(),
+                index: index.to_owned(),
+            })
+        }
+    }
+
+    /// Collects the pointers the script's blocks define, e.g. `$Script_Init` for
+    /// `#new:Script $Script_Init`, reporting any defined twice.
+    fn check_definitions<'a>(&self, script: &'a Script, errors: &mut Vec<ActorLoadError>) -> HashSet<&'a str> {
+        let mut defined = HashSet::new();
+
+        for block in &script.blocks {
+            let (line_no, header) = &block.lines[0];
+            if !header.starts_with('#') {
+                continue;
+            }
+
+            if let Some(pointer) = header.split_whitespace().nth(1).filter(|word| word.starts_with('$')) {
+                if !defined.insert(pointer) {
+                    errors.push(ActorLoadError::DuplicatePointer {
+                        span: script.span(*line_no, pointer),
+                        actor: self.name(),
+                        pointer: pointer.to_owned(),
+                    });
+                }
+            }
+        }
+
+        defined
+    }
+
+    /// Checks every `$Pointer` the script uses is defined in it. Star Rod's pointers are local to
+    /// the file, so none can come from elsewhere.
+    fn check_pointers(&self, script: &Script, defined: &HashSet<&str>, errors: &mut Vec<ActorLoadError>) {
+        let mut reported = HashSet::new();
+
+        for block in &script.blocks {
+            let lines = if block.lines[0].1.starts_with('#') { &block.lines[1..] } else { &block.lines[..] };
+
+            for (line_no, line) in lines {
+                let words = line.split(|c: char| c.is_whitespace() || c == '(' || c == ')' || c == ',');
+
+                for word in words.filter(|word| word.starts_with('$')) {
+                    // A pointer can be offset, e.g. `$Table[4]`.
+                    let pointer = word.split('[').next().unwrap();
+
+                    // Loading checks the tables the TOML can define, instead.
+                    let is_table = pointer == "$StatusTable" || pointer == "$DefenseTable";
+
+                    if !defined.contains(pointer) && !is_table && reported.insert(pointer) {
+                        errors.push(ActorLoadError::UndefinedPointer {
+                            span: script.span(*line_no, pointer),
+                            actor: self.name(),
+                            pointer: pointer.to_owned(),
+                        });
+                    }
+                }
+            }
+        }
+    }
+}
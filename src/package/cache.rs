@@ -0,0 +1,77 @@
+use crate::prelude::*;
+use super::digest::{self, Sha256};
+use std::collections::HashMap;
+
+/// Content hashes of the files written to a build directory, remembered between builds so that
+/// unchanged files aren't written again.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct BuildCache {
+    #[serde(skip)]
+    build_dir: PathBuf,
+
+    /// SHA-256 digests in hex, by path relative to the build directory. Caches from before digests
+    /// were used don't parse, so are discarded.
+    hashes: HashMap<PathBuf, String>,
+}
+
+impl BuildCache {
+    const FILE_NAME: &'static str = "cache.json";
+
+    /// Loads the cache for the given build directory. If there isn't one, or it can't be read,
+    /// every file will be written.
+    pub fn load(build_dir: &Path) -> BuildCache {
+        let cache = fs::read_to_string(build_dir.join(Self::FILE_NAME))
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default();
+
+        BuildCache {
+            build_dir: build_dir.to_owned(),
+            ..cache
+        }
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let path = self.build_dir.join(Self::FILE_NAME);
+
+        fs::write(&path, serde_json::to_string(self)?)
+            .with_context(|| format!("unable to write {}", path.display()))
+    }
+
+    /// Writes `contents` to `path`, unless it was written with the same contents last time.
+    /// Returns whether the file was written.
+    pub fn write(&mut self, path: &Path, contents: &[u8]) -> Result<bool> {
+        let key = self.key(path);
+        let hash = hash(contents);
+
+        if path.is_file() && self.hashes.get(&key) == Some(&hash) {
+            trace!("unchanged: {}", path.display());
+            return Ok(false);
+        }
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, contents)
+            .with_context(|| format!("unable to write {}", path.display()))?;
+
+        self.hashes.insert(key, hash);
+        Ok(true)
+    }
+
+    /// Deletes a file that is no longer part of the build.
+    pub fn remove(&mut self, path: &Path) -> Result<()> {
+        self.hashes.remove(&self.key(path));
+
+        fs::remove_file(path)
+            .with_context(|| format!("unable to remove {}", path.display()))
+    }
+
+    fn key(&self, path: &Path) -> PathBuf {
+        path.strip_prefix(&self.build_dir).unwrap_or(path).to_owned()
+    }
+}
+
+fn hash(contents: &[u8]) -> String {
+    digest::to_hex(&Sha256::digest(contents))
+}
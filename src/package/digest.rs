@@ -0,0 +1,35 @@
+//! SHA-256, for content digests that are saved between builds. Unlike `std`'s hashers, its output
+//! is the same on every platform and Rust release.
+
+use sha2::Digest as _;
+
+/// A SHA-256 digest.
+pub type Digest = [u8; 32];
+
+/// Incremental SHA-256 hasher.
+#[derive(Default)]
+pub struct Sha256(sha2::Sha256);
+
+impl Sha256 {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Hashes `data` in one go.
+    pub fn digest(data: &[u8]) -> Digest {
+        sha2::Sha256::digest(data).into()
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        self.0.update(data);
+    }
+
+    pub fn finish(self) -> Digest {
+        self.0.finalize().into()
+    }
+}
+
+/// Formats a digest as lowercase hex.
+pub fn to_hex(digest: &Digest) -> String {
+    digest.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
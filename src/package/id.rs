@@ -23,6 +23,14 @@ impl Identifier {
     pub fn from_package(pkg: &Package, name: &str) -> Identifier {
         Identifier::new(&pkg.manifest.name, name)
     }
+
+    pub fn package(&self) -> &str {
+        &self.package
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
 }
 
 impl fmt::Display for Identifier {
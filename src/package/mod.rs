@@ -1,5 +1,7 @@
 mod id;
 mod script;
+mod cache;
+mod digest;
 
 // TODO: make these share a common trait so code doesn't have to be copied everywhere.
 // Call them 'exports'?
@@ -8,30 +10,41 @@ mod text;
 mod actor;
 
 use crate::prelude::*;
+use crate::logger;
 use crate::sanitize;
-use std::collections::HashMap;
+use crate::diagnostics::{Diagnostics, Diagnostic, Span, Code};
+use std::collections::{HashMap, BTreeMap};
 use id::Identify;
 use script::Script;
+use cache::BuildCache;
+use digest::Digest;
 
 use sprite::*;
 use actor::*;
 use text::*;
 
-const SPRITESHEET_XML_PLAYERSPRITES: &str = r#"<PlayerSprites>
-    <Sprite id="1" src="01" name="Mario 1"/>
-    <Sprite id="2" src="02" name="Mario 2"/>
-    <Sprite id="3" src="03" name="Mario 3"/>
-    <Sprite id="4" src="04" name="Mario 4"/>
-    <Sprite id="5" src="05" name="Mario 5"/>
-    <Sprite id="6" src="06" name="Mario 6"/>
-    <Sprite id="7" src="07" name="Mario 7"/>
-    <Sprite id="8" src="08" name="Mario 8"/>
-    <Sprite id="9" src="09" name="Mario 9"/>
-    <Sprite id="A" src="0A" name="Peach 1"/>
-    <Sprite id="B" src="0B" name="Peach 2"/>
-    <Sprite id="C" src="0C" name="Peach 3"/>
-    <Sprite id="D" src="0D" name="Peach 4"/>
-</PlayerSprites>"#;
+pub use script::ResolveError;
+pub use text::LoadError as TextLoadError;
+pub use text::{StringFormat, ImportError, write_entries};
+pub use sprite::{SpriteLoadError, PreviewFormat};
+pub use actor::ActorLoadError;
+
+/// The reserved package name that refers to the base game, e.g. `{Sprite:pm64/goomba}`.
+const VANILLA_PACKAGE_NAME: &str = "pm64";
+
+/// The base game's player sprites, in index order. A package replaces one by naming a
+/// `src/player_sprite` directory after it, e.g. `mario_1`.
+const VANILLA_PLAYER_SPRITES: [&str; 13] = [
+    "Mario 1", "Mario 2", "Mario 3", "Mario 4", "Mario 5", "Mario 6", "Mario 7", "Mario 8",
+    "Mario 9", "Peach 1", "Peach 2", "Peach 3", "Peach 4",
+];
+
+/// The index of the vanilla player sprite that a player sprite export with this name replaces.
+fn vanilla_player_sprite(name: &str) -> Option<u8> {
+    VANILLA_PLAYER_SPRITES.iter()
+        .position(|vanilla| vanilla.to_lowercase().replace(' ', "_") == name)
+        .map(|idx| idx as u8 + 1)
+}
 
 /// A package is a collection of sprites, actors, etc that optionally depends on other packages.
 /// They can be assembled into a Star Rod mod folders to be compiled.
@@ -48,8 +61,14 @@ pub struct Package {
 
     /// Maps of Identifier -> export for this package and its cummulative dependencies.
     sprites: SpriteMap,
+    player_sprites: PlayerSpriteMap,
+    sprite_patches: SpritePatchMap,
+    player_sprite_patches: SpritePatchMap,
     actors: ActorMap,
     texts: TextMap,
+
+    /// Translated strings by locale, with the same ids as the strings in `texts` they translate.
+    translations: HashMap<String, TextMap>,
 }
 
 impl Package {
@@ -87,13 +106,19 @@ impl Package {
                 version: Version::parse("0.1.0").unwrap(),
 
                 dependencies: HashMap::new(),
+
+                strings: StringOptions::default(),
             },
 
             dependencies: Vec::new(),
 
             sprites: HashMap::new(),
+            player_sprites: HashMap::new(),
+            sprite_patches: HashMap::new(),
+            player_sprite_patches: HashMap::new(),
             actors: HashMap::new(),
             texts: HashMap::new(),
+            translations: HashMap::new(),
         };
 
         package.write_manifest()?;
@@ -103,8 +128,9 @@ impl Package {
         Ok(package)
     }
 
-    /// Loads the package at the given directory.
-    pub fn load(dir: &Path) -> Result<Package, LoadError> {
+    /// Loads the package at the given directory. Exports that fail to load are reported to
+    /// `diagnostics` and skipped, rather than failing the whole package.
+    pub fn load(dir: &Path, diagnostics: &mut Diagnostics) -> Result<Package, LoadError> {
         if !dir.is_dir() {
             return Err(LoadError::NotDirectory(dir.to_owned()));
         }
@@ -115,18 +141,27 @@ impl Package {
         debug!("loading package: {}", dir.display());
 
         // Read starpkg.toml.
+        let manifest_path = dir.join("starpkg.toml");
         let manifest: Manifest = {
-            let string = fs::read_to_string(dir.join("starpkg.toml"))
+            let string = fs::read_to_string(&manifest_path)
                 .map_err(LoadError::UnfoundManifest)?;
 
-            toml::from_str(&string)?
+            toml::from_str(&string)
+                .map_err(|error| LoadError::MalformedManifest {
+                    manifest: manifest_path.clone(),
+                    error,
+                })?
         };
 
-        sanitize::package_name(&manifest.name)?;
+        sanitize::package_name(&manifest.name)
+            .map_err(|error| LoadError::BadPackageName {
+                manifest: manifest_path.clone(),
+                error,
+            })?;
 
         // Load all dependencies into a flat vec.
         let mut deps = manifest
-            .load_dependencies(&dir)?
+            .load_dependencies(&dir, diagnostics)?
             .into_iter()
             .fold(Vec::new(), |mut deps, mut dep| {
                 deps.append(&mut dep.dependencies);
@@ -166,6 +201,21 @@ impl Package {
                 .map(|dep| dep.sprites.clone())
                 .fold(HashMap::new(), sum_hashmaps),
 
+            player_sprites: deps
+                .iter()
+                .map(|dep| dep.player_sprites.clone())
+                .fold(HashMap::new(), sum_hashmaps),
+
+            sprite_patches: deps
+                .iter()
+                .map(|dep| dep.sprite_patches.clone())
+                .fold(HashMap::new(), sum_hashmaps),
+
+            player_sprite_patches: deps
+                .iter()
+                .map(|dep| dep.player_sprite_patches.clone())
+                .fold(HashMap::new(), sum_hashmaps),
+
             actors: deps
                 .iter()
                 .map(|dep| dep.actors.clone())
@@ -176,6 +226,15 @@ impl Package {
                 .map(|dep| dep.texts.clone())
                 .fold(HashMap::new(), sum_hashmaps),
 
+            translations: deps
+                .iter()
+                .flat_map(|dep| dep.translations.clone())
+                .fold(HashMap::new(), |mut sum_map, (locale, texts)| {
+                    let map = sum_map.remove(&locale).unwrap_or_default();
+                    sum_map.insert(locale, sum_hashmaps(map, texts));
+                    sum_map
+                }),
+
             dependencies: deps,
         };
 
@@ -183,9 +242,10 @@ impl Package {
         let sprites_dir = dir.join("src/sprite");
         if sprites_dir.is_dir() {
             for entry in sprites_dir.read_dir().unwrap() {
-                let dir = entry.unwrap().path();
-                let sprite = Sprite::load(pkg.name(), &dir)
-                    .with_context(|| format!("unable to load sprite: {}", dir.display()))?;
+                let sprite = match load_sprite(pkg.name(), &entry.unwrap().path(), diagnostics) {
+                    Some(sprite) => sprite,
+                    None => continue,
+                };
 
                 let id = SpriteId::identify(&pkg, &sprite);
                 info!("loaded {:?}", &id);
@@ -194,12 +254,61 @@ impl Package {
             }
         }
 
+        // Load this package's player sprites.
+        let player_sprites_dir = dir.join("src/player_sprite");
+        if player_sprites_dir.is_dir() {
+            for entry in player_sprites_dir.read_dir().unwrap() {
+                let sprite = match load_sprite(pkg.name(), &entry.unwrap().path(), diagnostics) {
+                    Some(sprite) => sprite,
+                    None => continue,
+                };
+
+                let id = PlayerSpriteId::identify(&pkg, &sprite);
+                info!("loaded {:?}", &id);
+
+                pkg.player_sprites.insert(id, sprite);
+            }
+        }
+
+        pkg.check_player_sprite_conflicts(diagnostics);
+
+        // Load this package's patches to base game sprites.
+        for (patches_dir, player) in [("src/sprite_patch", false), ("src/player_sprite_patch", true)] {
+            let patches_dir = dir.join(patches_dir);
+            if !patches_dir.is_dir() {
+                continue;
+            }
+
+            for entry in patches_dir.read_dir().unwrap() {
+                let sprite = match load_sprite(pkg.name(), &entry.unwrap().path(), diagnostics) {
+                    Some(sprite) => sprite,
+                    None => continue,
+                };
+
+                let id = SpritePatchId::identify(&pkg, &sprite);
+                info!("loaded {:?}", &id);
+
+                if player {
+                    pkg.player_sprite_patches.insert(id, sprite);
+                } else {
+                    pkg.sprite_patches.insert(id, sprite);
+                }
+            }
+        }
+
         // Load this package's actors.
         let actors_dir = dir.join("src/actor");
         if actors_dir.is_dir() {
             for entry in actors_dir.read_dir().unwrap() {
                 let dir = entry.unwrap().path();
-                let actor = Actor::load(pkg.name(), dir)?;
+                let actor = match diagnostics.ok(Actor::load(pkg.name(), dir)) {
+                    Some(actor) => actor,
+                    None => continue,
+                };
+
+                for error in actor.validate() {
+                    diagnostics.report(error);
+                }
 
                 let id = ActorId::identify(&pkg, &actor);
                 info!("loaded {:?}", &id);
@@ -208,18 +317,35 @@ impl Package {
             }
         }
 
-        // Load this package's texts (strings).
+        // Load this package's texts (strings). Subdirectories hold translations, by locale.
         let texts_dir = dir.join("src/string");
         if texts_dir.is_dir() {
             for entry in texts_dir.read_dir().unwrap() {
-                let file = entry.unwrap().path();
-                let texts = Text::load_many(pkg.name(), file.clone())?;
+                let path = entry.unwrap().path();
+
+                if path.is_dir() {
+                    let locale = path.file_name().unwrap().to_string_lossy().into_owned();
+
+                    for entry in path.read_dir().unwrap() {
+                        let file = entry.unwrap().path();
+                        let texts = load_texts(pkg.name(), file, pkg.manifest.strings.wrap, diagnostics)?;
 
-                for text in texts {
-                    let id = TextId::identify(&pkg, &text);
-                    info!("loaded {:?}", &id);
+                        for text in texts {
+                            let id = TextId::identify(&pkg, &text);
+                            info!("loaded {:?} ({})", &id, &locale);
 
-                    pkg.texts.insert(id, text);
+                            pkg.translations.entry(locale.clone()).or_default().insert(id, text);
+                        }
+                    }
+                } else {
+                    let texts = load_texts(pkg.name(), path, pkg.manifest.strings.wrap, diagnostics)?;
+
+                    for text in texts {
+                        let id = TextId::identify(&pkg, &text);
+                        info!("loaded {:?}", &id);
+
+                        pkg.texts.insert(id, text);
+                    }
                 }
             }
         }
@@ -228,7 +354,7 @@ impl Package {
     }
 
     /// Traverses upwards from the given root path, looking for the first package we see.
-    pub fn find(root: &Path) -> Result<Package, FindError> {
+    pub fn find(root: &Path, diagnostics: &mut Diagnostics) -> Result<Package, FindError> {
         // Make the root path absolute.
         let mut path = root.canonicalize()
             .map_err(|source| FindError::UnfoundRoot {
@@ -237,7 +363,7 @@ impl Package {
             })?;
 
         loop {
-            match Package::load(&path) {
+            match Package::load(&path, diagnostics) {
                 Ok(package) => return Ok(package),
                 Err(LoadError::UnfoundManifest(_)) => (),
                 Err(err) => return Err(FindError::LoadError(err)),
@@ -256,22 +382,175 @@ impl Package {
         &self.manifest.name
     }
 
+    /// Reports vanilla player sprites replaced by more than one package. Conflicts that one of our
+    /// dependencies has already reported aren't reported again.
+    fn check_player_sprite_conflicts(&self, diagnostics: &mut Diagnostics) {
+        let mut replacements: Vec<(u8, Vec<&PlayerSpriteId>)> = Vec::new();
+        for (id, sprite) in &self.player_sprites {
+            if let Some(index) = vanilla_player_sprite(&sprite.name()) {
+                match replacements.iter_mut().find(|(i, _)| *i == index) {
+                    Some((_, ids)) => ids.push(id),
+                    None => replacements.push((index, vec![id])),
+                }
+            }
+        }
+
+        for (index, ids) in replacements {
+            let already_reported = self.dependencies.iter()
+                .any(|dep| ids.iter().all(|id| dep.player_sprites.contains_key(id)));
+
+            if ids.len() > 1 && !already_reported {
+                let mut packages: Vec<String> = ids.iter().map(|id| id.package().to_owned()).collect();
+                packages.sort();
+
+                diagnostics.report(LoadError::PlayerSpriteConflict {
+                    sprite: VANILLA_PLAYER_SPRITES[index as usize - 1].to_owned(),
+                    packages,
+                });
+            }
+        }
+    }
+
+    /// Looks up a sprite by identifier, e.g. `fizzlit` or `some_dependency/sprite`.
+    pub fn sprite(&self, id: &str) -> Result<&Sprite> {
+        SpriteId::parse(id, self.name())?
+            .resolve(&self.sprites)
+            .ok_or_else(|| anyhow!("no such sprite: {}", id))
+    }
+
+    /// This package's own strings, in the order they appear in their files, with their
+    /// translations into the given locale.
+    pub fn string_entries(&self, locale: Option<&str>) -> Vec<StringEntry> {
+        let translations = locale.and_then(|locale| self.translations.get(locale));
+
+        let mut texts: Vec<(&TextId, &Text)> = self.texts.iter()
+            .filter(|(id, _)| id.package() == self.name())
+            .collect();
+        texts.sort_by_key(|(_, text)| text.location());
+
+        texts.into_iter()
+            .map(|(id, text)| text.entry(translations.and_then(|translations| translations.get(id))))
+            .collect()
+    }
+
+    /// Reads translations of this package's strings from a file, and writes them into the string
+    /// files of the given locale. Untranslated entries are skipped, and bad translations reported
+    /// to `diagnostics`, in which case nothing is written. Returns the number of strings translated.
+    pub fn import_strings(
+        &self,
+        locale: &str,
+        path: &Path,
+        format: StringFormat,
+        diagnostics: &mut Diagnostics,
+    ) -> Result<usize> {
+        // The locale names a directory, so mustn't lead out of `src/string`.
+        sanitize::locale(locale)?;
+
+        let source = fs::read_to_string(path)
+            .with_context(|| format!("unable to read {}", path.display()))?;
+        let entries = match text::parse_entries(format, path, &source) {
+            Ok(entries) => entries,
+            Err(error) => {
+                diagnostics.report(error);
+                return Ok(0);
+            },
+        };
+
+        let existing = self.translations.get(locale);
+        let mut translations = Vec::new();
+
+        for (line_no, entry) in entries {
+            if entry.translation.trim().is_empty() {
+                continue;
+            }
+
+            let span = source.lines()
+                .nth(line_no - 1)
+                .map(|line| Span::new(path, line_no, 1, line.trim_end().chars().count()));
+            let id = TextId::new(self.name(), &entry.name);
+
+            let text = match self.texts.get(&id) {
+                Some(text) => text,
+                None => {
+                    diagnostics.report(ImportError::UnknownString {
+                        span,
+                        name: entry.name,
+                    });
+                    continue;
+                },
+            };
+
+            // Star Rod reads a line starting with `#` as the start of the next block.
+            if entry.translation.lines().any(|line| line.trim_start().starts_with('#')) {
+                diagnostics.report(ImportError::DirectiveLine {
+                    span,
+                    name: entry.name,
+                });
+                continue;
+            }
+
+            if let Err(error) = text::check_translation(&entry.translation) {
+                diagnostics.report(ImportError::BadTranslation {
+                    span,
+                    name: entry.name,
+                    error,
+                });
+                continue;
+            }
+
+            let (missing, added) = text::tag_differences(text, &entry.translation);
+            if !missing.is_empty() || !added.is_empty() {
+                diagnostics.report(ImportError::MismatchedTags {
+                    span,
+                    name: entry.name,
+                    missing,
+                    added,
+                });
+                continue;
+            }
+
+            translations.push((text, existing.and_then(|existing| existing.get(&id)), entry.translation));
+        }
+
+        // Only write anything once every translation is known to be good.
+        if diagnostics.error_count() > 0 {
+            return Ok(0);
+        }
+
+        text::save_translations(&self.dir.join("src/string").join(locale), &translations)?;
+        Ok(translations.len())
+    }
+
     /// Assembles the package to a mod directory, ready to be compiled by Star Rod.
-    pub fn assemble(&mut self, build_dir: &Path) -> Result<()> {
+    /// Unresolvable references are reported to `diagnostics`. Strings are in the given locale, or
+    /// the manifest's default one, falling back to the base language.
+    pub fn assemble(
+        &mut self,
+        build_dir: &Path,
+        locale: Option<&str>,
+        diagnostics: &mut Diagnostics,
+    ) -> Result<()> {
         let _ = fs::create_dir_all(build_dir);
 
         // Scripts can reference assembled exports, so we'll process them after assembling
         // everything else.
         let mut scripts = Vec::new();
+        let mut cache = BuildCache::load(build_dir);
 
         // Sprites.
-        self.assemble_sprites(&build_dir.join("sprite"))?;
+        self.assemble_sprites(&build_dir.join("sprite"), &mut cache, diagnostics)?;
 
         // Texts.
+        self.report_translations(diagnostics);
+        let locale = locale.map(str::to_owned).or_else(|| self.manifest.strings.locale.clone());
+        if let Some(locale) = locale {
+            self.translate_strings(&locale)?;
+        }
+        text::resolve_expressions(&mut self.texts, &self.actors, diagnostics);
         self.assemble_strings(&build_dir.join("strings"))?;
 
         // Actors.
-        scripts.append(&mut self.assemble_actors(&build_dir.join("battle"))?);
+        scripts.append(&mut self.assemble_actors(&build_dir.join("battle"), diagnostics)?);
 
         // TODO: assemble battles
         // TODO: assemble npcs
@@ -279,16 +558,38 @@ impl Package {
 
         // Assembly is done - time to process + save scripts!
         for mut script in scripts {
-            script.resolve_expressions(&self.sprites, &self.texts, &self.actors)?;
+            script.resolve_expressions(
+                &self.sprites,
+                &self.player_sprites,
+                &self.texts,
+                &self.actors,
+                diagnostics,
+            );
             script.save()?;
         }
 
-        Ok(())
+        cache.save()
     }
 
-    fn assemble_sprites(&mut self, sprites_dir: &Path) -> Result<()> {
+    fn assemble_sprites(
+        &mut self,
+        sprites_dir: &Path,
+        cache: &mut BuildCache,
+        diagnostics: &mut Diagnostics,
+    ) -> Result<()> {
         let _ = fs::create_dir_all(sprites_dir);
 
+        // Patched base game sprites keep their place, e.g. `{Sprite:pm64/goomba}` is still 01.
+        for (name, sprite) in self.patch_vanilla_sprites(&self.sprite_patches, VanillaTable::Npc, diagnostics) {
+            self.sprites.insert(SpriteId::new(VANILLA_PACKAGE_NAME, &name), sprite);
+        }
+
+        // Patched player sprites keep their place, e.g. `{PlayerSprite:pm64/mario_1}` is still 01.
+        self.check_player_sprite_patches(diagnostics);
+        for (name, sprite) in self.patch_vanilla_sprites(&self.player_sprite_patches, VanillaTable::Player, diagnostics) {
+            self.player_sprites.insert(PlayerSpriteId::new(VANILLA_PACKAGE_NAME, &name), sprite);
+        }
+
         fs::write(sprites_dir.join("SpriteTable.xml"), {
             let mut xml = String::new();
             writeln!(xml, r#"<?xml version="1.0" encoding="UTF-8" standalone="no"?>"#)?;
@@ -296,8 +597,36 @@ impl Package {
 
             // NPC sprites.
             writeln!(xml, "    <NpcSprites>")?;
-            let mut index = 1u8;
+            let mut shared = 0;
+
+            // If any are patched, the base game's sprites are listed first so their indices are kept.
+            let vanilla_sprites = match self.sprites.values().any(|sprite| sprite.vanilla().is_some()) {
+                true => VanillaSprite::list(VanillaTable::Npc)?,
+                false => Vec::new(),
+            };
+            for vanilla in &vanilla_sprites {
+                writeln!(xml, r#"        <Sprite id="{idx:X}" src="{idx:02X}" name="{name}"/>"#,
+                    idx  = vanilla.index,
+                    name = vanilla.name,
+                )?;
+
+                let patched = self.sprites.iter_mut()
+                    .find(|(_, sprite)| sprite.vanilla().map(|v| v.index) == Some(vanilla.index));
+                if let Some((sprite_id, sprite)) = patched {
+                    let sprite_dir = sprites_dir.join(format!("npc/src/{:02X}", vanilla.index));
+                    let _ = fs::create_dir_all(&sprite_dir);
+
+                    shared += sprite.assemble(&sprite_dir, vanilla.index, true, cache, diagnostics)?;
+                    debug!("npc sprite {:02X} = {:?}", vanilla.index, &sprite_id);
+                }
+            }
+
+            let mut index = vanilla_sprites.last().map_or(1, |vanilla| vanilla.index + 1);
             for (sprite_id, sprite) in &mut self.sprites {
+                if sprite.vanilla().is_some() {
+                    continue;
+                }
+
                 writeln!(xml, r#"        <Sprite id="{idx:X}" src="{idx:02X}" name="{name}"/>"#,
                     idx  = index,
                     name = sprite_id,
@@ -306,20 +635,202 @@ impl Package {
                 let sprite_dir = sprites_dir.join(format!("npc/src/{:02X}", index));
                 let _ = fs::create_dir_all(&sprite_dir);
 
-                sprite.assemble(&sprite_dir, index)?;
+                shared += sprite.assemble(&sprite_dir, index, false, cache, diagnostics)?;
                 debug!("npc sprite {:02X} = {:?}", index, &sprite_id);
 
                 index += 1; // TODO: check overflow
             }
             writeln!(xml, "    </NpcSprites>")?;
 
-            // Player sprites (TODO: support patching these?)
-            writeln!(xml, "{}", SPRITESHEET_XML_PLAYERSPRITES)?;
+            // Player sprites. Packages can replace the vanilla ones, and add more after them.
+            writeln!(xml, "    <PlayerSprites>")?;
+            let (mut replacements, mut additions): (Vec<_>, Vec<_>) = self.player_sprites.iter_mut()
+                .partition(|(_, sprite)| vanilla_player_sprite(&sprite.name()).is_some());
+            replacements.sort_by_key(|(_, sprite)| vanilla_player_sprite(&sprite.name()));
+
+            for (idx, vanilla_name) in VANILLA_PLAYER_SPRITES.iter().enumerate() {
+                let index = idx as u8 + 1;
+                writeln!(xml, r#"        <Sprite id="{idx:X}" src="{idx:02X}" name="{name}"/>"#,
+                    idx  = index,
+                    name = vanilla_name,
+                )?;
+
+                // Conflicting replacements have already been reported, so just use the first.
+                let replacement = replacements.iter_mut()
+                    .find(|(_, sprite)| vanilla_player_sprite(&sprite.name()) == Some(index));
+                if let Some((sprite_id, sprite)) = replacement {
+                    let sprite_dir = sprites_dir.join(format!("player/src/{:02X}", index));
+                    shared += sprite.assemble(&sprite_dir, index, true, cache, diagnostics)?;
+                    debug!("player sprite {:02X} = {:?}", index, &sprite_id);
+                }
+            }
+
+            let first_addition = VANILLA_PLAYER_SPRITES.len() as u8 + 1;
+            for (index, (sprite_id, sprite)) in (first_addition..).zip(&mut additions) {
+                writeln!(xml, r#"        <Sprite id="{idx:X}" src="{idx:02X}" name="{name}"/>"#,
+                    idx  = index,
+                    name = sprite_id,
+                )?;
+
+                let sprite_dir = sprites_dir.join(format!("player/src/{:02X}", index));
+                shared += sprite.assemble(&sprite_dir, index, false, cache, diagnostics)?;
+                debug!("player sprite {:02X} = {:?}", index, &sprite_id);
+            }
+            writeln!(xml, "    </PlayerSprites>")?;
+
+            if shared > 0 {
+                info!("shared identical palettes and rasters within sprites, saving {} bytes", shared);
+            }
 
             xml += "</SpriteTable>";
             xml
         }).with_context(|| "unable to write to SpriteTable.xml")?;
 
+        self.report_duplicate_images();
+
+        Ok(())
+    }
+
+    /// Reports each image of the assembled sprites that is identical to one in another sprite, and
+    /// how many bytes they take up. Star Rod stores each sprite separately, so these can't be shared.
+    fn report_duplicate_images(&self) {
+        let npc_sprites = self.sprites.iter().map(|(id, sprite)| (format!("{:?}", id), sprite));
+        let player_sprites = self.player_sprites.iter().map(|(id, sprite)| (format!("{:?}", id), sprite));
+        let mut sprites: Vec<(String, &Sprite)> = npc_sprites.chain(player_sprites).collect();
+        sprites.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        // A raster and a palette can't be identical, as they store different data.
+        let mut first_rasters: HashMap<Digest, (String, &str)> = HashMap::new();
+        let mut first_palettes: HashMap<Digest, (String, &str)> = HashMap::new();
+        let mut duplicated = 0;
+        for (id, sprite) in &sprites {
+            for (images, first_seen) in [
+                (sprite.rasters(), &mut first_rasters),
+                (sprite.palettes(), &mut first_palettes),
+            ] {
+                for image in images {
+                    match first_seen.get(&image.digest) {
+                        // Only images in other sprites are duplicates; within a sprite, they can be shared.
+                        Some((first_src, first_id)) if first_id != id => {
+                            info!("{} in {} is identical to {} in {}",
+                                image.src, id, first_src, first_id);
+                            duplicated += image.size;
+                        },
+                        Some(_) => (),
+                        None => {
+                            first_seen.insert(image.digest, (image.src, id));
+                        },
+                    }
+                }
+            }
+        }
+
+        if duplicated > 0 {
+            info!("sprites have {} bytes of images identical to those in other sprites", duplicated);
+        }
+    }
+
+    /// Applies every package's patches to the base game sprites they patch, in package name order,
+    /// returning each patched sprite by name. Sprites that can't be patched are reported and skipped.
+    fn patch_vanilla_sprites(
+        &self,
+        sprite_patches: &SpritePatchMap,
+        table: VanillaTable,
+        diagnostics: &mut Diagnostics,
+    ) -> Vec<(String, Sprite)> {
+        let mut patches: BTreeMap<&str, Vec<(&SpritePatchId, &Sprite)>> = BTreeMap::new();
+        for (id, sprite) in sprite_patches {
+            patches.entry(id.vanilla_name()).or_default().push((id, sprite));
+        }
+
+        let mut patched = Vec::new();
+        for (name, mut patches) in patches {
+            patches.sort_by_key(|(id, _)| id.package());
+
+            match Sprite::patch_vanilla(name, table, &patches) {
+                Ok(sprite) => patched.push((name.to_owned(), sprite)),
+                Err(source) => diagnostics.report(LoadError::BadSprite {
+                    dir: patches[0].1.dir().to_owned(),
+                    source: Box::new(source),
+                }),
+            }
+        }
+        patched
+    }
+
+    /// Reports base game player sprites that are both replaced and patched, since patches add to
+    /// the base game's sprite rather than a replacement.
+    fn check_player_sprite_patches(&self, diagnostics: &mut Diagnostics) {
+        let mut patched: Vec<&SpritePatchId> = self.player_sprite_patches.keys().collect();
+        patched.sort_by_key(|id| (id.vanilla_name(), id.package()));
+
+        for patch_id in patched {
+            let replacement = self.player_sprites.iter()
+                .find(|(_, sprite)| sprite.name() == patch_id.vanilla_name());
+
+            if let Some((replacement_id, _)) = replacement {
+                diagnostics.report(LoadError::PatchedPlayerSpriteReplaced {
+                    sprite: patch_id.vanilla_name().to_owned(),
+                    replaced_by: replacement_id.package().to_owned(),
+                    patched_by: patch_id.package().to_owned(),
+                });
+            }
+        }
+    }
+
+    /// Warns about strings each locale is missing, and those it has that the base language doesn't.
+    fn report_translations(&self, diagnostics: &mut Diagnostics) {
+        let mut locales: Vec<&String> = self.translations.keys().collect();
+        locales.sort();
+
+        for locale in locales {
+            let translations = &self.translations[locale];
+
+            let mut untranslated: Vec<&TextId> = self.texts.keys()
+                .filter(|id| !translations.contains_key(id))
+                .collect();
+            untranslated.sort_by_key(|id| id.to_string());
+
+            let mut orphaned: Vec<&TextId> = translations.keys()
+                .filter(|id| !self.texts.contains_key(id))
+                .collect();
+            orphaned.sort_by_key(|id| id.to_string());
+
+            if untranslated.is_empty() {
+                info!("locale '{}' translates every string", locale);
+            } else {
+                let list: Vec<String> = untranslated.iter().map(|id| format!("{:?}", id)).collect();
+                diagnostics.warn(Diagnostic::warning(format!("locale '{}' is missing {} of {} strings: {}",
+                    locale, untranslated.len(), self.texts.len(), list.join(", ")))
+                    .with_note("untranslated strings are left in the base language"));
+            }
+
+            for id in orphaned {
+                let text = &translations[id];
+                let (path, line) = text.location();
+                let span = fs::read_to_string(path).ok()
+                    .and_then(|source| Span::find(path, &source, line, text.name()));
+
+                diagnostics.warn(Diagnostic::warning(format!(
+                    "locale '{}' translates {:?}, which isn't a string in the base language", locale, id,
+                )).with_label(span, "no such string"));
+            }
+        }
+    }
+
+    /// Replaces strings with their translations into the given locale. Strings without one are
+    /// left in the base language.
+    fn translate_strings(&mut self, locale: &str) -> Result<()> {
+        let translations = self.translations.get(locale)
+            .ok_or_else(|| anyhow!("no strings are translated to locale '{}'", locale))?;
+
+        for (id, translation) in translations {
+            if let Some(text) = self.texts.get_mut(id) {
+                text.translate(translation);
+            }
+        }
+
+        info!("using strings from locale '{}'", locale);
         Ok(())
     }
 
@@ -328,19 +839,41 @@ impl Package {
         let _ = fs::remove_dir_all(&strings_dir);
         fs::create_dir_all(strings_dir)?;
 
-        let mut sections = [0u16; 255];
-        for text in self.texts.values_mut() {
-            let section_idx = text.string_section() as usize;
+        // Strings that don't give a section go in one for what they're used for.
+        let mut usages = HashMap::new();
+        for actor in self.actors.values() {
+            usages.entry(actor.name.clone()).or_insert(Usage::ActorName);
+            usages.entry(actor.tattle.clone()).or_insert(Usage::Tattle);
+        }
+
+        // Strings with a section go first, so that new sections can avoid theirs.
+        let mut ids: Vec<TextId> = self.texts.keys().cloned().collect();
+        ids.sort_by_key(|id| (self.texts[id].string_section().is_none(), id.to_string()));
+
+        let mut sections = SectionAllocator::new();
+        for id in ids {
+            let text = self.texts.get_mut(&id).unwrap();
 
-            text.assemble(strings_dir, sections[section_idx])?;
+            let (section, index) = match text.string_section() {
+                Some(section) => (section, sections.next_in(section)),
+                None => {
+                    let usage = usages.get(&id).copied().unwrap_or(Usage::Dialogue);
+                    sections.allocate(id.package(), usage)?
+                },
+            };
 
-            sections[section_idx] += 1;
+            text.assemble(strings_dir, section, index)?;
+            debug!("string {:02X}:{:03X} = {:?}", section, index, &id);
         }
 
         Ok(())
     }
 
-    fn assemble_actors(&mut self, battle_dir: &Path) -> Result<Vec<Script>> {
+    fn assemble_actors(
+        &mut self,
+        battle_dir: &Path,
+        diagnostics: &mut Diagnostics,
+    ) -> Result<Vec<Script>> {
         let actors_dir = battle_dir.join("formation/import/actor");
         let _ = fs::create_dir_all(&actors_dir);
 
@@ -353,17 +886,21 @@ impl Package {
 
             let mut index = 0u8;
             for (actor_id, actor) in &mut self.actors {
-                writeln!(xml, r#"   <Actor id="{idx:02X}" name="{name}" tattle="{tattle}"/>"#,
-                    idx  = index,
-                    name = actor.name.resolve(&self.texts)
-                        .with_context(|| format!("actor name not found: {:?}", actor.name))?
-                        .assembled_hex_id()
-                        .expect("actor name string was not assembled"),
-                    tattle = actor.tattle.resolve(&self.texts)
-                        .with_context(|| format!("actor tattle not found: {:?}", actor.tattle))?
-                        .assembled_hex_id()
-                        .expect("actor tattle string was not assembled"),
-                )?;
+                let name = actor.name.resolve(&self.texts)
+                    .map(|text| text.assembled_hex_id().expect("actor name string was not assembled"))
+                    .ok_or_else(|| anyhow!("actor name not found: {:?}", actor.name));
+                let tattle = actor.tattle.resolve(&self.texts)
+                    .map(|text| text.assembled_hex_id().expect("actor tattle string was not assembled"))
+                    .ok_or_else(|| anyhow!("actor tattle not found: {:?}", actor.tattle));
+
+                if let (Some(name), Some(tattle)) = (diagnostics.ok(name), diagnostics.ok(tattle)) {
+                    writeln!(xml, r#"   <Actor id="{idx:02X}" name="{name}" tattle="{tattle}"{attributes}/>"#,
+                        idx        = index,
+                        name       = name,
+                        tattle     = tattle,
+                        attributes = actor.type_attributes(),
+                    )?;
+                }
 
                 scripts.push(actor.assemble(&actors_dir, index)?);
                 debug!("actor {:02X} = {:?}", index, &actor_id);
@@ -396,7 +933,7 @@ impl Eq for Package {}
 
 impl fmt::Display for Package {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", Color::Fixed(14).normal().paint(
+        write!(f, "{}", logger::style(Color::Fixed(14)).paint(
             format!("{} v{}", &self.manifest.name, &self.manifest.version)
         ))
     }
@@ -410,17 +947,54 @@ pub enum LoadError {
     #[error("missing starpkg.toml - not a package?")]
     UnfoundManifest(#[source] io::Error),
 
-    #[error("malformed starpkg.toml: {0}")]
-    MalformedManifest(#[from] toml::de::Error),
+    #[error("malformed starpkg.toml: {error}")]
+    MalformedManifest {
+        manifest: PathBuf,
+
+        #[source]
+        error: toml::de::Error,
+    },
 
     #[error("dependencies with the same name but different versions found")]
     MultiDependencyVersionMismatch,
 
-    #[error(transparent)]
-    BadPackageName(#[from] sanitize::PackageNameError),
+    #[error("{error}")]
+    BadPackageName {
+        manifest: PathBuf,
 
-    #[error(transparent)]
-    BadDependencyName(#[from] sanitize::DependencyNameError),
+        #[source]
+        error: sanitize::PackageNameError,
+    },
+
+    #[error("{error}")]
+    BadDependencyName {
+        manifest: PathBuf,
+        name: String,
+
+        #[source]
+        error: sanitize::DependencyNameError,
+    },
+
+    #[error("unable to load sprite {dir}: {source}")]
+    BadSprite {
+        dir: PathBuf,
+
+        #[source]
+        source: Box<SpriteLoadError>,
+    },
+
+    #[error("player sprite '{sprite}' is replaced by more than one package: {}", packages.join(", "))]
+    PlayerSpriteConflict {
+        sprite: String,
+        packages: Vec<String>,
+    },
+
+    #[error("player sprite '{sprite}' is replaced by package '{replaced_by}', so package '{patched_by}' can't patch it")]
+    PatchedPlayerSpriteReplaced {
+        sprite: String,
+        replaced_by: String,
+        patched_by: String,
+    },
 
     #[error(transparent)]
     TextLoadError(#[from] text::LoadError),
@@ -429,6 +1003,57 @@ pub enum LoadError {
     Other(#[from] Error),
 }
 
+impl Code for LoadError {
+    fn code(&self) -> Option<&'static str> {
+        match self {
+            LoadError::NotDirectory(_) => Some("SP0101"),
+            LoadError::UnfoundManifest(_) => Some("SP0102"),
+            LoadError::MalformedManifest { .. } => Some("SP0103"),
+            LoadError::MultiDependencyVersionMismatch => Some("SP0104"),
+            LoadError::BadPackageName { error, .. } => error.code(),
+            LoadError::BadDependencyName { error, .. } => error.code(),
+            LoadError::BadSprite { source, .. } => source.code(),
+            LoadError::PlayerSpriteConflict { .. } => Some("SP0107"),
+            LoadError::PatchedPlayerSpriteReplaced { .. } => Some("SP0108"),
+            LoadError::TextLoadError(error) => error.code(),
+            LoadError::Other(_) => None,
+        }
+    }
+}
+
+impl From<LoadError> for Diagnostic {
+    fn from(error: LoadError) -> Diagnostic {
+        let manifest_source = |manifest: &Path| fs::read_to_string(manifest).unwrap_or_default();
+        let code = error.code();
+
+        let diagnostic = match error {
+            LoadError::MalformedManifest { manifest, error } => Diagnostic::toml(&manifest, &error),
+            LoadError::BadPackageName { ref manifest, error: ref name_error } => {
+                let source = manifest_source(manifest);
+                let diagnostic = Diagnostic::error(&error)
+                    .with_label(Span::toml_value(manifest, &source, "name"), "invalid name");
+
+                match name_error {
+                    sanitize::PackageNameError::Reserved(_) =>
+                        diagnostic.with_note("this name is reserved for the base game"),
+                    _ => diagnostic,
+                }
+            },
+            LoadError::BadDependencyName { ref manifest, ref name, .. } => {
+                let source = manifest_source(manifest);
+                Diagnostic::error(&error)
+                    .with_label(Span::toml_key(manifest, &source, name), "invalid name")
+            },
+            LoadError::BadSprite { source, .. } if source.has_span() => (*source).into(),
+            LoadError::TextLoadError(error) => error.into(),
+            LoadError::Other(error) => error.into(),
+            error => Error::from(error).into(),
+        };
+
+        diagnostic.with_code(code)
+    }
+}
+
 #[derive(Error, Debug)]
 pub enum FindError {
     #[error("root path {root} does not exist")]
@@ -448,6 +1073,37 @@ pub enum FindError {
     LoadError(#[from] LoadError),
 }
 
+impl Code for FindError {
+    fn code(&self) -> Option<&'static str> {
+        match self {
+            FindError::UnfoundRoot { .. } => Some("SP0105"),
+            FindError::NotFound { .. } => Some("SP0106"),
+            FindError::LoadError(error) => error.code(),
+        }
+    }
+}
+
+/// Loads a sprite, reporting any problems with it. Sprites with invalid rasters are still returned,
+/// so they can be referenced by scripts.
+fn load_sprite(pkg_name: &str, dir: &Path, diagnostics: &mut Diagnostics) -> Option<Sprite> {
+    let sprite = match Sprite::load(pkg_name, dir) {
+        Ok(sprite) => sprite,
+        Err(source) => {
+            diagnostics.report(LoadError::BadSprite { dir: dir.to_owned(), source: Box::new(source) });
+            return None;
+        },
+    };
+
+    for source in sprite.validate() {
+        diagnostics.report(LoadError::BadSprite {
+            dir: dir.to_owned(),
+            source: Box::new(source),
+        });
+    }
+
+    Some(sprite)
+}
+
 /// Finds the relative path to the given target path from the current working directory.
 fn relative_path_to(target: &Path) -> io::Result<PathBuf> {
     use std::path::Component;
@@ -491,6 +1147,19 @@ fn combine_path_components(components: Vec<std::path::Component>) -> PathBuf {
     buf
 }
 
+/// Loads a `.str` or `.toml` string file.
+fn load_texts(
+    pkg_name: &str,
+    path: PathBuf,
+    wrap: bool,
+    diagnostics: &mut Diagnostics,
+) -> Result<Vec<Text>, text::LoadError> {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("toml") => Text::load_toml(path, wrap, diagnostics),
+        _ => Text::load_many(pkg_name, path, wrap, diagnostics),
+    }
+}
+
 /// A starpkg.toml.
 #[derive(Serialize, Deserialize, Debug)]
 struct Manifest {
@@ -499,14 +1168,44 @@ struct Manifest {
 
     #[serde(default)]
     dependencies: HashMap<String, Dependency>,
+
+    #[serde(default, skip_serializing_if = "StringOptions::is_default")]
+    strings: StringOptions,
+}
+
+/// The `[strings]` table of a starpkg.toml.
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq, Eq)]
+struct StringOptions {
+    /// Break lines that are too wide for their box, rather than warning about them.
+    #[serde(default)]
+    wrap: bool,
+
+    /// The locale to build with when none is given.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    locale: Option<String>,
+}
+
+impl StringOptions {
+    fn is_default(&self) -> bool {
+        *self == StringOptions::default()
+    }
 }
 
 impl Manifest {
-    fn load_dependencies(&self, pkg_dir: &Path) -> Result<Vec<Package>, LoadError> {
+    fn load_dependencies(
+        &self,
+        pkg_dir: &Path,
+        diagnostics: &mut Diagnostics,
+    ) -> Result<Vec<Package>, LoadError> {
         let mut packages = Vec::new();
 
         for (name, dep) in &self.dependencies {
-            sanitize::dependency_name(&name, &self.name)?;
+            sanitize::dependency_name(&name, &self.name)
+                .map_err(|error| LoadError::BadDependencyName {
+                    manifest: pkg_dir.join("starpkg.toml"),
+                    name: name.to_owned(),
+                    error,
+                })?;
 
             if dep.path.is_absolute() {
                 warn!("dependency '{}' uses an absolute path", name);
@@ -522,7 +1221,7 @@ impl Manifest {
             //
             // parent = { path = ".." }
 
-            let package = Package::load(&path)?; // TODO: error context
+            let package = Package::load(&path, diagnostics)?; // TODO: error context
 
             if package.manifest.name != *name {
                 warn!("dependency '{}' actually has name '{}'", name, &package.manifest.name)
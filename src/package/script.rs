@@ -1,8 +1,9 @@
 use crate::prelude::*;
+use crate::diagnostics::{Diagnostics, Diagnostic, Span, Code};
 use super::id::{self, Identify};
-use super::{SpriteMap, SpriteId, ActorMap, ActorId, TextMap, TextId};
-use std::collections::VecDeque;
+use super::{Sprite, SpriteMap, SpriteId, PlayerSpriteMap, PlayerSpriteId, ActorMap, ActorId, TextMap, TextId};
 use regex::{Regex, Captures};
+use std::collections::HashMap;
 
 /// A script patch file, e.g. *.bscr, *.mscr, *.str.
 /// This interface allows you to read a script file, process it, and then output it to a file.
@@ -16,6 +17,10 @@ pub struct Script {
     pub blocks: Vec<Block>,
 
     src_pkg_name: String,
+
+    /// Where the script was loaded from, and its original source code.
+    src_path: PathBuf,
+    source: String,
 }
 
 impl Script {
@@ -24,45 +29,43 @@ impl Script {
         let source = fs::read_to_string(&path)
             .with_context(|| format!("script file not found: {}", &path.display()))?;
 
-        // Ignore comments in the source string.
-        // FIXME: line numbers are not representative of the source if it has comments
-        let source = {
-            let mut string = String::new();
+        // Ignore comments in the source, keeping track of which line each line came from.
+        // Lines that were entirely comment are dropped, so they don't end blocks.
+        let lines = {
+            let mut lines = Vec::new();
             let mut in_block_comment = false;
-            let mut in_line_comment = false;
-            let mut prev_ch = '_';
 
-            for ch in source.chars() {
-                if in_block_comment {
-                    if ch == '/' && prev_ch == '%' {
-                        in_block_comment = false;
-                    }
-                } else if in_line_comment {
-                    if ch == '\n' {
-                        in_line_comment = false;
-                    }
-                } else if ch == '\r' {
-                    // Ignore carriage returns.
-                } else if ch == '%' {
-                    if prev_ch == '/' {
-                        string.pop();
-                        in_block_comment = true;
-                    } else {
-                        in_line_comment = true;
+            for (line_idx, source_line) in source.lines().enumerate() {
+                let mut line = String::new();
+                let mut had_comment = in_block_comment;
+                let mut chars = source_line.chars().peekable();
 
-                        if prev_ch != '\n' {
-                            string.push('\n');
+                while let Some(ch) = chars.next() {
+                    if in_block_comment {
+                        if ch == '%' && chars.peek() == Some(&'/') {
+                            chars.next();
+                            in_block_comment = false;
                         }
+                    } else if ch == '/' && chars.peek() == Some(&'%') {
+                        chars.next();
+                        in_block_comment = true;
+                        had_comment = true;
+                    } else if ch == '%' {
+                        had_comment = true;
+                        break;
+                    } else if ch != '\r' {
+                        line.push(ch);
                     }
-                } else {
-                    string.push(ch);
                 }
 
-                prev_ch = ch;
+                let line = line.trim();
+
+                if !(line.is_empty() && had_comment) {
+                    lines.push((line_idx + 1, line.to_owned()));
+                }
             }
 
-            string.push('\n');
-            string
+            lines
         };
 
         Ok(Script {
@@ -71,10 +74,8 @@ impl Script {
                 let mut blocks = Vec::new();
                 let mut line_buf = Vec::new();
 
-                for (line_idx, line) in source.lines().enumerate() {
-                    let line = line.trim();
-
-                    if line == "" {
+                for (line_no, line) in lines.into_iter().chain(std::iter::once((0, String::new()))) {
+                    if line.is_empty() {
                         // End of block.
                         if !line_buf.is_empty() {
                             // Remove all lines from `line_buf` and create a Block from them.
@@ -85,7 +86,7 @@ impl Script {
                         }
                     } else {
                         // Start/continuation of block.
-                        line_buf.push((line_idx + 1, line.to_owned()));
+                        line_buf.push((line_no, line));
                     }
                 }
 
@@ -96,11 +97,23 @@ impl Script {
                 blocks
             },
 
+            src_path: path.clone(),
+            source,
             path,
             src_pkg_name: src_pkg_name.to_owned(),
         })
     }
 
+    /// Finds where `needle` is on the given line of the original source, for diagnostics.
+    pub fn span(&self, line_no: usize, needle: &str) -> Option<Span> {
+        Span::find(&self.src_path, &self.source, line_no, needle)
+    }
+
+    /// The source code the script was loaded from.
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
     /// Writes updated sourcecode. To 'save as,' set `self.path` beforehand.
     pub fn save(&self) -> io::Result<()> {
         use std::io::Write;
@@ -123,14 +136,20 @@ impl Script {
     /// - `{Sprite:id}`
     /// - `{Sprite:id:anim}`
     /// - `{Sprite:id:anim:palette}
+    /// - `{PlayerSprite:id}`, `{PlayerSprite:id:anim}` and `{PlayerSprite:id:anim:palette}`
     /// - `{String:id}` - overloads Star Rod's
     /// - `{Actor:id}`
+    /// - `{ActorPart:id:part}` - the index of a part declared in the actor's TOML
+    ///
+    /// Expressions that fail to resolve are reported to `diagnostics` and left as-is.
     pub fn resolve_expressions(
         &mut self,
         sprites: &SpriteMap,
+        player_sprites: &PlayerSpriteMap,
         texts: &TextMap,
         actors: &ActorMap,
-    ) -> Result<(), ResolveError> {
+        diagnostics: &mut Diagnostics,
+    ) {
         use ResolveError::*;
 
         lazy_static! {
@@ -146,6 +165,18 @@ impl Script {
                 r"\{Sprite:([^:}]*):([^:}]*):([^:}]*)\}"
             ).unwrap();
 
+            static ref PLAYER_SPRITE_ID: Regex = Regex::new(
+                r"\{PlayerSprite:([^:}]*)\}"
+            ).unwrap();
+
+            static ref PLAYER_SPRITE_ID_ANIM: Regex = Regex::new(
+                r"\{PlayerSprite:([^:}]*):([^:}]*)\}"
+            ).unwrap();
+
+            static ref PLAYER_SPRITE_ID_ANIM_PALETTE: Regex = Regex::new(
+                r"\{PlayerSprite:([^:}]*):([^:}]*):([^:}]*)\}"
+            ).unwrap();
+
             static ref STRING_ID: Regex = Regex::new(
                 r"\{String:([^:}]*)\}"
             ).unwrap();
@@ -153,146 +184,78 @@ impl Script {
             static ref ACTOR_ID: Regex = Regex::new(
                 r"\{Actor:([^:}]*)\}"
             ).unwrap();
+
+            static ref ACTOR_PART: Regex = Regex::new(
+                r"\{ActorPart:([^:}]*):([^:}]*)\}"
+            ).unwrap();
         }
 
-        fn replace<F: Fn(Captures) -> Result<String, ResolveError>>(
-            regex: &Regex,
-            text: &str,
-            func: F,
-        ) -> Result<String, ResolveError> {
-            let mut new = String::with_capacity(text.len());
-            let mut last_match = 0;
-            for cap in regex.captures_iter(text) {
-                let m = cap.get(0).unwrap();
-                new.push_str(&text[last_match..m.start()]);
-                last_match = m.end();
-
-                new.push_str(&func(cap)?);
+        let src_pkg_name = &self.src_pkg_name;
+        let (src_path, source) = (&self.src_path, &self.source);
+
+        // Finds the span of capture group `group` of the expression `g` on the given line.
+        let span = |line_no: usize, g: &Captures, group: usize| {
+            let expr = g.get(0).unwrap();
+            let part = g.get(group).unwrap();
+
+            match Span::find(src_path, source, line_no, expr.as_str()) {
+                Some(mut span) => {
+                    span.column += expr.as_str()[..part.start() - expr.start()].chars().count();
+                    span.length = part.as_str().chars().count();
+                    span
+                },
+                None => Span::new(src_path, line_no, 1, 0),
             }
-            new.push_str(&text[last_match..]);
-            Ok(new)
-        }
+        };
 
-        let mut new = VecDeque::new();
-        for block in &self.blocks {
-            new.push_front(block.lines.iter().map(|(line_no, line)| {
+        for block in &mut self.blocks {
+            for (line_no, line) in &mut block.lines {
                 let line_no = *line_no;
 
-                // {Sprite:id}
-                let mut line = replace(&SPRITE_ID, line, |g| {
-                    let id = g.get(1).unwrap().as_str();
-                    let id = SpriteId::parse(id, &self.src_pkg_name)
-                        .map_err(|err| IdParseError {
-                            path: self.path.clone(),
-                            line_no,
-                            id_string: id.to_string(),
-                            parse_error: err,
-                        })?;
-
-                    match id.resolve(sprites) {
-                        Some(sprite) => Ok(format!("{:02X}",
-                            sprite
-                                .assembled_index()
-                                .expect("unassembled sprite")
-                        )),
-                        None => Err(UnknownSprite { path: self.path.clone(), line_no, id }),
-                    }
-                })?;
-
-                // {Sprite:id:anim}
-                line = replace(&SPRITE_ID_ANIM, &line, |g| {
-                    let id = g.get(1).unwrap().as_str();
-                    let id = SpriteId::parse(id, &self.src_pkg_name)
-                        .map_err(|err| IdParseError {
-                            path: self.path.clone(),
-                            line_no,
-                            id_string: id.to_string(),
-                            parse_error: err,
-                        })?;
-
-                    let anim = g.get(2).unwrap().as_str();
-
-                    match id.resolve(sprites) {
-                        Some(sprite) => Ok(format!(
-                            "00{index:02X}00{anim:02X}",
-                            index = sprite
-                                .assembled_index()
-                                .expect("unassembled sprite"),
-                            anim = sprite.animation_by_name(anim)
-                                .ok_or_else(|| SpriteLacksAnimation {
-                                    path: self.path.clone(),
-                                    line_no,
-                                    id,
-                                    animation: anim.to_string(),
-                                })?
-                        )),
-                        None => Err(UnknownSprite { path: self.path.clone(), line_no, id }),
-                    }
-                })?;
-
-                // {Sprite:id:anim:palette}
-                line = replace(&SPRITE_ID_ANIM_PALETTE, &line, |g| {
-                    let id = g.get(1).unwrap().as_str();
-                    let id = SpriteId::parse(id, &self.src_pkg_name)
-                        .map_err(|err| IdParseError {
-                            path: self.path.clone(),
-                            line_no,
-                            id_string: id.to_string(),
-                            parse_error: err,
-                        })?;
-
-                    let anim = g.get(2).unwrap().as_str();
-                    let palette = g.get(3).unwrap().as_str();
-
-                    match id.resolve(sprites) {
-                        Some(sprite) => Ok(format!(
-                            "00{index:02X}{palette:02X}{anim:02X}",
-                            index = sprite
-                                .assembled_index()
-                                .expect("unassembled sprite"),
-                            anim = sprite.animation_by_name(anim)
-                                .ok_or_else(|| SpriteLacksAnimation {
-                                    path: self.path.clone(),
-                                    line_no,
-                                    id: id.clone(),
-                                    animation: anim.to_string(),
-                                })?,
-                            palette = sprite.palette_by_name(palette)
-                                .ok_or_else(|| SpriteLacksPalette {
-                                    path: self.path.clone(),
-                                    line_no,
-                                    id,
-                                    palette: palette.to_string(),
-                                })?
-                        )),
-                        None => Err(UnknownSprite { path: self.path.clone(), line_no, id }),
-                    }
-                })?;
+                let span = |g: &Captures, group: usize| span(line_no, g, group);
+
+                *line = replace_sprites(
+                    [&SPRITE_ID, &SPRITE_ID_ANIM, &SPRITE_ID_ANIM_PALETTE],
+                    line,
+                    sprites,
+                    src_pkg_name,
+                    |span, id| UnknownSprite { span, id },
+                    &span,
+                    diagnostics,
+                );
+
+                *line = replace_sprites(
+                    [&PLAYER_SPRITE_ID, &PLAYER_SPRITE_ID_ANIM, &PLAYER_SPRITE_ID_ANIM_PALETTE],
+                    line,
+                    player_sprites,
+                    src_pkg_name,
+                    |span, id| UnknownPlayerSprite { span, id },
+                    &span,
+                    diagnostics,
+                );
 
                 // {String:id}
-                line = replace(&STRING_ID, &line, |g| {
+                *line = replace(&STRING_ID, line, diagnostics, |g| {
                     let id = g.get(1).unwrap().as_str();
-                    let id = TextId::parse(id, &self.src_pkg_name)
+                    let id = TextId::parse(id, src_pkg_name)
                         .map_err(|err| IdParseError {
-                            path: self.path.clone(),
-                            line_no,
+                            span: span(&g, 1),
                             id_string: id.to_string(),
                             parse_error: err,
                         })?;
 
                     match id.resolve(texts) {
                         Some(text) => Ok(text.assembled_hex_id().expect("unassembled text")),
-                        None => Err(UnknownText { path: self.path.clone(), line_no, id }),
+                        None => Err(UnknownText { span: span(&g, 1), id }.into()),
                     }
-                })?;
+                });
 
                 // {Actor:id}
-                line = replace(&ACTOR_ID, &line, |g| {
+                *line = replace(&ACTOR_ID, line, diagnostics, |g| {
                     let id = g.get(1).unwrap().as_str();
-                    let id = ActorId::parse(id, &self.src_pkg_name)
+                    let id = ActorId::parse(id, src_pkg_name)
                         .map_err(|err| IdParseError {
-                            path: self.path.clone(),
-                            line_no,
+                            span: span(&g, 1),
                             id_string: id.to_string(),
                             parse_error: err,
                         })?;
@@ -303,70 +266,273 @@ impl Script {
                                 .assembled_index()
                                 .expect("unassembled actor")
                         )),
-                        None => Err(UnknownActor { path: self.path.clone(), line_no, id }),
+                        None => Err(UnknownActor { span: span(&g, 1), id }.into()),
                     }
-                })?;
+                });
 
-                Ok((line_no, line))
-            }).collect::<Result<Vec<_>, ResolveError>>()?);
-        }
+                // {ActorPart:id:part}
+                *line = replace(&ACTOR_PART, line, diagnostics, |g| {
+                    let (id, part) = (g.get(1).unwrap().as_str(), g.get(2).unwrap().as_str());
+                    let id = ActorId::parse(id, src_pkg_name)
+                        .map_err(|err| IdParseError {
+                            span: span(&g, 1),
+                            id_string: id.to_string(),
+                            parse_error: err,
+                        })?;
 
-        for mut block in self.blocks.iter_mut() {
-            block.lines = new.pop_back().unwrap();
+                    let actor = id.resolve(actors)
+                        .ok_or_else(|| UnknownActor { span: span(&g, 1), id: id.clone() })?;
+
+                    match actor.part_index(part) {
+                        Some(index) => Ok(format!("{:02X}", index)),
+                        None => Err(ActorLacksPart {
+                            span: span(&g, 2),
+                            actor: format!("{:?}", id),
+                            part: part.to_owned(),
+                            parts: actor.part_names(),
+                        }.into()),
+                    }
+                });
+            }
         }
+    }
+}
 
-        Ok(())
+/// Replaces every match of `regex` in `text` with the result of `func`. Matches that
+/// `func` fails on are reported and left untouched.
+fn replace<F: Fn(Captures) -> Result<String, Diagnostic>>(
+    regex: &Regex,
+    text: &str,
+    diagnostics: &mut Diagnostics,
+    func: F,
+) -> String {
+    let mut new = String::with_capacity(text.len());
+    let mut last_match = 0;
+    for cap in regex.captures_iter(text) {
+        let m = cap.get(0).unwrap();
+        new.push_str(&text[last_match..m.start()]);
+        last_match = m.end();
+
+        match func(cap) {
+            Ok(replacement) => new.push_str(&replacement),
+            Err(error) => {
+                diagnostics.report(error);
+                new.push_str(m.as_str());
+            },
+        }
     }
+    new.push_str(&text[last_match..]);
+    new
+}
+
+/// Replaces `{Sprite:id}`, `{Sprite:id:anim}` and `{Sprite:id:anim:palette}` style expressions,
+/// matched by `regexes` in that order, with sprites from `sprites`.
+fn replace_sprites<I: Identify<T = Sprite> + fmt::Debug>(
+    regexes: [&Regex; 3],
+    line: &str,
+    sprites: &HashMap<I, Sprite>,
+    src_pkg_name: &str,
+    unknown: fn(Span, I) -> ResolveError,
+    span: &dyn Fn(&Captures, usize) -> Span,
+    diagnostics: &mut Diagnostics,
+) -> String {
+    use ResolveError::*;
+
+    let resolve = |g: &Captures| -> Result<(&Sprite, String), Diagnostic> {
+        let id = g.get(1).unwrap().as_str();
+        let id = I::parse(id, src_pkg_name)
+            .map_err(|err| IdParseError {
+                span: span(g, 1),
+                id_string: id.to_string(),
+                parse_error: err,
+            })?;
+        let name = format!("{:?}", id);
+
+        match id.resolve(sprites) {
+            Some(sprite) => Ok((sprite, name)),
+            None => Err(unknown(span(g, 1), id).into()),
+        }
+    };
+
+    let index = |sprite: &Sprite| sprite.assembled_index().expect("unassembled sprite");
+
+    let animation = |g: &Captures, sprite: &Sprite, name: &str| {
+        let anim = g.get(2).unwrap().as_str();
+        sprite.animation_by_name(anim)
+            .ok_or_else(|| SpriteLacksAnimation {
+                span: span(g, 2),
+                sprite: name.to_owned(),
+                animation: anim.to_string(),
+                animations: sprite.animation_names(),
+            })
+    };
+
+    // {Sprite:id}
+    let line = replace(regexes[0], line, diagnostics, |g| {
+        let (sprite, _) = resolve(&g)?;
+        Ok(format!("{:02X}", index(sprite)))
+    });
+
+    // {Sprite:id:anim}
+    let line = replace(regexes[1], &line, diagnostics, |g| {
+        let (sprite, name) = resolve(&g)?;
+        Ok(format!(
+            "00{index:02X}00{anim:02X}",
+            index = index(sprite),
+            anim = animation(&g, sprite, &name)?,
+        ))
+    });
+
+    // {Sprite:id:anim:palette}
+    replace(regexes[2], &line, diagnostics, |g| {
+        let (sprite, name) = resolve(&g)?;
+        let palette = g.get(3).unwrap().as_str();
+
+        Ok(format!(
+            "00{index:02X}{palette:02X}{anim:02X}",
+            index = index(sprite),
+            anim = animation(&g, sprite, &name)?,
+            palette = sprite.palette_by_name(palette)
+                .ok_or_else(|| SpriteLacksPalette {
+                    span: span(&g, 3),
+                    sprite: name,
+                    palette: palette.to_string(),
+                    palettes: sprite.palette_names(),
+                })?,
+        ))
+    })
 }
 
 #[derive(Error, Debug)]
 pub enum ResolveError {
-    #[error("{path}:{line_no}: failed to parse id '{id_string}': {parse_error}")]
+    #[error("failed to parse id '{id_string}'")]
     IdParseError {
-        path: PathBuf,
-        line_no: usize,
+        span: Span,
         id_string: String,
 
         #[source]
         parse_error: id::ParseError,
     },
 
-    #[error("{path}:{line_no}: unknown sprite: {id:#?}")]
+    #[error("unknown sprite: {id:#?}")]
     UnknownSprite {
-        path: PathBuf,
-        line_no: usize,
+        span: Span,
         id: SpriteId,
     },
 
-    #[error("{path}:{line_no}: sprite {id:#?} has no animation '{animation}'")]
+    #[error("unknown player sprite: {id:#?}")]
+    UnknownPlayerSprite {
+        span: Span,
+        id: PlayerSpriteId,
+    },
+
+    #[error("sprite {sprite} has no animation '{animation}'")]
     SpriteLacksAnimation {
-        path: PathBuf,
-        line_no: usize,
-        id: SpriteId,
+        span: Span,
+        sprite: String,
         animation: String,
+        animations: Vec<String>,
     },
 
-    #[error("{path}:{line_no}: sprite {id:#?} has no palette '{palette}'")]
+    #[error("sprite {sprite} has no palette '{palette}'")]
     SpriteLacksPalette {
-        path: PathBuf,
-        line_no: usize,
-        id: SpriteId,
+        span: Span,
+        sprite: String,
         palette: String,
+        palettes: Vec<String>,
     },
 
-    #[error("{path}:{line_no}: unknown string: {id:#?}")]
+    #[error("unknown string: {id:#?}")]
     UnknownText {
-        path: PathBuf,
-        line_no: usize,
+        span: Span,
         id: TextId,
     },
 
-    #[error("{path}:{line_no}: unknown actor: {id:#?}")]
+    #[error("unknown actor: {id:#?}")]
     UnknownActor {
-        path: PathBuf,
-        line_no: usize,
+        span: Span,
         id: ActorId,
     },
+
+    #[error("actor {actor} has no field '{field}'")]
+    ActorLacksField {
+        span: Span,
+        actor: String,
+        field: String,
+        fields: Vec<String>,
+    },
+
+    #[error("actor {actor} has no part '{part}'")]
+    ActorLacksPart {
+        span: Span,
+        actor: String,
+        part: String,
+        parts: Vec<String>,
+    },
+
+    #[error("string {id:#?} includes itself")]
+    RecursiveText {
+        span: Span,
+        id: TextId,
+    },
+}
+
+impl Code for ResolveError {
+    fn code(&self) -> Option<&'static str> {
+        use ResolveError::*;
+
+        Some(match self {
+            IdParseError { .. } => "SP0201",
+            UnknownSprite { .. } => "SP0202",
+            SpriteLacksAnimation { .. } => "SP0203",
+            SpriteLacksPalette { .. } => "SP0204",
+            UnknownText { .. } => "SP0205",
+            UnknownActor { .. } => "SP0206",
+            UnknownPlayerSprite { .. } => "SP0207",
+            ActorLacksField { .. } => "SP0208",
+            RecursiveText { .. } => "SP0209",
+            ActorLacksPart { .. } => "SP0210",
+        })
+    }
+}
+
+impl From<ResolveError> for Diagnostic {
+    fn from(error: ResolveError) -> Diagnostic {
+        use ResolveError::*;
+
+        let diagnostic = Diagnostic::error(&error).with_code(error.code());
+
+        match error {
+            IdParseError { span, parse_error, .. } =>
+                diagnostic.with_label(Some(span), parse_error),
+            UnknownSprite { span, .. } =>
+                diagnostic.with_label(Some(span), "no such sprite"),
+            UnknownPlayerSprite { span, .. } =>
+                diagnostic.with_label(Some(span), "no such player sprite"),
+            SpriteLacksAnimation { span, sprite, animations, .. } =>
+                diagnostic.with_label(Some(span), "unknown animation")
+                    .with_help(format!("sprite {} has animations: {}", sprite, animations.join(", "))),
+            SpriteLacksPalette { span, sprite, palettes, .. } =>
+                diagnostic.with_label(Some(span), "unknown palette")
+                    .with_help(format!("sprite {} has palettes: {}", sprite, palettes.join(", "))),
+            UnknownText { span, .. } =>
+                diagnostic.with_label(Some(span), "no such string"),
+            UnknownActor { span, .. } =>
+                diagnostic.with_label(Some(span), "no such actor"),
+            ActorLacksField { span, actor, fields, .. } =>
+                diagnostic.with_label(Some(span), "unknown field")
+                    .with_help(format!("actor {} has fields: {}", actor, fields.join(", "))),
+            ActorLacksPart { span, actor, parts, .. } if parts.is_empty() =>
+                diagnostic.with_label(Some(span), "unknown part")
+                    .with_help(format!("actor {} declares no parts; add `[[parts]]` to its TOML", actor)),
+            ActorLacksPart { span, actor, parts, .. } =>
+                diagnostic.with_label(Some(span), "unknown part")
+                    .with_help(format!("actor {} has parts: {}", actor, parts.join(", "))),
+            RecursiveText { span, .. } =>
+                diagnostic.with_label(Some(span), "included here")
+                    .with_note("strings can't include themselves, directly or through other strings"),
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -386,7 +552,7 @@ impl Block {
             kind: {
                 lazy_static! {
                     static ref STRING_NAMED: Regex = Regex::new(
-                        r"#string:([0-9A-F]{2}):\((.*)\)"
+                        r"#string:(?:([0-9A-F]{2}|auto):)?\((.*)\)"
                     ).unwrap();
                 }
 
@@ -396,9 +562,12 @@ impl Block {
                     trace!("STRING_NAMED {:?}", g);
 
                     BlockKind::StringNamed {
-                        section: u8::from_str_radix(g.get(1).unwrap().as_str(), 16)
-                            .with_context(||
-                                format!("bad string section index on line {}", line_no))?,
+                        section: match g.get(1).map(|m| m.as_str()) {
+                            None | Some("auto") => None,
+                            Some(section) => Some(u8::from_str_radix(section, 16)
+                                .with_context(||
+                                    format!("bad string section index on line {}", line_no))?),
+                        },
                         name: g.get(2).unwrap().as_str().to_owned(),
                     }
                 } else {
@@ -420,9 +589,9 @@ impl Block {
 
 #[derive(Debug)]
 pub enum BlockKind {
-    /// `#string:XX:(Name)`
+    /// `#string:XX:(Name)`, or `#string:(Name)` to have starpkg pick the section.
     StringNamed {
-        section: u8,
+        section: Option<u8>,
         name: String,
     },
 
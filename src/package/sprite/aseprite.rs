@@ -0,0 +1,387 @@
+//! Reads Aseprite (`.ase`/`.aseprite`) files. See the [file format spec].
+//!
+//! Only indexed colour mode is supported, since N64 sprites are always paletted.
+//!
+//! [file format spec]: https://github.com/aseprite/aseprite/blob/master/docs/ase-file-specs.md
+
+use crate::prelude::*;
+use super::image::{Color, IndexedImage};
+
+const FILE_MAGIC: u16 = 0xA5E0;
+const FRAME_MAGIC: u16 = 0xF1FA;
+
+const CHUNK_OLD_PALETTE: u16 = 0x0004;
+const CHUNK_LAYER: u16 = 0x2004;
+const CHUNK_CEL: u16 = 0x2005;
+const CHUNK_TAGS: u16 = 0x2018;
+const CHUNK_PALETTE: u16 = 0x2019;
+
+const COLOR_DEPTH_INDEXED: u16 = 8;
+
+#[derive(Debug)]
+pub struct Aseprite {
+    pub width: u32,
+    pub height: u32,
+    pub palette: Vec<Color>,
+    pub frames: Vec<Frame>,
+    pub tags: Vec<Tag>,
+}
+
+#[derive(Debug)]
+pub struct Frame {
+    /// How long this frame is shown for, in milliseconds.
+    pub duration: u16,
+
+    /// All visible layers flattened together.
+    pub image: IndexedImage,
+}
+
+#[derive(Debug)]
+pub struct Tag {
+    pub name: String,
+    pub from: usize,
+    pub to: usize,
+    pub direction: Direction,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Forward,
+    Reverse,
+    PingPong,
+}
+
+#[derive(Error, Debug)]
+pub enum AsepriteError {
+    #[error("not an Aseprite file")]
+    BadMagic,
+
+    #[error("unexpected end of file")]
+    UnexpectedEof,
+
+    #[error("only indexed colour mode is supported (this file is {0} bits per pixel)")]
+    NotIndexed(u16),
+
+    #[error("unable to decompress cel in frame {0}")]
+    BadCelData(usize),
+
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+struct Layer {
+    visible: bool,
+}
+
+#[derive(Clone)]
+struct Cel {
+    x: i32,
+    y: i32,
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>,
+}
+
+impl Aseprite {
+    pub fn load(path: &Path) -> Result<Aseprite, AsepriteError> {
+        Aseprite::parse(&fs::read(path)?)
+    }
+
+    pub fn parse(data: &[u8]) -> Result<Aseprite, AsepriteError> {
+        let mut r = Reader(data);
+
+        // Header.
+        r.u32()?;
+        if r.u16()? != FILE_MAGIC {
+            return Err(AsepriteError::BadMagic);
+        }
+        let frame_count = r.u16()? as usize;
+        let width = r.u16()? as u32;
+        let height = r.u16()? as u32;
+        let color_depth = r.u16()?;
+        r.skip(14)?;
+        let transparent_index = r.u8()?;
+        r.skip(3)?;
+        let color_count = r.u16()?;
+        r.skip(94)?;
+
+        if color_depth != COLOR_DEPTH_INDEXED {
+            return Err(AsepriteError::NotIndexed(color_depth));
+        }
+
+        let mut palette = vec![[0, 0, 0, 0xFF]; if color_count == 0 { 256 } else { color_count as usize }];
+        let mut has_new_palette = false;
+        let mut layers: Vec<Layer> = Vec::new();
+        let mut ancestors_visible: Vec<bool> = Vec::new(); // Indexed by layer child level.
+        let mut frame_cels: Vec<Vec<(usize, Cel)>> = Vec::with_capacity(frame_count);
+        let mut durations = Vec::with_capacity(frame_count);
+        let mut tags = Vec::new();
+
+        for frame_idx in 0..frame_count {
+            let frame_len = r.u32()? as usize;
+            let mut fr = Reader(r.take(frame_len.saturating_sub(4))?);
+
+            if fr.u16()? != FRAME_MAGIC {
+                return Err(AsepriteError::BadMagic);
+            }
+            let old_chunk_count = fr.u16()? as usize;
+            durations.push(fr.u16()?);
+            fr.skip(2)?;
+            let chunk_count = match fr.u32()? as usize {
+                0 => old_chunk_count,
+                n => n,
+            };
+
+            let mut cels = Vec::new();
+
+            for _ in 0..chunk_count {
+                let chunk_len = fr.u32()? as usize;
+                let chunk_type = fr.u16()?;
+                let mut cr = Reader(fr.take(chunk_len.saturating_sub(6))?);
+
+                match chunk_type {
+                    CHUNK_PALETTE => {
+                        has_new_palette = true;
+                        let size = cr.u32()? as usize;
+                        let first = cr.u32()? as usize;
+                        let last = cr.u32()? as usize;
+                        cr.skip(8)?;
+
+                        palette.resize(size, [0, 0, 0, 0xFF]);
+                        for idx in first..=last {
+                            let flags = cr.u16()?;
+                            let color = [cr.u8()?, cr.u8()?, cr.u8()?, cr.u8()?];
+                            if flags & 1 != 0 {
+                                cr.string()?;
+                            }
+                            if let Some(entry) = palette.get_mut(idx) {
+                                *entry = color;
+                            }
+                        }
+                    },
+                    CHUNK_OLD_PALETTE if !has_new_palette => {
+                        let mut idx = 0;
+                        for _ in 0..cr.u16()? {
+                            idx += cr.u8()? as usize;
+                            let count = match cr.u8()? {
+                                0 => 256,
+                                n => n as usize,
+                            };
+                            for _ in 0..count {
+                                let color = [cr.u8()?, cr.u8()?, cr.u8()?, 0xFF];
+                                if idx >= palette.len() {
+                                    palette.resize(idx + 1, [0, 0, 0, 0xFF]);
+                                }
+                                palette[idx] = color;
+                                idx += 1;
+                            }
+                        }
+                    },
+                    CHUNK_LAYER => {
+                        let flags = cr.u16()?;
+                        cr.skip(2)?;
+                        let level = cr.u16()?;
+
+                        // A layer is only visible if every group it is nested in is, too.
+                        ancestors_visible.truncate(level as usize);
+                        let parent_visible = ancestors_visible.last().copied().unwrap_or(true);
+                        let visible = parent_visible && flags & 1 != 0;
+                        ancestors_visible.push(visible);
+
+                        layers.push(Layer { visible });
+                    },
+                    CHUNK_CEL => {
+                        let layer = cr.u16()? as usize;
+                        let x = cr.i16()? as i32;
+                        let y = cr.i16()? as i32;
+                        cr.skip(1)?;
+                        let cel_type = cr.u16()?;
+                        cr.skip(7)?;
+
+                        let cel = match cel_type {
+                            0 | 2 => {
+                                let width = cr.u16()? as u32;
+                                let height = cr.u16()? as u32;
+                                let pixels = if cel_type == 0 {
+                                    cr.take((width * height) as usize)?.to_vec()
+                                } else {
+                                    miniz_oxide::inflate::decompress_to_vec_zlib(cr.0)
+                                        .map_err(|_| AsepriteError::BadCelData(frame_idx))?
+                                };
+                                if pixels.len() < (width * height) as usize {
+                                    return Err(AsepriteError::BadCelData(frame_idx));
+                                }
+                                Cel { x, y, width, height, pixels }
+                            },
+                            1 => {
+                                let linked_frame = cr.u16()? as usize;
+                                match frame_cels.get(linked_frame)
+                                    .and_then(|cels| cels.iter().find(|(l, _)| *l == layer))
+                                {
+                                    Some((_, cel)) => Cel { x, y, ..cel.clone() },
+                                    None => continue,
+                                }
+                            },
+                            // Tilemaps aren't useful for sprites.
+                            _ => continue,
+                        };
+
+                        cels.push((layer, cel));
+                    },
+                    CHUNK_TAGS => {
+                        let count = cr.u16()?;
+                        cr.skip(8)?;
+                        for _ in 0..count {
+                            let from = cr.u16()? as usize;
+                            let to = cr.u16()? as usize;
+                            let direction = match cr.u8()? {
+                                1 => Direction::Reverse,
+                                2 | 3 => Direction::PingPong,
+                                _ => Direction::Forward,
+                            };
+                            cr.skip(12)?;
+                            let name = cr.string()?;
+
+                            tags.push(Tag { name, from, to, direction });
+                        }
+                    },
+                    _ => {},
+                }
+            }
+
+            frame_cels.push(cels);
+        }
+
+        // The transparent colour is transparent regardless of what the palette says.
+        if let Some(color) = palette.get_mut(transparent_index as usize) {
+            color[3] = 0;
+        }
+
+        let frames = frame_cels.iter()
+            .zip(durations)
+            .map(|(cels, duration)| {
+                let mut pixels = vec![transparent_index; (width * height) as usize];
+
+                // Layers are stored bottom to top.
+                let mut cels: Vec<&(usize, Cel)> = cels.iter()
+                    .filter(|(layer, _)| layers.get(*layer).map(|l| l.visible).unwrap_or(false))
+                    .collect();
+                cels.sort_by_key(|(layer, _)| *layer);
+
+                for (_, cel) in cels {
+                    cel.draw_onto(&mut pixels, width, height, transparent_index);
+                }
+
+                Frame {
+                    duration,
+                    image: IndexedImage {
+                        width,
+                        height,
+                        pixels,
+                        palette: palette.clone(),
+                    },
+                }
+            })
+            .collect();
+
+        Ok(Aseprite {
+            width,
+            height,
+            palette,
+            frames,
+            tags,
+        })
+    }
+
+    /// The frame indices played by a tag, in order.
+    pub fn tag_frames(&self, tag: &Tag) -> Vec<usize> {
+        let forward = tag.from..=tag.to.min(self.frames.len().saturating_sub(1));
+
+        match tag.direction {
+            Direction::Forward => forward.collect(),
+            Direction::Reverse => forward.rev().collect(),
+            Direction::PingPong => {
+                let mut frames: Vec<usize> = forward.clone().collect();
+                frames.extend(forward.rev().skip(1));
+                frames.pop();
+                frames
+            },
+        }
+    }
+
+    /// This file's palette, with its first frame as the image, like Star Rod's `Palette_XX.png`s.
+    pub fn palette_image(&self) -> IndexedImage {
+        let mut image = match self.frames.first() {
+            Some(frame) => frame.image.clone(),
+            None => IndexedImage {
+                width: self.width,
+                height: self.height,
+                pixels: vec![0; (self.width * self.height) as usize],
+                palette: Vec::new(),
+            },
+        };
+        image.palette = self.palette.clone();
+        image
+    }
+}
+
+impl Cel {
+    fn draw_onto(&self, pixels: &mut [u8], width: u32, height: u32, transparent_index: u8) {
+        for cy in 0..self.height {
+            for cx in 0..self.width {
+                let x = self.x + cx as i32;
+                let y = self.y + cy as i32;
+                if x < 0 || y < 0 || x >= width as i32 || y >= height as i32 {
+                    continue;
+                }
+
+                let index = self.pixels[(cy * self.width + cx) as usize];
+                if index != transparent_index {
+                    pixels[(y as u32 * width + x as u32) as usize] = index;
+                }
+            }
+        }
+    }
+}
+
+/// Little-endian reader over a byte slice.
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], AsepriteError> {
+        if self.0.len() < len {
+            return Err(AsepriteError::UnexpectedEof);
+        }
+
+        let (head, tail) = self.0.split_at(len);
+        self.0 = tail;
+        Ok(head)
+    }
+
+    fn skip(&mut self, len: usize) -> Result<(), AsepriteError> {
+        self.take(len).map(|_| ())
+    }
+
+    fn u8(&mut self) -> Result<u8, AsepriteError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, AsepriteError> {
+        let b = self.take(2)?;
+        Ok(u16::from_le_bytes([b[0], b[1]]))
+    }
+
+    fn i16(&mut self) -> Result<i16, AsepriteError> {
+        self.u16().map(|n| n as i16)
+    }
+
+    fn u32(&mut self) -> Result<u32, AsepriteError> {
+        let b = self.take(4)?;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn string(&mut self) -> Result<String, AsepriteError> {
+        let len = self.u16()? as usize;
+        Ok(String::from_utf8_lossy(self.take(len)?).into_owned())
+    }
+}
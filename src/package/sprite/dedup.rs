@@ -0,0 +1,145 @@
+use crate::prelude::*;
+use super::Sprite;
+use super::image::IndexedImage;
+use crate::package::digest::{Digest, Sha256};
+
+/// Palettes are always stored as 16 colours, 2 bytes each.
+const PALETTE_SIZE: usize = 16 * 2;
+
+/// A raster or palette as stored in the ROM.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoredImage {
+    /// The file it was read from.
+    pub src: String,
+
+    /// Digest of the data stored, so identical images have the same digest.
+    pub digest: Digest,
+
+    /// Number of bytes stored.
+    pub size: usize,
+}
+
+impl Sprite {
+    /// Removes palettes with the same colours as an earlier palette, using the earlier one instead.
+    /// Their names still refer to it, e.g. in `{Sprite:id:anim:palette}`. If the sprite is `vanilla`,
+    /// i.e. stands in for a base game sprite, its own palettes are kept; only patches' are removed.
+    /// Returns the number of bytes saved.
+    pub(super) fn merge_duplicate_palettes(&mut self, vanilla: bool) -> usize {
+        let keys: Vec<Option<Digest>> = self.sheet.palettes.iter()
+            .map(|palette| match self.image(&palette.src) {
+                Some(Ok(image)) => Some(palette_digest(&image)),
+                _ => None, // Leave unreadable ones alone.
+            })
+            .collect();
+
+        let replacements = self.replacements(&keys, vanilla, |sprite, idx| &sprite.sheet.palettes[idx].src);
+        let saved = replacements.iter().enumerate().filter(|&(idx, &r)| r != idx).count() * PALETTE_SIZE;
+
+        if saved > 0 {
+            self.sheet.merge_palettes(&replacements);
+        }
+        saved
+    }
+
+    /// Removes rasters with the same image and palette as an earlier raster, showing the earlier
+    /// one instead. Sprites are stored separately in the ROM, so this is the only sharing possible.
+    /// Palettes should be merged first, so rasters using identical palettes are merged too. As with
+    /// palettes, a `vanilla` sprite's own rasters are kept. Returns the number of bytes saved.
+    pub(super) fn merge_duplicate_rasters(&mut self, vanilla: bool) -> usize {
+        let images: Vec<Option<IndexedImage>> = self.sheet.rasters.iter()
+            .map(|raster| self.image(&raster.src).and_then(Result::ok)) // Leave unreadable ones alone.
+            .collect();
+        let keys: Vec<Option<(usize, Digest)>> = self.sheet.rasters.iter()
+            .zip(&images)
+            .map(|(raster, image)| image.as_ref().map(|image| (raster.palette, raster_digest(image))))
+            .collect();
+
+        let replacements = self.replacements(&keys, vanilla, |sprite, idx| &sprite.sheet.rasters[idx].src);
+        let saved = replacements.iter().enumerate()
+            .filter(|&(idx, &r)| r != idx)
+            .map(|(idx, _)| images[idx].as_ref().map(raster_size).unwrap_or(0))
+            .sum();
+
+        if saved > 0 {
+            self.sheet.merge_rasters(&replacements);
+        }
+        saved
+    }
+
+    /// For each key, the index of the first item with the same key, which can replace it.
+    fn replacements<K: PartialEq>(
+        &self,
+        keys: &[Option<K>],
+        vanilla: bool,
+        src: impl Fn(&Sprite, usize) -> &String,
+    ) -> Vec<usize> {
+        let mut replacements = Vec::with_capacity(keys.len());
+        for (idx, key) in keys.iter().enumerate() {
+            // The base game refers to its own palettes and rasters by index, so they must stay put.
+            if vanilla && !self.is_patch_file(src(self, idx)) {
+                replacements.push(idx);
+                continue;
+            }
+
+            // The first item with a key is always kept, so can replace the rest.
+            let earlier = key.as_ref()
+                .and_then(|key| keys[..idx].iter().position(|k| k.as_ref() == Some(key)));
+            match earlier {
+                Some(earlier) => {
+                    debug!("sprite '{}' uses {} instead of identical {}",
+                        self.name(), src(self, earlier), src(self, idx));
+                    replacements.push(earlier);
+                },
+                None => replacements.push(idx),
+            }
+        }
+        replacements
+    }
+
+    /// The rasters this sprite stores, in order. Missing and unreadable images are skipped.
+    pub fn rasters(&self) -> Vec<StoredImage> {
+        self.sheet.rasters.iter()
+            .filter_map(|raster| match self.image(&raster.src) {
+                Some(Ok(image)) => Some(StoredImage {
+                    src: raster.src.clone(),
+                    digest: raster_digest(&image),
+                    size: raster_size(&image),
+                }),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// The palettes this sprite stores, in order. Missing and unreadable images are skipped.
+    pub fn palettes(&self) -> Vec<StoredImage> {
+        self.sheet.palettes.iter()
+            .filter_map(|palette| match self.image(&palette.src) {
+                Some(Ok(image)) => Some(StoredImage {
+                    src: palette.src.clone(),
+                    digest: palette_digest(&image),
+                    size: PALETTE_SIZE,
+                }),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+/// Rasters are stored at 4 bits per pixel.
+fn raster_size(image: &IndexedImage) -> usize {
+    (image.width * image.height) as usize / 2
+}
+
+/// Only the pixels of a raster are stored; its colours come from the sprite's palettes.
+fn raster_digest(image: &IndexedImage) -> Digest {
+    let mut sha = Sha256::new();
+    sha.update(&image.width.to_le_bytes());
+    sha.update(&image.height.to_le_bytes());
+    sha.update(&image.pixels);
+    sha.finish()
+}
+
+/// Only the colours of a palette are stored.
+fn palette_digest(image: &IndexedImage) -> Digest {
+    Sha256::digest(&image.palette.concat())
+}
@@ -0,0 +1,135 @@
+use crate::prelude::*;
+
+/// An RGBA colour.
+pub type Color = [u8; 4];
+
+/// A colour-indexed (paletted) image, the only kind the N64 sprite format can hold.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IndexedImage {
+    pub width: u32,
+    pub height: u32,
+
+    /// One palette index per pixel, row by row.
+    pub pixels: Vec<u8>,
+    pub palette: Vec<Color>,
+}
+
+impl IndexedImage {
+    pub fn read_png(path: &Path) -> Result<IndexedImage, ReadPngError> {
+        let file = fs::File::open(path).map_err(png::DecodingError::from)?;
+        let mut decoder = png::Decoder::new(file);
+        decoder.set_transformations(png::Transformations::IDENTITY);
+
+        let mut reader = decoder.read_info()?;
+        let color_type = reader.info().color_type;
+        if color_type != png::ColorType::Indexed {
+            return Err(ReadPngError::NotIndexed(color_type));
+        }
+
+        let mut data = vec![0; reader.output_buffer_size()];
+        let frame = reader.next_frame(&mut data)?;
+        data.truncate(frame.buffer_size());
+
+        // Unpack pixels narrower than a byte, e.g. 4-bit indices.
+        let bits = frame.bit_depth as usize;
+        let width = frame.width as usize;
+        let pixels = if bits == 8 {
+            data
+        } else {
+            let per_byte = 8 / bits;
+            let mask = (1 << bits) - 1;
+
+            data.chunks(frame.line_size)
+                .flat_map(|row| (0..width).map(move |x| {
+                    let shift = 8 - bits * (x % per_byte + 1);
+                    (row[x / per_byte] >> shift) & mask
+                }))
+                .collect()
+        };
+
+        let info = reader.info();
+        let rgb = info.palette.as_deref().unwrap_or_default();
+        let alpha = info.trns.as_deref().unwrap_or_default();
+        let palette = rgb.chunks(3)
+            .enumerate()
+            .map(|(idx, c)| [c[0], c[1], c[2], alpha.get(idx).copied().unwrap_or(0xFF)])
+            .collect();
+
+        Ok(IndexedImage {
+            width: info.width,
+            height: info.height,
+            pixels,
+            palette,
+        })
+    }
+
+    /// The highest palette index used by any pixel.
+    pub fn max_index(&self) -> Option<u8> {
+        self.pixels.iter().copied().max()
+    }
+
+    /// Encodes this image as an 8-bit indexed PNG, as Star Rod expects.
+    pub fn to_png(&self) -> Result<Vec<u8>, png::EncodingError> {
+        let mut png = Vec::new();
+
+        let mut encoder = png::Encoder::new(&mut png, self.width, self.height);
+        encoder.set_color(png::ColorType::Indexed);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.set_palette(self.palette.iter().flat_map(|c| c[..3].to_vec()).collect::<Vec<u8>>());
+        encoder.set_trns(self.palette.iter().map(|c| c[3]).collect::<Vec<u8>>());
+
+        encoder.write_header()?.write_image_data(&self.pixels)?;
+
+        Ok(png)
+    }
+}
+
+/// Rotates the hue of a colour by the given number of degrees, keeping its saturation, lightness
+/// and alpha.
+pub fn shift_hue(color: Color, degrees: f32) -> Color {
+    if degrees % 360.0 == 0.0 {
+        return color;
+    }
+
+    let [r, g, b, a] = color;
+    let (r, g, b) = (r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0);
+
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let chroma = max - min;
+    if chroma == 0.0 {
+        return color; // Grey.
+    }
+
+    let hue = if max == r {
+        (g - b) / chroma
+    } else if max == g {
+        (b - r) / chroma + 2.0
+    } else {
+        (r - g) / chroma + 4.0
+    };
+    let hue = (hue * 60.0 + degrees).rem_euclid(360.0) / 60.0;
+
+    // Back to RGB, keeping the same chroma and minimum.
+    let x = chroma * (1.0 - (hue % 2.0 - 1.0).abs());
+    let (r, g, b) = match hue as u32 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+    let channel = |c: f32| ((c + min) * 255.0).round() as u8;
+
+    [channel(r), channel(g), channel(b), a]
+}
+
+#[derive(Error, Debug)]
+pub enum ReadPngError {
+    #[error("not a colour-indexed PNG (it is {0:?})")]
+    NotIndexed(png::ColorType),
+
+    #[error(transparent)]
+    Decoding(#[from] png::DecodingError),
+}
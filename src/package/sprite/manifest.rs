@@ -0,0 +1,529 @@
+use crate::prelude::*;
+use crate::diagnostics::Span;
+use super::SpriteLoadError;
+use super::aseprite::Aseprite;
+use super::image::{self, Color, IndexedImage};
+use super::sheet::{self, SpriteSheet};
+
+use std::collections::BTreeMap;
+
+/// A `sprite.toml` file, describing a sprite built from plain PNG frames. We generate the
+/// `SpriteSheet.xml` that Star Rod expects from this at assembly time.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+    /// Imports rasters, animations and a palette from an Aseprite file.
+    aseprite: Option<AsepriteImport>,
+
+    #[serde(default, rename = "palette")]
+    palettes: Vec<Palette>,
+
+    #[serde(default, rename = "raster")]
+    rasters: Vec<Raster>,
+
+    #[serde(default, rename = "animation")]
+    animations: Vec<Animation>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+struct AsepriteImport {
+    src: String,
+
+    /// Name of the imported palette. Defaults to the file name of `src`, without its extension.
+    palette: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+struct Palette {
+    name: String,
+
+    /// A PNG, or an Aseprite file to take the palette of. Variants are generated as this, which
+    /// defaults to `<name>.png`.
+    #[serde(default)]
+    src: String,
+
+    /// Makes this a variant of an earlier palette, recoloured by the rules below.
+    base: Option<String>,
+
+    /// Colour indices to take from another index of the base palette.
+    #[serde(default)]
+    remap: BTreeMap<ColorIndex, usize>,
+
+    /// Degrees to rotate the hue of every colour not swapped.
+    #[serde(default)]
+    hue_shift: f32,
+
+    /// Exact colours to replace.
+    #[serde(default)]
+    swap: BTreeMap<Rgb, Rgb>,
+
+    /// Generated at assembly time as `src`, rather than copied.
+    #[serde(skip)]
+    image: Option<IndexedImage>,
+}
+
+/// A colour index, which TOML only allows as a string when it is a key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct ColorIndex(usize);
+
+/// A `#rrggbb` colour.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct Rgb([u8; 3]);
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+struct Raster {
+    /// Defaults to the file name of `src`, without its extension.
+    name: Option<String>,
+    src: String,
+
+    /// Defaults to the first palette.
+    palette: Option<String>,
+
+    /// Generated at assembly time as `src`, rather than copied.
+    #[serde(skip)]
+    image: Option<IndexedImage>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+struct Animation {
+    name: String,
+
+    /// Whether to play again from the first frame after the last, or hold the last frame.
+    #[serde(default = "default_loop", rename = "loop")]
+    looping: bool,
+
+    frames: Vec<Frame>,
+}
+
+fn default_loop() -> bool {
+    true
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+struct Frame {
+    raster: String,
+
+    /// How long to show this frame for, in game frames (1/30ths of a second).
+    duration: u16,
+
+    /// Position of the raster relative to the sprite's origin.
+    offset: Option<[i16; 3]>,
+
+    /// Switches palette before showing this frame.
+    palette: Option<String>,
+}
+
+/// The largest value a sprite animation command can hold.
+const MAX_COMMAND_VALUE: u16 = 0xFFF;
+
+impl Manifest {
+    pub fn load(path: &Path) -> Result<Manifest, SpriteLoadError> {
+        let source = fs::read_to_string(path)
+            .map_err(SpriteLoadError::UnreadableManifest)?;
+        let mut manifest: Manifest = toml::from_str(&source)
+            .map_err(|error| SpriteLoadError::MalformedManifest {
+                path: path.to_owned(),
+                error,
+            })?;
+
+        let dir = path.parent().unwrap();
+        manifest.import_aseprite(dir)?;
+
+        let malformed = |error| SpriteLoadError::MalformedManifest {
+            path: path.to_owned(),
+            error,
+        };
+
+        if manifest.palettes.is_empty() {
+            return Err(malformed(serde::de::Error::missing_field("palette")));
+        }
+
+        for palette in &manifest.palettes {
+            if palette.base.is_none() && palette.src.is_empty() {
+                return Err(malformed(serde::de::Error::missing_field("src")));
+            }
+
+            if palette.base.is_none() && palette.has_rules() {
+                return Err(malformed(serde::de::Error::custom(format!(
+                    "palette '{}' has colour rules, so needs a `base` palette to apply them to",
+                    palette.name,
+                ))));
+            }
+        }
+
+        // Check every reference up-front, so generating the XML can't fail.
+        let find = |needle: &str| Span::find_first(path, &source, needle);
+        let span = |needle: &str| find(&format!("\"{}\"", needle));
+
+        manifest.derive_palettes(dir, &find)?;
+
+        for raster in &manifest.rasters {
+            if let Some(palette) = &raster.palette {
+                manifest.check_palette(palette, &span)?;
+            }
+        }
+
+        for animation in &manifest.animations {
+            for frame in &animation.frames {
+                if manifest.raster_by_name(&frame.raster).is_none() {
+                    return Err(SpriteLoadError::UnknownRaster {
+                        span: span(&frame.raster),
+                        raster: frame.raster.clone(),
+                        rasters: manifest.rasters.iter().map(Raster::name).collect(),
+                    });
+                }
+
+                if let Some(palette) = &frame.palette {
+                    manifest.check_palette(palette, &span)?;
+                }
+
+                if frame.duration == 0 || frame.duration > MAX_COMMAND_VALUE {
+                    return Err(SpriteLoadError::BadFrameDuration {
+                        span: Span::find_first(path, &source, &format!("duration = {}", frame.duration)),
+                        animation: animation.name.clone(),
+                        duration: frame.duration,
+                    });
+                }
+            }
+        }
+
+        Ok(manifest)
+    }
+
+    /// Replaces Aseprite sources with the rasters, palettes and animations they contain.
+    fn import_aseprite(&mut self, dir: &Path) -> Result<(), SpriteLoadError> {
+        let load = |src: &str| {
+            let path = dir.join(src);
+            Aseprite::load(&path)
+                .map_err(|error| SpriteLoadError::BadAseprite { path, error })
+        };
+
+        for palette in &mut self.palettes {
+            if is_aseprite(&palette.src) {
+                palette.image = Some(load(&palette.src)?.palette_image());
+                palette.src = format!("{}.png", file_stem(&palette.src));
+            }
+        }
+
+        let import = match self.aseprite.take() {
+            Some(import) => import,
+            None => return Ok(()),
+        };
+
+        let ase = load(&import.src)?;
+        let stem = file_stem(&import.src);
+        let palette_name = import.palette.unwrap_or_else(|| stem.clone());
+
+        // The imported palette comes first, so it is the default for every raster.
+        self.palettes.insert(0, Palette {
+            name: palette_name.clone(),
+            src: format!("{}.png", stem),
+            image: Some(ase.palette_image()),
+            ..Palette::default()
+        });
+
+        let raster_names: Vec<String> = (1..=ase.frames.len())
+            .map(|n| format!("{}_{}", stem, n))
+            .collect();
+
+        for (frame, name) in ase.frames.iter().zip(&raster_names) {
+            self.rasters.push(Raster {
+                name: Some(name.clone()),
+                src: format!("{}.png", name),
+                palette: Some(palette_name.clone()),
+                image: Some(frame.image.clone()),
+            });
+        }
+
+        let to_frame = |idx: usize| Frame {
+            raster: raster_names[idx].clone(),
+            duration: ms_to_frames(ase.frames[idx].duration),
+            offset: None,
+            palette: None,
+        };
+
+        if ase.tags.is_empty() {
+            self.animations.push(Animation {
+                name: stem,
+                looping: true,
+                frames: (0..ase.frames.len()).map(to_frame).collect(),
+            });
+        } else {
+            for tag in &ase.tags {
+                self.animations.push(Animation {
+                    name: tag.name.clone(),
+                    looping: true,
+                    frames: ase.tag_frames(tag).into_iter().map(to_frame).collect(),
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Generates the image of each palette variant from its base palette.
+    fn derive_palettes(&mut self, dir: &Path, find: &dyn Fn(&str) -> Option<Span>)
+        -> Result<(), SpriteLoadError>
+    {
+        let span = |needle: &str| find(&format!("\"{}\"", needle));
+
+        for idx in 0..self.palettes.len() {
+            let base_name = match &self.palettes[idx].base {
+                Some(base) => base.clone(),
+                None => continue,
+            };
+
+            // Bases must come first, which also rules out cycles.
+            let earlier = &self.palettes[..idx];
+            let base = match earlier.iter().find(|p| p.name == base_name) {
+                Some(base) => base,
+                None => return Err(SpriteLoadError::UnknownPalette {
+                    span: span(&base_name),
+                    palette: base_name,
+                    palettes: earlier.iter().map(|p| p.name.clone()).collect(),
+                }),
+            };
+
+            let base_image = match &base.image {
+                Some(image) => image.clone(),
+                None => IndexedImage::read_png(&dir.join(&base.src))
+                    .map_err(|error| SpriteLoadError::BadImage {
+                        span: span(&base.src),
+                        src: base.src.clone(),
+                        animations: Vec::new(),
+                        error: Box::new(error),
+                    })?,
+            };
+
+            let palette = &self.palettes[idx];
+            let colors = palette.derive(&base_image.palette, &base_name, find)?;
+
+            let palette = &mut self.palettes[idx];
+            if palette.src.is_empty() {
+                palette.src = format!("{}.png", palette.name);
+            }
+            palette.image = Some(IndexedImage {
+                palette: colors,
+                ..base_image
+            });
+        }
+
+        Ok(())
+    }
+
+    fn check_palette(&self, name: &str, span: &dyn Fn(&str) -> Option<Span>)
+        -> Result<(), SpriteLoadError>
+    {
+        match self.palette_by_name(name) {
+            Some(_) => Ok(()),
+            None => Err(SpriteLoadError::UnknownPalette {
+                span: span(name),
+                palette: name.to_owned(),
+                palettes: self.palette_names(),
+            }),
+        }
+    }
+
+    fn palette_names(&self) -> Vec<String> {
+        self.palettes.iter().map(|p| p.name.clone()).collect()
+    }
+
+    fn palette_by_name(&self, name: &str) -> Option<usize> {
+        self.palettes.iter().position(|p| p.name == name)
+    }
+
+    fn raster_by_name(&self, name: &str) -> Option<usize> {
+        self.rasters.iter().position(|r| r.name() == name)
+    }
+
+    /// Generates the equivalent Star Rod `SpriteSheet.xml`.
+    pub fn to_sheet(&self) -> SpriteSheet {
+        SpriteSheet {
+            palettes: self.palettes.iter()
+                .map(|palette| sheet::Palette {
+                    name: palette.name.clone(),
+                    src: palette.src.clone(),
+                })
+                .collect(),
+            rasters: self.rasters.iter()
+                .map(|raster| sheet::Raster {
+                    palette: raster.palette.as_ref()
+                        .and_then(|name| self.palette_by_name(name))
+                        .unwrap_or(0),
+                    src: raster.src.clone(),
+                })
+                .collect(),
+            animations: self.animations.iter()
+                .map(|animation| sheet::Animation {
+                    name: animation.name.clone(),
+                    components: vec![sheet::Component {
+                        name: None,
+                        xyz: "0,0,0".to_string(),
+                        commands: self.commands(animation),
+                    }],
+                })
+                .collect(),
+            palette_aliases: Vec::new(),
+        }
+    }
+
+    /// The image to generate as `src` at assembly time, if any.
+    pub fn generated_image(&self, src: &str) -> Option<&IndexedImage> {
+        let palette_images = self.palettes.iter().map(|p| (&p.src, &p.image));
+        let raster_images = self.rasters.iter().map(|r| (&r.src, &r.image));
+
+        palette_images.chain(raster_images)
+            .find(|(s, _)| *s == src)
+            .and_then(|(_, image)| image.as_ref())
+    }
+
+    /// Compiles an animation to sprite animation commands (see [sheet::Component::decode]).
+    fn commands(&self, animation: &Animation) -> Vec<u16> {
+        let has_offsets = animation.frames.iter().any(|frame| frame.offset.is_some());
+
+        let mut commands = Vec::new();
+        let mut last_frame_idx = 0;
+
+        for frame in &animation.frames {
+            last_frame_idx = commands.len();
+
+            // Every frame gets a position if any does, so offsets don't leak into later frames.
+            if has_offsets {
+                let [x, y, z] = frame.offset.unwrap_or_default();
+                commands.extend_from_slice(&[0x3000, x as u16, y as u16, z as u16]);
+            }
+
+            if let Some(palette) = &frame.palette {
+                commands.push(0x6000 | self.palette_by_name(palette).unwrap() as u16);
+            }
+
+            commands.push(0x1000 | self.raster_by_name(&frame.raster).unwrap() as u16);
+            commands.push(frame.duration);
+        }
+
+        commands.push(0x2000 | if animation.looping { 0 } else { last_frame_idx as u16 });
+        commands
+    }
+}
+
+fn is_aseprite(src: &str) -> bool {
+    src.ends_with(".ase") || src.ends_with(".aseprite")
+}
+
+fn file_stem(src: &str) -> String {
+    Path::new(src)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// Converts milliseconds to game frames (1/30ths of a second).
+fn ms_to_frames(ms: u16) -> u16 {
+    ((ms as u32 * 30 + 500) / 1000).max(1).min(MAX_COMMAND_VALUE as u32) as u16
+}
+
+impl Palette {
+    fn has_rules(&self) -> bool {
+        !self.remap.is_empty() || self.hue_shift != 0.0 || !self.swap.is_empty()
+    }
+
+    /// Applies this variant's rules to the colours of its base palette. Remaps come first, then
+    /// each colour is either swapped or has its hue shifted.
+    fn derive(&self, base: &[Color], base_name: &str, find: &dyn Fn(&str) -> Option<Span>)
+        -> Result<Vec<Color>, SpriteLoadError>
+    {
+        for (ColorIndex(to), &from) in &self.remap {
+            if let Some(&index) = [*to, from].iter().find(|&&index| index >= base.len()) {
+                return Err(SpriteLoadError::BadPaletteRemap {
+                    span: find(&format!("{} = {}", to, from)),
+                    palette: self.name.clone(),
+                    base: base_name.to_owned(),
+                    index,
+                    count: base.len(),
+                });
+            }
+        }
+
+        for from in self.swap.keys() {
+            if !base.iter().any(|color| Rgb::from(*color) == *from) {
+                return Err(SpriteLoadError::UnmatchedColorSwap {
+                    span: find(&format!("\"{}\"", from))
+                        .or_else(|| find(&format!("\"{}\"", from).to_uppercase())),
+                    palette: self.name.clone(),
+                    base: base_name.to_owned(),
+                    color: from.to_string(),
+                });
+            }
+        }
+
+        Ok((0..base.len())
+            .map(|idx| {
+                let color = base[self.remap.get(&ColorIndex(idx)).copied().unwrap_or(idx)];
+
+                match self.swap.get(&Rgb::from(color)) {
+                    Some(Rgb([r, g, b])) => [*r, *g, *b, color[3]],
+                    None => image::shift_hue(color, self.hue_shift),
+                }
+            })
+            .collect())
+    }
+}
+
+impl<'de> Deserialize<'de> for ColorIndex {
+    fn deserialize<D: de::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let string = String::deserialize(deserializer)?;
+
+        string.parse()
+            .map(ColorIndex)
+            .map_err(|_| de::Error::custom(format!("expected a colour index, found '{}'", string)))
+    }
+}
+
+impl From<Color> for Rgb {
+    fn from([r, g, b, _]: Color) -> Rgb {
+        Rgb([r, g, b])
+    }
+}
+
+impl std::str::FromStr for Rgb {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Rgb, String> {
+        let hex = s.trim_start_matches('#');
+        let channel = |idx: usize| hex.get(idx..idx + 2).and_then(|c| u8::from_str_radix(c, 16).ok());
+
+        match (hex.len(), channel(0), channel(2), channel(4)) {
+            (6, Some(r), Some(g), Some(b)) => Ok(Rgb([r, g, b])),
+            _ => Err(format!("expected a colour like \"#ff8800\", found \"{}\"", s)),
+        }
+    }
+}
+
+impl fmt::Display for Rgb {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let Rgb([r, g, b]) = self;
+        write!(f, "#{:02x}{:02x}{:02x}", r, g, b)
+    }
+}
+
+impl<'de> Deserialize<'de> for Rgb {
+    fn deserialize<D: de::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+impl Raster {
+    fn name(&self) -> String {
+        match &self.name {
+            Some(name) => name.clone(),
+            None => file_stem(&self.src),
+        }
+    }
+}
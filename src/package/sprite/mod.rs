@@ -0,0 +1,591 @@
+mod manifest;
+mod sheet;
+mod aseprite;
+mod image;
+mod validate;
+mod render;
+mod patch;
+mod dedup;
+
+use crate::prelude::*;
+use crate::logger;
+use crate::sanitize;
+use crate::diagnostics::{Diagnostics, Diagnostic, Span, Code};
+use super::Package;
+use super::cache::BuildCache;
+use super::id::{Identify, Identifier};
+use manifest::Manifest;
+use sheet::SpriteSheet;
+use patch::Patch;
+
+pub use render::PreviewFormat;
+pub use patch::{VanillaTable, VanillaSprite};
+
+pub type SpriteMap = std::collections::HashMap<SpriteId, Sprite>;
+
+#[derive(Hash, Clone, PartialEq, Eq)]
+pub struct SpriteId(Identifier);
+
+impl Identify for SpriteId {
+    type T = Sprite;
+
+    fn new(pkg_name: &str, sprite_name: &str) -> Self {
+        Self(Identifier::new(pkg_name, sprite_name))
+    }
+
+    fn identify(pkg: &Package, sprite: &Sprite) -> Self {
+        Self(Identifier::from_package(pkg, &sprite.name()))
+    }
+}
+
+impl fmt::Display for SpriteId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl fmt::Debug for SpriteId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", logger::style(Color::Fixed(12)).paint(format!("{{Sprite:{:?}}}", self.0)))
+    }
+}
+
+pub type PlayerSpriteMap = std::collections::HashMap<PlayerSpriteId, Sprite>;
+
+/// Player sprites are identified separately, since their indices are separate from NPC sprites'.
+#[derive(Hash, Clone, PartialEq, Eq)]
+pub struct PlayerSpriteId(Identifier);
+
+impl Identify for PlayerSpriteId {
+    type T = Sprite;
+
+    fn new(pkg_name: &str, sprite_name: &str) -> Self {
+        Self(Identifier::new(pkg_name, sprite_name))
+    }
+
+    fn identify(pkg: &Package, sprite: &Sprite) -> Self {
+        Self(Identifier::from_package(pkg, &sprite.name()))
+    }
+}
+
+impl PlayerSpriteId {
+    pub fn package(&self) -> &str {
+        self.0.package()
+    }
+}
+
+impl fmt::Display for PlayerSpriteId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl fmt::Debug for PlayerSpriteId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", logger::style(Color::Fixed(12)).paint(format!("{{PlayerSprite:{:?}}}", self.0)))
+    }
+}
+
+pub type SpritePatchMap = std::collections::HashMap<SpritePatchId, Sprite>;
+
+/// Sprite patches are identified by the package adding them and the base game sprite they add to,
+/// since more than one package can patch the same sprite.
+#[derive(Hash, Clone, PartialEq, Eq)]
+pub struct SpritePatchId(Identifier);
+
+impl Identify for SpritePatchId {
+    type T = Sprite;
+
+    fn new(pkg_name: &str, sprite_name: &str) -> Self {
+        Self(Identifier::new(pkg_name, sprite_name))
+    }
+
+    fn identify(pkg: &Package, sprite: &Sprite) -> Self {
+        Self(Identifier::from_package(pkg, &sprite.name()))
+    }
+}
+
+impl SpritePatchId {
+    pub fn package(&self) -> &str {
+        self.0.package()
+    }
+
+    /// The name of the base game sprite this patches, e.g. `goomba`.
+    pub fn vanilla_name(&self) -> &str {
+        self.0.name()
+    }
+}
+
+impl fmt::Display for SpritePatchId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl fmt::Debug for SpritePatchId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", logger::style(Color::Fixed(12)).paint(format!("{{SpritePatch:{:?}}}", self.0)))
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Sprite {
+    dir: PathBuf,
+    sheet: SpriteSheet,
+
+    /// Sprites built from a `sprite.toml` rather than a Star Rod `SpriteSheet.xml`.
+    manifest: Option<Manifest>,
+
+    /// For base game sprites with patches applied, the original that Star Rod dumped.
+    vanilla: Option<VanillaSprite>,
+    patches: Vec<Patch>,
+
+    assembled_index: u8, // Zero => unassembled.
+}
+
+impl Sprite {
+    pub fn load(_: &str, dir: &Path) -> Result<Sprite, SpriteLoadError> {
+        let manifest_path = dir.join("sprite.toml");
+
+        let spr = if manifest_path.is_file() {
+            if dir.join("SpriteSheet.xml").is_file() {
+                return Err(SpriteLoadError::AmbiguousSpriteSheet);
+            }
+
+            let manifest = Manifest::load(&manifest_path)?;
+
+            Sprite {
+                sheet: manifest.to_sheet(),
+                manifest: Some(manifest),
+                dir: dir.to_owned(),
+                vanilla: None,
+                patches: Vec::new(),
+                assembled_index: 0,
+            }
+        } else {
+            let spritesheet = fs::read_to_string(dir.join("SpriteSheet.xml"))
+                .map_err(SpriteLoadError::MissingSpriteSheet)?;
+
+            Sprite {
+                sheet: SpriteSheet::parse(&spritesheet)?,
+                manifest: None,
+                dir: dir.to_owned(),
+                vanilla: None,
+                patches: Vec::new(),
+                assembled_index: 0,
+            }
+        };
+
+        sanitize::export_name(&spr.name())?;
+
+        Ok(spr)
+    }
+
+    pub fn name(&self) -> String {
+        self.dir
+            .file_name()
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .to_string()
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn assembled_index(&self) -> Option<u8> {
+        match self.assembled_index {
+            0 => None,
+            _ => Some(self.assembled_index),
+        }
+    }
+
+    /// Copies the files referenced by the sprite sheet to `out_dir`, skipping those which haven't
+    /// changed since the last build. Missing and unused files are reported as warnings. Returns the
+    /// number of bytes saved by sharing identical palettes and rasters; those of a sprite that
+    /// `replaces_vanilla` one, or patches it, keep their indices.
+    pub fn assemble(
+        &mut self,
+        out_dir: &Path,
+        index: u8,
+        replaces_vanilla: bool,
+        cache: &mut BuildCache,
+        diagnostics: &mut Diagnostics,
+    ) -> Result<usize> {
+        // Files only used by merged duplicates are still used, so aren't orphans.
+        let described = self.referenced_files();
+        let vanilla = replaces_vanilla || self.vanilla.is_some();
+        let shared = self.merge_duplicate_palettes(vanilla) + self.merge_duplicate_rasters(vanilla);
+        let referenced = self.referenced_files();
+
+        for src in &referenced {
+            let target_path = out_dir.join(src);
+            let (sprite, file) = self.source(src);
+            let generated = sprite.manifest.as_ref().and_then(|m| m.generated_image(file));
+
+            let contents = match generated {
+                Some(image) => image.to_png()
+                    .with_context(|| format!("unable to encode {}", target_path.display()))?,
+                None => {
+                    let path = sprite.files_dir().join(file);
+                    if !path.is_file() {
+                        let message = format!("sprite '{}' is missing {}", self.name(), src);
+                        diagnostics.warn(Diagnostic::warning(message)
+                            .with_label(sprite.src_span(file), "file not found"));
+                        continue;
+                    }
+
+                    fs::read(&path).with_context(|| format!("unable to read {}", path.display()))?
+                },
+            };
+
+            cache.write(&target_path, &contents)?;
+        }
+
+        let spritesheet = if self.manifest.is_some() || !self.patches.is_empty() || shared > 0 {
+            self.sheet.to_xml().into_bytes()
+        } else {
+            fs::read(self.dir.join("SpriteSheet.xml"))?
+        };
+        cache.write(&out_dir.join("SpriteSheet.xml"), &spritesheet)?;
+
+        // Star Rod's dump has no strays, so only the patches need checking.
+        if self.patches.is_empty() {
+            self.warn_orphans(&described, diagnostics)?;
+        } else {
+            for patch in self.patches() {
+                patch.warn_orphans(&patch.referenced_files(), diagnostics)?;
+            }
+        }
+
+        // Remove anything left over from whichever sprite had this index last build.
+        for entry in out_dir.read_dir()? {
+            let path = entry?.path();
+            let file_name = path.file_name().unwrap().to_string_lossy();
+
+            if path.is_file() && file_name != "SpriteSheet.xml" && !referenced.iter().any(|src| *src == file_name) {
+                cache.remove(&path)?;
+            }
+        }
+
+        self.assembled_index = index;
+
+        Ok(shared)
+    }
+
+    /// The file describing the sprite: its `sprite.toml`, or else its `SpriteSheet.xml`.
+    fn description_path(&self) -> PathBuf {
+        match self.manifest {
+            Some(_) => self.dir.join("sprite.toml"),
+            None => self.dir.join("SpriteSheet.xml"),
+        }
+    }
+
+    /// Where the sprite's description lists the file `src`.
+    fn src_span(&self, src: &str) -> Option<Span> {
+        let path = self.description_path();
+        let description = fs::read_to_string(&path).ok()?;
+        let line_idx = description.lines().position(|line| line.contains(&format!("\"{}\"", src)))?;
+        Span::find(&path, &description, line_idx + 1, src)
+    }
+
+    /// The palette and raster files used by the sprite sheet, without duplicates.
+    fn referenced_files(&self) -> Vec<String> {
+        let palettes = self.sheet.palettes.iter().map(|p| &p.src);
+        let rasters = self.sheet.rasters.iter().map(|r| &r.src);
+
+        let mut files: Vec<String> = Vec::new();
+        for src in palettes.chain(rasters) {
+            if !src.is_empty() && !files.contains(src) {
+                files.push(src.clone());
+            }
+        }
+        files
+    }
+
+    /// Warns about files in the sprite directory which won't make it into the build.
+    fn warn_orphans(&self, referenced: &[String], diagnostics: &mut Diagnostics) -> Result<()> {
+        for entry in self.dir.read_dir()? {
+            let path = entry?.path();
+            let file_name = path.file_name().unwrap().to_string_lossy();
+
+            let is_description = file_name == "SpriteSheet.xml" || file_name == "sprite.toml";
+            let is_hidden = file_name.starts_with('.');
+
+            // Aseprite files are sources for the images, not images themselves.
+            let is_aseprite = matches!(path.extension().and_then(|ext| ext.to_str()),
+                Some("ase") | Some("aseprite"));
+
+            if path.is_file() && !is_description && !is_hidden && !is_aseprite
+                && !referenced.iter().any(|src| *src == file_name)
+            {
+                let message = format!("sprite '{}' does not use {}", self.name(), file_name);
+                diagnostics.warn(Diagnostic::warning(message)
+                    .with_help("remove it, or list it in sprite.toml or SpriteSheet.xml"));
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn palette_names(&self) -> Vec<String> {
+        let aliases = self.sheet.palette_aliases.iter().map(|(name, _)| name);
+        self.sheet.palettes.iter().map(|p| &p.name).chain(aliases).cloned().collect()
+    }
+
+    pub fn animation_names(&self) -> Vec<String> {
+        self.sheet.animations.iter().map(|a| a.name.clone()).collect()
+    }
+
+    pub fn palette_by_name(&self, name: &str) -> Option<usize> {
+        self.sheet.palette_by_name(name)
+    }
+
+    pub fn animation_by_name(&self, name: &str) -> Option<usize> {
+        self.sheet.animation_by_name(name)
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum SpriteLoadError {
+    #[error("missing SpriteSheet.xml")]
+    MissingSpriteSheet(#[source] io::Error),
+
+    #[error("malformed SpriteSheet.xml")]
+    MalformedSpriteSheet(#[source] roxmltree::Error),
+
+    #[error("SpriteSheet.xml is missing PaletteList")]
+    SpriteSheetMissingPaletteList,
+
+    #[error("SpriteSheet.xml is missing AnimationList")]
+    SpriteSheetMissingAnimationList,
+
+    #[error("unable to read sprite.toml")]
+    UnreadableManifest(#[source] io::Error),
+
+    #[error("malformed sprite.toml: {error}")]
+    MalformedManifest {
+        path: PathBuf,
+
+        #[source]
+        error: toml::de::Error,
+    },
+
+    #[error("unknown raster '{raster}'")]
+    UnknownRaster {
+        span: Option<Span>,
+        raster: String,
+        rasters: Vec<String>,
+    },
+
+    #[error("unknown palette '{palette}'")]
+    UnknownPalette {
+        span: Option<Span>,
+        palette: String,
+        palettes: Vec<String>,
+    },
+
+    #[error("animation '{animation}' has a frame lasting {duration} frames (must be 1 to 4095)")]
+    BadFrameDuration {
+        span: Option<Span>,
+        animation: String,
+        duration: u16,
+    },
+
+    #[error("sprite has both sprite.toml and SpriteSheet.xml")]
+    AmbiguousSpriteSheet,
+
+    #[error("unable to read Aseprite file {}: {error}", path.display())]
+    BadAseprite {
+        path: PathBuf,
+
+        #[source]
+        error: aseprite::AsepriteError,
+    },
+
+    #[error("{src}: {error}")]
+    BadImage {
+        span: Option<Span>,
+        src: String,
+        animations: Vec<String>,
+
+        #[source]
+        error: Box<image::ReadPngError>,
+    },
+
+    #[error("{src} uses colour index {index}, but sprites can have at most 16 colours")]
+    TooManyColors {
+        span: Option<Span>,
+        src: String,
+        index: usize,
+        animations: Vec<String>,
+    },
+
+    #[error("{src} is {width}x{height}, but rasters must be at most 255x255 with an even width")]
+    BadRasterSize {
+        span: Option<Span>,
+        src: String,
+        width: u32,
+        height: u32,
+        animations: Vec<String>,
+    },
+
+    #[error("raster {src} uses palette {palette:X}, but there are only {count} palettes")]
+    UnknownPaletteIndex {
+        span: Option<Span>,
+        src: String,
+        palette: usize,
+        count: usize,
+    },
+
+    #[error("{src} uses colour index {index}, but its palette '{palette}' only has {count} colours")]
+    PaletteTooSmall {
+        span: Option<Span>,
+        src: String,
+        index: usize,
+        palette: String,
+        count: usize,
+    },
+
+    #[error("palette '{palette}' remaps colour {index}, but its base palette '{base}' only has {count} colours")]
+    BadPaletteRemap {
+        span: Option<Span>,
+        palette: String,
+        base: String,
+        index: usize,
+        count: usize,
+    },
+
+    #[error("palette '{palette}' swaps {color}, but its base palette '{base}' doesn't have that colour")]
+    UnmatchedColorSwap {
+        span: Option<Span>,
+        palette: String,
+        base: String,
+        color: String,
+    },
+
+    #[error("unable to find Star Rod's dump of the base game's sprites")]
+    NoVanillaSprites,
+
+    #[error("the base game has no sprite named '{0}'")]
+    UnknownVanillaSprite(String),
+
+    #[error("package '{package}' adds {kind} '{name}', but the sprite already has one by that name")]
+    PatchNameClash {
+        kind: &'static str,
+        name: String,
+        package: String,
+    },
+
+    #[error(transparent)]
+    BadName(#[from] sanitize::ExportNameError),
+}
+
+impl SpriteLoadError {
+    /// Whether this error points at the offending source, so needn't say which sprite it's from.
+    pub fn has_span(&self) -> bool {
+        match self {
+            SpriteLoadError::MalformedManifest { .. } => true,
+            SpriteLoadError::UnknownRaster { span, .. } => span.is_some(),
+            SpriteLoadError::UnknownPalette { span, .. } => span.is_some(),
+            SpriteLoadError::BadFrameDuration { span, .. } => span.is_some(),
+            SpriteLoadError::BadImage { span, .. } => span.is_some(),
+            SpriteLoadError::TooManyColors { span, .. } => span.is_some(),
+            SpriteLoadError::BadRasterSize { span, .. } => span.is_some(),
+            SpriteLoadError::UnknownPaletteIndex { span, .. } => span.is_some(),
+            SpriteLoadError::PaletteTooSmall { span, .. } => span.is_some(),
+            SpriteLoadError::BadPaletteRemap { span, .. } => span.is_some(),
+            SpriteLoadError::UnmatchedColorSwap { span, .. } => span.is_some(),
+            _ => false,
+        }
+    }
+}
+
+impl From<SpriteLoadError> for Diagnostic {
+    fn from(error: SpriteLoadError) -> Diagnostic {
+        let code = error.code();
+        let diagnostic = Diagnostic::error(&error).with_code(code);
+
+        match error {
+            SpriteLoadError::MalformedManifest { path, error } =>
+                Diagnostic::toml(&path, &error).with_code(code),
+            SpriteLoadError::UnknownRaster { span, rasters, .. } => diagnostic
+                .with_label(span, "no such raster")
+                .with_help(format!("rasters are: {}", rasters.join(", "))),
+            SpriteLoadError::UnknownPalette { span, palettes, .. } => diagnostic
+                .with_label(span, "no such palette")
+                .with_help(format!("palettes are: {}", palettes.join(", "))),
+            SpriteLoadError::BadFrameDuration { span, .. } => diagnostic
+                .with_label(span, "bad duration"),
+            SpriteLoadError::BadImage { span, animations, .. } => {
+                let diagnostic = diagnostic.with_label(span, "unusable image");
+
+                // Palettes aren't used by animations directly.
+                match animations.is_empty() {
+                    true => diagnostic,
+                    false => diagnostic.with_note(used_by(&animations)),
+                }
+            },
+            SpriteLoadError::TooManyColors { span, animations, .. } => diagnostic
+                .with_label(span, "too many colours")
+                .with_note(used_by(&animations))
+                .with_help("reduce the image to 16 colours, with the transparent colour first"),
+            SpriteLoadError::BadRasterSize { span, animations, .. } => diagnostic
+                .with_label(span, "bad size")
+                .with_note(used_by(&animations)),
+            SpriteLoadError::UnknownPaletteIndex { span, .. } => diagnostic
+                .with_label(span, "no such palette"),
+            SpriteLoadError::PaletteTooSmall { span, .. } => diagnostic
+                .with_label(span, "palette too small"),
+            SpriteLoadError::BadPaletteRemap { span, .. } => diagnostic
+                .with_label(span, "no such colour"),
+            SpriteLoadError::UnmatchedColorSwap { span, .. } => diagnostic
+                .with_label(span, "no such colour")
+                .with_help("swapped colours must match the base palette exactly"),
+            SpriteLoadError::NoVanillaSprites => diagnostic
+                .with_help("open the ROM in Star Rod to dump its assets, or set STARPKG_DUMP_DIR to where they were dumped"),
+            _ => diagnostic,
+        }
+    }
+}
+
+fn used_by(animations: &[String]) -> String {
+    match animations.len() {
+        0 => "not used by any animation".to_string(),
+        1 => format!("used by animation '{}'", animations[0]),
+        _ => format!("used by animations: {}", animations.join(", ")),
+    }
+}
+
+impl Code for SpriteLoadError {
+    fn code(&self) -> Option<&'static str> {
+        match self {
+            SpriteLoadError::MissingSpriteSheet(_) => Some("SP0401"),
+            SpriteLoadError::MalformedSpriteSheet(_) => Some("SP0402"),
+            SpriteLoadError::SpriteSheetMissingPaletteList => Some("SP0403"),
+            SpriteLoadError::SpriteSheetMissingAnimationList => Some("SP0404"),
+            SpriteLoadError::UnreadableManifest(_) => None,
+            SpriteLoadError::MalformedManifest { .. } => Some("SP0405"),
+            SpriteLoadError::UnknownRaster { .. } => Some("SP0406"),
+            SpriteLoadError::UnknownPalette { .. } => Some("SP0407"),
+            SpriteLoadError::BadFrameDuration { .. } => Some("SP0408"),
+            SpriteLoadError::AmbiguousSpriteSheet => Some("SP0409"),
+            SpriteLoadError::BadAseprite { .. } => Some("SP0410"),
+            SpriteLoadError::BadImage { error, .. } => match **error {
+                image::ReadPngError::NotIndexed(_) => Some("SP0411"),
+                image::ReadPngError::Decoding(_) => Some("SP0412"),
+            },
+            SpriteLoadError::TooManyColors { .. } => Some("SP0413"),
+            SpriteLoadError::BadRasterSize { .. } => Some("SP0414"),
+            SpriteLoadError::UnknownPaletteIndex { .. } => Some("SP0415"),
+            SpriteLoadError::PaletteTooSmall { .. } => Some("SP0416"),
+            SpriteLoadError::BadPaletteRemap { .. } => Some("SP0417"),
+            SpriteLoadError::UnmatchedColorSwap { .. } => Some("SP0418"),
+            SpriteLoadError::NoVanillaSprites => Some("SP0419"),
+            SpriteLoadError::UnknownVanillaSprite(_) => Some("SP0420"),
+            SpriteLoadError::PatchNameClash { .. } => Some("SP0421"),
+            SpriteLoadError::BadName(error) => error.code(),
+        }
+    }
+}
@@ -0,0 +1,158 @@
+use crate::prelude::*;
+use crate::starrod::StarRod;
+use super::{Sprite, SpritePatchId, SpriteLoadError};
+use super::sheet::SpriteSheet;
+
+/// Which of the base game's sprite tables a patched sprite is from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VanillaTable {
+    Npc,
+    Player,
+}
+
+impl VanillaTable {
+    /// The sprite table's element in the dumped `SpriteTable.xml`, and the directory its sprites
+    /// were dumped to.
+    fn dump_location(self) -> (&'static str, &'static str) {
+        match self {
+            VanillaTable::Npc => ("NpcSprites", "npc/src"),
+            VanillaTable::Player => ("PlayerSprites", "player/src"),
+        }
+    }
+}
+
+/// A package's additions to a base game sprite. Its files are renamed with `prefix` in the merged
+/// sprite sheet, so they can't collide with the base game's or another package's.
+#[derive(Clone, Debug)]
+pub struct Patch {
+    prefix: String,
+    sprite: Sprite,
+}
+
+impl Sprite {
+    /// Applies patches to the base game sprite with the given name, as dumped by Star Rod. Each
+    /// patch's palettes, rasters and animations are added after those of the sprites before it, so
+    /// existing indices are kept.
+    pub fn patch_vanilla(
+        name: &str,
+        table: VanillaTable,
+        patches: &[(&SpritePatchId, &Sprite)],
+    ) -> Result<Sprite, SpriteLoadError> {
+        let vanilla = VanillaSprite::find(name, table)?;
+        let xml = fs::read_to_string(vanilla.dir.join("SpriteSheet.xml"))
+            .map_err(SpriteLoadError::MissingSpriteSheet)?;
+        let mut sheet = SpriteSheet::parse(&xml)?;
+
+        let mut applied = Vec::new();
+        for (id, sprite) in patches {
+            let clash = sprite.sheet.palettes.iter()
+                .find(|palette| sheet.palette_by_name(&palette.name).is_some())
+                .map(|palette| ("palette", &palette.name))
+                .or_else(|| sprite.sheet.animations.iter()
+                    .find(|animation| sheet.animation_by_name(&animation.name).is_some())
+                    .map(|animation| ("animation", &animation.name)));
+
+            if let Some((kind, name)) = clash {
+                return Err(SpriteLoadError::PatchNameClash {
+                    kind,
+                    name: name.clone(),
+                    package: id.package().to_owned(),
+                });
+            }
+
+            let prefix = format!("{}_", id.package());
+            sheet.append(&sprite.sheet, |src| format!("{}{}", prefix, src));
+
+            applied.push(Patch {
+                prefix,
+                sprite: (*sprite).clone(),
+            });
+        }
+
+        Ok(Sprite {
+            dir: patches[0].1.dir.clone(),
+            sheet,
+            manifest: None,
+            vanilla: Some(vanilla),
+            patches: applied,
+            assembled_index: 0,
+        })
+    }
+
+    /// The sprite that a file referenced by the sprite sheet belongs to, and its name there.
+    pub(super) fn source<'a>(&'a self, src: &'a str) -> (&'a Sprite, &'a str) {
+        for patch in &self.patches {
+            if let Some(file) = src.strip_prefix(&patch.prefix) {
+                return (&patch.sprite, file);
+            }
+        }
+
+        (self, src)
+    }
+
+    /// Whether a file referenced by the sprite sheet was added by a patch.
+    pub(super) fn is_patch_file(&self, src: &str) -> bool {
+        self.patches.iter().any(|patch| src.starts_with(&patch.prefix))
+    }
+
+    /// The directory holding the sprite's own files.
+    pub(super) fn files_dir(&self) -> &Path {
+        self.vanilla.as_ref().map_or(&self.dir, |vanilla| &vanilla.dir)
+    }
+
+    /// The base game sprite this sprite patches, if any.
+    pub fn vanilla(&self) -> Option<&VanillaSprite> {
+        self.vanilla.as_ref()
+    }
+
+    pub(super) fn patches(&self) -> impl Iterator<Item = &Sprite> {
+        self.patches.iter().map(|patch| &patch.sprite)
+    }
+}
+
+/// A base game sprite, as listed in Star Rod's dumped `SpriteTable.xml`.
+#[derive(Clone, Debug)]
+pub struct VanillaSprite {
+    pub index: u8,
+    pub name: String,
+
+    /// Where Star Rod dumped its files.
+    dir: PathBuf,
+}
+
+impl VanillaSprite {
+    /// The sprites in the given table of Star Rod's dump, in index order.
+    pub fn list(table: VanillaTable) -> Result<Vec<VanillaSprite>, SpriteLoadError> {
+        let (table_tag, src_dir) = table.dump_location();
+        let sprites_dir = StarRod::dump_dir()
+            .map(|dir| dir.join("sprite"))
+            .ok_or(SpriteLoadError::NoVanillaSprites)?;
+        let table = fs::read_to_string(sprites_dir.join("SpriteTable.xml"))
+            .map_err(|_| SpriteLoadError::NoVanillaSprites)?;
+        let doc = roxmltree::Document::parse(&table)
+            .map_err(|_| SpriteLoadError::NoVanillaSprites)?;
+
+        let mut sprites: Vec<VanillaSprite> = doc.descendants()
+            .filter(|n| n.has_tag_name(table_tag))
+            .flat_map(|n| n.children())
+            .filter(|n| n.has_tag_name("Sprite"))
+            .filter_map(|n| Some(VanillaSprite {
+                index: u8::from_str_radix(n.attribute("id")?, 16).ok()?,
+                name: n.attribute("name")?.to_owned(),
+                dir: sprites_dir.join(src_dir).join(n.attribute("src")?),
+            }))
+            .collect();
+        sprites.sort_by_key(|sprite| sprite.index);
+
+        Ok(sprites)
+    }
+
+    /// Finds a base game sprite by its name in the given table, lowercased and with spaces as
+    /// underscores.
+    fn find(name: &str, table: VanillaTable) -> Result<VanillaSprite, SpriteLoadError> {
+        VanillaSprite::list(table)?
+            .into_iter()
+            .find(|sprite| sprite.name.to_lowercase().replace(' ', "_") == name)
+            .ok_or_else(|| SpriteLoadError::UnknownVanillaSprite(name.to_owned()))
+    }
+}
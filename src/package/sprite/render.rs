@@ -0,0 +1,459 @@
+//! Plays sprite animations on the CPU, so they can be previewed without running the game.
+//!
+//! Only raster, palette, position and timing commands are simulated; rotation and scale are
+//! ignored.
+
+use crate::prelude::*;
+use super::Sprite;
+use super::image::{Color, IndexedImage};
+use super::sheet::Command;
+
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+
+/// The game runs sprite animations at 30 frames per second.
+pub const FRAMES_PER_SECOND: u16 = 30;
+
+/// Animations which never loop or finish are cut off after a minute.
+const MAX_TICKS: usize = 60 * FRAMES_PER_SECOND as usize;
+
+/// Commands run in a single tick before a component is considered stuck, e.g. on a `Goto` to itself.
+const MAX_COMMANDS_PER_TICK: usize = 1000;
+
+/// Gap between cells of a contact sheet, in pixels.
+const CONTACT_SHEET_SPACING: u32 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PreviewFormat {
+    Gif,
+    Apng,
+}
+
+/// A truecolour image, as rendered from one or more rasters.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RgbaImage {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<Color>,
+}
+
+/// A rendered animation frame.
+#[derive(Clone, Debug)]
+pub struct Frame {
+    pub image: RgbaImage,
+
+    /// How long this frame is shown for, in game frames.
+    pub duration: u16,
+}
+
+/// What a component is showing on a given tick.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Draw {
+    raster: usize,
+    palette: usize,
+    x: i32,
+    y: i32,
+}
+
+/// Steps through the commands of one animation component.
+struct Player {
+    commands: Vec<(usize, Command)>,
+    next: usize,
+    wait: u16,
+    loop_count: Option<u16>,
+
+    base: (i32, i32),
+    offset: (i32, i32),
+    raster: Option<usize>,
+    palette: Option<usize>,
+
+    /// The component ran out of commands, so will show its last raster forever.
+    finished: bool,
+
+    /// The component has jumped back to an earlier command, so will repeat itself from here on.
+    looped: bool,
+}
+
+impl Player {
+    fn new(component: &super::sheet::Component) -> Player {
+        let mut xyz = component.xyz.split(',').map(|n| n.trim().parse().unwrap_or(0));
+
+        Player {
+            commands: component.decode_with_offsets(),
+            next: 0,
+            wait: 0,
+            loop_count: None,
+            base: (xyz.next().unwrap_or(0), xyz.next().unwrap_or(0)),
+            offset: (0, 0),
+            raster: None,
+            palette: None,
+            finished: false,
+            looped: false,
+        }
+    }
+
+    /// Runs commands until the next wait.
+    fn advance(&mut self) {
+        for _ in 0..MAX_COMMANDS_PER_TICK {
+            let command = match self.commands.get(self.next) {
+                Some((_, command)) => *command,
+                None => {
+                    self.finished = true;
+                    return;
+                },
+            };
+            self.next += 1;
+
+            match command {
+                Command::Wait(0) => {},
+                Command::Wait(frames) => {
+                    self.wait = frames;
+                    return;
+                },
+                Command::SetRaster(raster) => self.raster = Some(raster),
+                Command::SetPalette(palette) => self.palette = Some(palette),
+                Command::SetPosition(x, y, _) => self.offset = (x as i32, y as i32),
+                Command::Goto(offset) => {
+                    self.jump(offset);
+                    self.looped = true;
+                },
+                Command::Loop { to, count } => {
+                    let remaining = self.loop_count.unwrap_or(count);
+                    if remaining > 0 {
+                        self.loop_count = Some(remaining - 1);
+                        self.jump(to);
+                    } else {
+                        self.loop_count = None;
+                    }
+                },
+                Command::SetRotation(..) | Command::SetScale(..) | Command::Other(_) => {},
+            }
+        }
+
+        // Stuck in a loop without waiting; freeze where we are.
+        self.finished = true;
+    }
+
+    fn jump(&mut self, offset: usize) {
+        self.next = self.commands.iter()
+            .position(|(o, _)| *o == offset)
+            .unwrap_or(self.commands.len());
+    }
+
+    fn tick(&mut self) {
+        if self.finished {
+            return;
+        }
+
+        self.wait = self.wait.saturating_sub(1);
+        if self.wait == 0 {
+            self.advance();
+        }
+    }
+
+    /// Draws the current raster. A `palette` override comes first, then the palette set by the
+    /// last `SetPalette` command, then the raster's own.
+    fn draw(&self, sprite: &Sprite, palette: Option<usize>) -> Option<Draw> {
+        let raster_idx = self.raster?;
+        let raster = sprite.sheet.rasters.get(raster_idx)?;
+
+        Some(Draw {
+            raster: raster_idx,
+            palette: palette.or(self.palette).unwrap_or(raster.palette),
+            x: self.base.0 + self.offset.0,
+            y: self.base.1 + self.offset.1,
+        })
+    }
+}
+
+impl Sprite {
+    /// Renders a preview of an animation, encoded in the given format. If `palette` is given, every
+    /// raster is drawn with it, even where the animation's `SetPalette` commands choose another.
+    pub fn preview(
+        &self,
+        animation: usize,
+        palette: Option<usize>,
+        scale: u32,
+        format: PreviewFormat,
+    ) -> Result<Vec<u8>> {
+        let frames = self.render(&[animation], palette)?.remove(0);
+        let frames: Vec<Frame> = frames.into_iter()
+            .map(|frame| Frame {
+                image: frame.image.scaled(scale),
+                ..frame
+            })
+            .collect();
+
+        match format {
+            PreviewFormat::Gif => encode_gif(&frames),
+            PreviewFormat::Apng => encode_apng(&frames),
+        }
+    }
+
+    /// Renders every animation as a PNG, one row per animation and one column per frame.
+    pub fn contact_sheet(&self, palette: Option<usize>, scale: u32) -> Result<Vec<u8>> {
+        let animations: Vec<usize> = (0..self.sheet.animations.len()).collect();
+        let rows = self.render(&animations, palette)?;
+
+        let cell_width = rows.iter().flatten().map(|f| f.image.width).max().unwrap_or(1);
+        let cell_height = rows.iter().flatten().map(|f| f.image.height).max().unwrap_or(1);
+        let columns = rows.iter().map(Vec::len).max().unwrap_or(1) as u32;
+
+        let mut sheet = RgbaImage::new(
+            columns * (cell_width + CONTACT_SHEET_SPACING) - CONTACT_SHEET_SPACING,
+            rows.len().max(1) as u32 * (cell_height + CONTACT_SHEET_SPACING) - CONTACT_SHEET_SPACING,
+        );
+        for (row, frames) in rows.iter().enumerate() {
+            for (column, frame) in frames.iter().enumerate() {
+                sheet.blit(
+                    &frame.image,
+                    column as i32 * (cell_width + CONTACT_SHEET_SPACING) as i32,
+                    row as i32 * (cell_height + CONTACT_SHEET_SPACING) as i32,
+                );
+            }
+        }
+
+        sheet.scaled(scale).to_png()
+    }
+
+    /// Plays the given animations, returning their distinct frames. Every frame of every animation
+    /// shares the same canvas, with the sprite's origin in the same place.
+    fn render(&self, animations: &[usize], palette: Option<usize>) -> Result<Vec<Vec<Frame>>> {
+        let timelines: Vec<Vec<(Vec<Draw>, u16)>> = animations.iter()
+            .map(|&animation| self.play(animation, palette))
+            .collect();
+
+        let mut images: HashMap<usize, IndexedImage> = HashMap::new();
+        let image = |src: &str| -> Result<IndexedImage> {
+            match self.image(src) {
+                Some(Ok(image)) => Ok(image),
+                Some(Err(error)) => Err(error).with_context(|| format!("unable to read {}", src)),
+                None => Err(anyhow!("sprite '{}' is missing {}", self.name(), src)),
+            }
+        };
+
+        // Load every raster and palette that is drawn.
+        let mut palettes: HashMap<usize, Vec<Color>> = HashMap::new();
+        for (draws, _) in timelines.iter().flatten() {
+            for draw in draws {
+                if let Entry::Vacant(entry) = images.entry(draw.raster) {
+                    entry.insert(image(&self.sheet.rasters[draw.raster].src)?);
+                }
+                if let Entry::Vacant(entry) = palettes.entry(draw.palette) {
+                    entry.insert(match self.sheet.palettes.get(draw.palette) {
+                        Some(palette) => image(&palette.src)?.palette,
+                        None => Vec::new(),
+                    });
+                }
+            }
+        }
+
+        // Rasters hang from their bottom-centre.
+        let rect = |draw: &Draw| {
+            let raster = &images[&draw.raster];
+            let left = draw.x - raster.width as i32 / 2;
+            let top = -draw.y - raster.height as i32;
+            (left, top, left + raster.width as i32, -draw.y)
+        };
+        let rects: Vec<_> = timelines.iter().flatten().flat_map(|(draws, _)| draws).map(rect).collect();
+        let left = rects.iter().map(|r| r.0).min().unwrap_or(0);
+        let top = rects.iter().map(|r| r.1).min().unwrap_or(0);
+        let right = rects.iter().map(|r| r.2).max().unwrap_or(1);
+        let bottom = rects.iter().map(|r| r.3).max().unwrap_or(1);
+
+        Ok(timelines.into_iter()
+            .map(|timeline| timeline.into_iter()
+                .map(|(draws, duration)| {
+                    let mut canvas = RgbaImage::new((right - left) as u32, (bottom - top) as u32);
+                    for draw in &draws {
+                        let (x, y, _, _) = rect(draw);
+                        canvas.draw_indexed(&images[&draw.raster], &palettes[&draw.palette], x - left, y - top);
+                    }
+                    Frame { image: canvas, duration }
+                })
+                .collect())
+            .collect())
+    }
+
+    /// Steps through an animation until every component has looped or finished, returning what is
+    /// shown and for how many ticks. Consecutive ticks showing the same thing are merged.
+    fn play(&self, animation: usize, palette: Option<usize>) -> Vec<(Vec<Draw>, u16)> {
+        let mut players: Vec<Player> = self.sheet.animations[animation].components.iter()
+            .map(Player::new)
+            .collect();
+        for player in &mut players {
+            player.advance();
+        }
+
+        let mut timeline: Vec<(Vec<Draw>, u16)> = Vec::new();
+        for _ in 0..MAX_TICKS {
+            let draws: Vec<Draw> = players.iter()
+                .filter_map(|player| player.draw(self, palette))
+                .collect();
+
+            match timeline.last_mut() {
+                Some((last, duration)) if *last == draws => *duration += 1,
+                _ => timeline.push((draws, 1)),
+            }
+
+            for player in &mut players {
+                player.tick();
+            }
+
+            if players.iter().all(|player| player.finished || player.looped) {
+                break;
+            }
+        }
+
+        timeline
+    }
+}
+
+impl RgbaImage {
+    pub fn new(width: u32, height: u32) -> RgbaImage {
+        RgbaImage {
+            width,
+            height,
+            pixels: vec![[0, 0, 0, 0]; (width * height) as usize],
+        }
+    }
+
+    /// Draws a colour-indexed image with the given palette. Fully transparent colours are skipped.
+    fn draw_indexed(&mut self, image: &IndexedImage, palette: &[Color], x: i32, y: i32) {
+        let colors: Vec<Color> = image.pixels.iter()
+            .map(|&index| palette.get(index as usize).copied().unwrap_or([0, 0, 0, 0]))
+            .collect();
+
+        self.blit(&RgbaImage {
+            width: image.width,
+            height: image.height,
+            pixels: colors,
+        }, x, y);
+    }
+
+    fn blit(&mut self, image: &RgbaImage, x: i32, y: i32) {
+        for iy in 0..image.height as i32 {
+            for ix in 0..image.width as i32 {
+                let (cx, cy) = (x + ix, y + iy);
+                if cx < 0 || cy < 0 || cx >= self.width as i32 || cy >= self.height as i32 {
+                    continue;
+                }
+
+                let color = image.pixels[(iy * image.width as i32 + ix) as usize];
+                if color[3] != 0 {
+                    self.pixels[(cy * self.width as i32 + cx) as usize] = color;
+                }
+            }
+        }
+    }
+
+    /// Scales this image up by a whole number, keeping pixels sharp.
+    fn scaled(&self, scale: u32) -> RgbaImage {
+        let scale = scale.max(1);
+        let width = self.width * scale;
+        let height = self.height * scale;
+
+        RgbaImage {
+            width,
+            height,
+            pixels: (0..height)
+                .flat_map(|y| (0..width).map(move |x| (x, y)))
+                .map(|(x, y)| self.pixels[((y / scale) * self.width + x / scale) as usize])
+                .collect(),
+        }
+    }
+
+    fn bytes(&self) -> Vec<u8> {
+        self.pixels.iter().flatten().copied().collect()
+    }
+
+    fn to_png(&self) -> Result<Vec<u8>> {
+        let mut png = Vec::new();
+
+        let mut encoder = png::Encoder::new(&mut png, self.width, self.height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.write_header()?.write_image_data(&self.bytes())?;
+
+        Ok(png)
+    }
+}
+
+fn encode_apng(frames: &[Frame]) -> Result<Vec<u8>> {
+    let (width, height) = frames.first()
+        .map(|f| (f.image.width, f.image.height))
+        .unwrap_or((1, 1));
+    let mut png = Vec::new();
+
+    let mut encoder = png::Encoder::new(&mut png, width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_animated(frames.len() as u32, 0)?; // Loop forever.
+
+    let mut writer = encoder.write_header()?;
+    for frame in frames {
+        writer.set_frame_delay(frame.duration, FRAMES_PER_SECOND)?;
+        writer.write_image_data(&frame.image.bytes())?;
+    }
+    writer.finish()?;
+
+    Ok(png)
+}
+
+fn encode_gif(frames: &[Frame]) -> Result<Vec<u8>> {
+    let (width, height) = frames.first()
+        .map(|f| (f.image.width, f.image.height))
+        .unwrap_or((1, 1));
+    if width > u16::MAX as u32 || height > u16::MAX as u32 {
+        return Err(anyhow!("preview is too large for a GIF ({}x{})", width, height));
+    }
+
+    // Sprites only have a handful of colours, so they usually fit in a GIF palette exactly. Index 0
+    // is reserved for transparency.
+    let mut colors: Vec<Color> = vec![[0, 0, 0, 0]];
+    for color in frames.iter().flat_map(|f| &f.image.pixels) {
+        if color[3] != 0 && colors.len() <= 256 && !colors.contains(color) {
+            colors.push(*color);
+        }
+    }
+    let exact = colors.len() <= 256;
+
+    let mut gif = Vec::new();
+    {
+        let palette: Vec<u8> = match exact {
+            true => colors.iter().flat_map(|c| c[..3].to_vec()).collect(),
+            false => Vec::new(),
+        };
+        let mut encoder = gif::Encoder::new(&mut gif, width as u16, height as u16, &palette)?;
+        encoder.set_repeat(gif::Repeat::Infinite)?;
+
+        // GIF delays are in hundredths of a second, so round each frame's end time rather than its
+        // duration to keep the animation in sync overall.
+        let mut ticks = 0;
+        let mut centiseconds = 0;
+
+        for frame in frames {
+            let mut gif_frame = if exact {
+                let pixels: Vec<u8> = frame.image.pixels.iter()
+                    .map(|color| match color[3] {
+                        0 => 0,
+                        _ => colors.iter().position(|c| c == color).unwrap() as u8,
+                    })
+                    .collect();
+                gif::Frame::from_indexed_pixels(width as u16, height as u16, &pixels, Some(0))
+            } else {
+                gif::Frame::from_rgba_speed(width as u16, height as u16, &mut frame.image.bytes(), 10)
+            };
+
+            ticks += frame.duration as u32;
+            let end = (ticks * 100 + FRAMES_PER_SECOND as u32 / 2) / FRAMES_PER_SECOND as u32;
+            gif_frame.delay = (end - centiseconds) as u16;
+            gif_frame.dispose = gif::DisposalMethod::Background;
+            centiseconds = end;
+
+            encoder.write_frame(&gif_frame)?;
+        }
+    }
+
+    Ok(gif)
+}
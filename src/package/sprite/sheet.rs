@@ -0,0 +1,363 @@
+use crate::prelude::*;
+use super::SpriteLoadError;
+
+/// The contents of a Star Rod `SpriteSheet.xml`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SpriteSheet {
+    pub palettes: Vec<Palette>,
+    pub rasters: Vec<Raster>,
+    pub animations: Vec<Animation>,
+
+    /// Names of palettes removed by [SpriteSheet::merge_palettes], with the index of the identical
+    /// palette shown instead. These aren't saved to the XML.
+    pub palette_aliases: Vec<(String, usize)>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Palette {
+    pub name: String,
+    pub src: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Raster {
+    /// Index into [SpriteSheet::palettes].
+    pub palette: usize,
+    pub src: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Animation {
+    pub name: String,
+    pub components: Vec<Component>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Component {
+    pub name: Option<String>,
+
+    /// Offset of this component, as `x,y,z`.
+    pub xyz: String,
+    pub commands: Vec<u16>,
+}
+
+/// A decoded sprite animation command.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Command {
+    Wait(u16),
+    SetRaster(usize),
+    Goto(usize),
+    SetPosition(i16, i16, i16),
+    SetRotation(i16, i16, i16),
+    SetScale(u16, u16),
+    SetPalette(usize),
+    Loop { to: usize, count: u16 },
+    Other(u16),
+}
+
+impl SpriteSheet {
+    pub fn parse(xml: &str) -> Result<SpriteSheet, SpriteLoadError> {
+        let doc = roxmltree::Document::parse(xml)
+            .map_err(SpriteLoadError::MalformedSpriteSheet)?;
+        let root = doc.root_element();
+
+        let list = |tag: &str| root.children().find(|n| n.tag_name().name() == tag);
+        let hex = |n: roxmltree::Node, attr: &str| n.attribute(attr)
+            .and_then(|v| usize::from_str_radix(v, 16).ok());
+
+        let palettes = list("PaletteList")
+            .ok_or(SpriteLoadError::SpriteSheetMissingPaletteList)?
+            .children()
+            .filter(|n| n.is_element())
+            .enumerate()
+            .map(|(idx, n)| Palette {
+                name: match n.attribute("name") {
+                    Some(attr) => attr.to_string(),
+                    None => format!("{:X}", idx),
+                },
+                src: n.attribute("src").unwrap_or_default().to_string(),
+            })
+            .collect();
+
+        let rasters = list("RasterList")
+            .map(|list| list.children()
+                .filter(|n| n.is_element())
+                .map(|n| Raster {
+                    palette: hex(n, "palette").unwrap_or(0),
+                    src: n.attribute("src").unwrap_or_default().to_string(),
+                })
+                .collect())
+            .unwrap_or_default();
+
+        let animations = list("AnimationList")
+            .ok_or(SpriteLoadError::SpriteSheetMissingAnimationList)?
+            .children()
+            .filter(|n| n.is_element())
+            .enumerate()
+            .map(|(idx, n)| Animation {
+                name: match n.attribute("name") {
+                    Some(attr) => attr.to_string(),
+                    None => format!("{:X}", idx),
+                },
+                components: n.children()
+                    .filter(|n| n.is_element())
+                    .map(|n| Component {
+                        name: n.attribute("name").map(str::to_string),
+                        xyz: n.attribute("xyz").unwrap_or("0,0,0").to_string(),
+                        commands: n.children()
+                            .filter(|n| n.is_element())
+                            .filter_map(|n| n.attribute("val"))
+                            .filter_map(|val| u16::from_str_radix(val, 16).ok())
+                            .collect(),
+                    })
+                    .collect(),
+            })
+            .collect();
+
+        Ok(SpriteSheet {
+            palettes,
+            rasters,
+            animations,
+            palette_aliases: Vec::new(),
+        })
+    }
+
+    pub fn to_xml(&self) -> String {
+        let mut xml = String::new();
+
+        // Writing to a String can't fail.
+        let _ = self.write_xml(&mut xml);
+
+        xml
+    }
+
+    fn write_xml(&self, xml: &mut String) -> fmt::Result {
+        writeln!(xml, r#"<?xml version="1.0" encoding="UTF-8" standalone="no"?>"#)?;
+        writeln!(xml, r#"<SpriteSheet a="1" b="1">"#)?;
+
+        writeln!(xml, "\t<PaletteList>")?;
+        for (idx, palette) in self.palettes.iter().enumerate() {
+            writeln!(xml, r#"		<Palette id="{:X}" name="{}" src="{}"/>"#,
+                idx, escape(&palette.name), escape(&palette.src))?;
+        }
+        writeln!(xml, "\t</PaletteList>")?;
+
+        writeln!(xml, "\t<RasterList>")?;
+        for (idx, raster) in self.rasters.iter().enumerate() {
+            writeln!(xml, r#"		<Raster id="{:X}" palette="{:X}" src="{}"/>"#,
+                idx, raster.palette, escape(&raster.src))?;
+        }
+        writeln!(xml, "\t</RasterList>")?;
+
+        writeln!(xml, "\t<AnimationList>")?;
+        for animation in &self.animations {
+            writeln!(xml, r#"		<Animation name="{}">"#, escape(&animation.name))?;
+            for component in &animation.components {
+                match &component.name {
+                    Some(name) => writeln!(xml, r#"			<Component name="{}" xyz="{}">"#,
+                        escape(name), escape(&component.xyz))?,
+                    None => writeln!(xml, r#"			<Component xyz="{}">"#, escape(&component.xyz))?,
+                }
+                for command in &component.commands {
+                    writeln!(xml, r#"				<Command val="{:X}"/>"#, command)?;
+                }
+                writeln!(xml, "\t\t\t</Component>")?;
+            }
+            writeln!(xml, "\t\t</Animation>")?;
+        }
+        writeln!(xml, "\t</AnimationList>")?;
+
+        writeln!(xml, "</SpriteSheet>")
+    }
+
+    pub fn palette_by_name(&self, name: &str) -> Option<usize> {
+        self.palettes.iter().position(|p| p.name == name).or_else(|| {
+            self.palette_aliases.iter()
+                .find(|(alias, _)| alias == name)
+                .map(|&(_, idx)| idx)
+        })
+    }
+
+    pub fn animation_by_name(&self, name: &str) -> Option<usize> {
+        self.animations.iter().position(|a| a.name == name)
+    }
+
+    /// Adds another sheet's palettes, rasters and animations after this one's, renaming their
+    /// files with `rename`. Raster and palette indices in the appended animations are adjusted to
+    /// match.
+    pub fn append(&mut self, other: &SpriteSheet, rename: impl Fn(&str) -> String) {
+        let palette_offset = self.palettes.len();
+        let raster_offset = self.rasters.len();
+        let rename = |src: &str| if src.is_empty() { String::new() } else { rename(src) };
+
+        self.palettes.extend(other.palettes.iter().map(|palette| Palette {
+            name: palette.name.clone(),
+            src: rename(&palette.src),
+        }));
+
+        self.rasters.extend(other.rasters.iter().map(|raster| Raster {
+            palette: raster.palette + palette_offset,
+            src: rename(&raster.src),
+        }));
+
+        self.animations.extend(other.animations.iter().map(|animation| Animation {
+            name: animation.name.clone(),
+            components: animation.components.iter()
+                .map(|component| component.remap(
+                    |raster| raster + raster_offset,
+                    |palette| palette + palette_offset,
+                ))
+                .collect(),
+        }));
+    }
+
+    /// Removes rasters that are duplicates of earlier ones. `replacements` gives, for each raster,
+    /// the index of the raster to show instead, or its own index to keep it. Animations are changed
+    /// to show the replacements.
+    pub fn merge_rasters(&mut self, replacements: &[usize]) {
+        let (rasters, new_indices) = merge(self.rasters.drain(..), replacements);
+        self.rasters = rasters;
+
+        for animation in &mut self.animations {
+            for component in &mut animation.components {
+                *component = component.remap(
+                    |raster| new_indices.get(raster).copied().unwrap_or(raster),
+                    |palette| palette,
+                );
+            }
+        }
+    }
+
+    /// Removes palettes that are duplicates of earlier ones, like [SpriteSheet::merge_rasters].
+    /// Rasters and animations are changed to use the replacements, and the removed palettes' names
+    /// become aliases for them.
+    pub fn merge_palettes(&mut self, replacements: &[usize]) {
+        let old_names: Vec<String> = self.palettes.iter().map(|p| p.name.clone()).collect();
+        let (palettes, new_indices) = merge(self.palettes.drain(..), replacements);
+        self.palettes = palettes;
+
+        for (_, palette) in &mut self.palette_aliases {
+            *palette = new_indices.get(*palette).copied().unwrap_or(*palette);
+        }
+        for (idx, name) in old_names.into_iter().enumerate() {
+            if replacements[idx] != idx {
+                self.palette_aliases.push((name, new_indices[idx]));
+            }
+        }
+
+        for raster in &mut self.rasters {
+            raster.palette = new_indices.get(raster.palette).copied().unwrap_or(raster.palette);
+        }
+        for animation in &mut self.animations {
+            for component in &mut animation.components {
+                *component = component.remap(
+                    |raster| raster,
+                    |palette| new_indices.get(palette).copied().unwrap_or(palette),
+                );
+            }
+        }
+    }
+
+    /// Names of the animations which show the given raster.
+    pub fn animations_using_raster(&self, raster: usize) -> Vec<&str> {
+        self.animations.iter()
+            .filter(|animation| animation.components.iter()
+                .flat_map(Component::decode)
+                .any(|command| command == Command::SetRaster(raster)))
+            .map(|animation| animation.name.as_str())
+            .collect()
+    }
+}
+
+impl Component {
+    /// Decodes this component's commands. Commands are a 4-bit opcode and 12-bit value, and some
+    /// take further arguments:
+    ///
+    /// - `0VVV` wait VVV frames
+    /// - `1RRR` show raster RRR
+    /// - `2III` go to command index III
+    /// - `3VVV XXXX YYYY ZZZZ` set position
+    /// - `4XXX YYYY ZZZZ` set rotation
+    /// - `5VVV PPPP` set scale
+    /// - `6PPP` set palette PPP
+    /// - `7III NNNN` loop back to command index III, NNNN times
+    pub fn decode(&self) -> Vec<Command> {
+        self.decode_with_offsets()
+            .into_iter()
+            .map(|(_, command)| command)
+            .collect()
+    }
+
+    /// Decodes this component's commands, alongside the index of the word each starts at. These
+    /// are the indices that [Command::Goto] and [Command::Loop] refer to.
+    pub fn decode_with_offsets(&self) -> Vec<(usize, Command)> {
+        let mut decoded = Vec::new();
+        let mut words = self.commands.iter().copied().enumerate();
+
+        while let Some((offset, word)) = words.next() {
+            let value = word & 0xFFF;
+            let mut arg = || words.next().map(|(_, word)| word).unwrap_or(0);
+
+            decoded.push((offset, match word >> 12 {
+                0x0 => Command::Wait(value),
+                0x1 => Command::SetRaster(value as usize),
+                0x2 => Command::Goto(value as usize),
+                0x3 => Command::SetPosition(arg() as i16, arg() as i16, arg() as i16),
+                0x4 => Command::SetRotation(sign_extend_12(value), arg() as i16, arg() as i16),
+                0x5 => Command::SetScale(value, arg()),
+                0x6 => Command::SetPalette(value as usize),
+                0x7 => Command::Loop { to: value as usize, count: arg() },
+                _ => Command::Other(word),
+            }));
+        }
+
+        decoded
+    }
+}
+
+impl Component {
+    /// Copies this component, changing the rasters and palettes it shows.
+    fn remap(&self, raster: impl Fn(usize) -> usize, palette: impl Fn(usize) -> usize) -> Component {
+        let mut commands = self.commands.clone();
+
+        for (offset, command) in self.decode_with_offsets() {
+            match command {
+                Command::SetRaster(idx) => commands[offset] = 0x1000 | raster(idx) as u16,
+                Command::SetPalette(idx) => commands[offset] = 0x6000 | palette(idx) as u16,
+                _ => (),
+            }
+        }
+
+        Component { commands, ..self.clone() }
+    }
+}
+
+/// Escapes text for an XML attribute value, as names can contain any character.
+fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// Keeps the items whose replacement is themselves, returning them and the new index of every item.
+fn merge<T>(items: impl Iterator<Item = T>, replacements: &[usize]) -> (Vec<T>, Vec<usize>) {
+    let mut new_indices = Vec::with_capacity(replacements.len());
+    let mut kept = Vec::new();
+
+    // Replacements always come first, so already have their new index.
+    for (idx, item) in items.enumerate() {
+        if replacements[idx] == idx {
+            new_indices.push(kept.len());
+            kept.push(item);
+        } else {
+            new_indices.push(new_indices[replacements[idx]]);
+        }
+    }
+    (kept, new_indices)
+}
+
+fn sign_extend_12(value: u16) -> i16 {
+    ((value << 4) as i16) >> 4
+}
@@ -0,0 +1,132 @@
+use crate::prelude::*;
+use crate::diagnostics::Span;
+use super::{Sprite, SpriteLoadError};
+use super::image::IndexedImage;
+
+use std::collections::HashMap;
+
+/// Sprite rasters are 4-bit colour-indexed.
+const MAX_COLORS: usize = 16;
+
+/// Raster dimensions are stored as single bytes.
+const MAX_SIZE: u32 = 255;
+
+impl Sprite {
+    /// Checks every raster and palette can be displayed by the game, returning all problems found.
+    /// Missing files are skipped.
+    pub fn validate(&self) -> Vec<SpriteLoadError> {
+        let description_path = self.description_path();
+        let description = fs::read_to_string(&description_path).unwrap_or_default();
+        let span = |src: &str| {
+            let line_idx = description.lines().position(|line| line.contains(&format!("\"{}\"", src)))?;
+            Span::find(&description_path, &description, line_idx + 1, src)
+        };
+
+        let mut errors = Vec::new();
+        let mut palette_images: HashMap<usize, Option<IndexedImage>> = HashMap::new();
+
+        for (idx, raster) in self.sheet.rasters.iter().enumerate() {
+            let animations = || self.sheet.animations_using_raster(idx)
+                .into_iter()
+                .map(str::to_owned)
+                .collect::<Vec<_>>();
+
+            let image = match self.image(&raster.src) {
+                None => continue,
+                Some(Ok(image)) => image,
+                Some(Err(error)) => {
+                    errors.push(SpriteLoadError::BadImage {
+                        span: span(&raster.src),
+                        src: raster.src.clone(),
+                        animations: animations(),
+                        error: Box::new(error),
+                    });
+                    continue;
+                },
+            };
+
+            if image.width == 0 || image.height == 0
+                || image.width > MAX_SIZE || image.height > MAX_SIZE
+                || image.width % 2 != 0
+            {
+                errors.push(SpriteLoadError::BadRasterSize {
+                    span: span(&raster.src),
+                    src: raster.src.clone(),
+                    width: image.width,
+                    height: image.height,
+                    animations: animations(),
+                });
+            }
+
+            let max_index = image.max_index().unwrap_or(0) as usize;
+            if max_index >= MAX_COLORS {
+                errors.push(SpriteLoadError::TooManyColors {
+                    span: span(&raster.src),
+                    src: raster.src.clone(),
+                    index: max_index,
+                    animations: animations(),
+                });
+                continue;
+            }
+
+            let palette = match self.sheet.palettes.get(raster.palette) {
+                Some(palette) => palette,
+                None => {
+                    errors.push(SpriteLoadError::UnknownPaletteIndex {
+                        span: span(&raster.src),
+                        src: raster.src.clone(),
+                        palette: raster.palette,
+                        count: self.sheet.palettes.len(),
+                    });
+                    continue;
+                },
+            };
+
+            // Check each palette once, and only compare rasters against palettes we could read.
+            let palette_image = palette_images.entry(raster.palette)
+                .or_insert_with(|| match self.image(&palette.src) {
+                    Some(Ok(image)) => Some(image),
+                    Some(Err(error)) => {
+                        errors.push(SpriteLoadError::BadImage {
+                            span: span(&palette.src),
+                            src: palette.src.clone(),
+                            animations: Vec::new(),
+                            error: Box::new(error),
+                        });
+                        None
+                    },
+                    None => None,
+                });
+
+            if let Some(palette_image) = palette_image {
+                if max_index >= palette_image.palette.len() {
+                    errors.push(SpriteLoadError::PaletteTooSmall {
+                        span: span(&raster.src),
+                        src: raster.src.clone(),
+                        index: max_index,
+                        palette: palette.name.clone(),
+                        count: palette_image.palette.len(),
+                    });
+                }
+            }
+        }
+
+        errors
+    }
+
+    /// Reads an image, which may be generated rather than on disk. Returns `None` if it is missing.
+    pub(super) fn image(&self, src: &str) -> Option<Result<IndexedImage, super::image::ReadPngError>> {
+        let (sprite, src) = self.source(src);
+
+        if let Some(image) = sprite.manifest.as_ref().and_then(|m| m.generated_image(src)) {
+            return Some(Ok(image.clone()));
+        }
+
+        let path = sprite.files_dir().join(src);
+        if !path.is_file() {
+            return None;
+        }
+
+        Some(IndexedImage::read_png(&path))
+    }
+}
@@ -0,0 +1,428 @@
+//! Strings in formats translators' tools understand: gettext PO files and CSV spreadsheets.
+
+use crate::prelude::*;
+use crate::diagnostics::{Diagnostic, Span, Code};
+use super::MarkupError;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Po,
+    Csv,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "po" => Ok(Format::Po),
+            "csv" => Ok(Format::Csv),
+            _ => Err(format!("unknown string format '{}' (expected po or csv)", s)),
+        }
+    }
+}
+
+impl Format {
+    /// Guesses the format of a file from its extension.
+    pub fn from_path(path: &Path) -> Option<Format> {
+        path.extension()?.to_str()?.to_lowercase().parse().ok()
+    }
+}
+
+/// A string and its translation, if it has one yet.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Entry {
+    /// Export name of the string.
+    pub name: String,
+
+    /// `None` if starpkg picks the section.
+    pub section: Option<u8>,
+
+    /// Where the string is, as `file:line`.
+    pub context: String,
+
+    pub source: String,
+
+    /// Empty if untranslated.
+    pub translation: String,
+}
+
+pub fn write(format: Format, entries: &[Entry], locale: Option<&str>) -> String {
+    match format {
+        Format::Po => write_po(entries, locale),
+        Format::Csv => write_csv(entries),
+    }
+}
+
+/// Reads entries from a file, alongside the line each starts on.
+pub fn parse(format: Format, path: &Path, source: &str) -> Result<Vec<(usize, Entry)>, ImportError> {
+    match format {
+        Format::Po => parse_po(path, source),
+        Format::Csv => parse_csv(path, source),
+    }
+}
+
+fn section_name(section: Option<u8>) -> String {
+    match section {
+        Some(section) => format!("{:02X}", section),
+        None => "auto".to_owned(),
+    }
+}
+
+fn parse_section(s: &str) -> Option<u8> {
+    u8::from_str_radix(s.trim(), 16).ok()
+}
+
+fn write_po(entries: &[Entry], locale: Option<&str>) -> String {
+    let mut po = String::new();
+
+    // The header entry, which gettext tools expect.
+    po.push_str("msgid \"\"\nmsgstr \"\"\n\"Content-Type: text/plain; charset=UTF-8\\n\"\n");
+    if let Some(locale) = locale {
+        po.push_str(&format!("\"Language: {}\\n\"\n", locale));
+    }
+
+    for entry in entries {
+        po.push('\n');
+        po.push_str(&format!("#: {}\n", entry.context));
+        po.push_str(&format!("#. section: {}\n", section_name(entry.section)));
+        po.push_str(&format!("msgctxt {}\n", po_string(&entry.name)));
+        po.push_str(&format!("msgid {}\n", po_string(&entry.source)));
+        po.push_str(&format!("msgstr {}\n", po_string(&entry.translation)));
+    }
+
+    po
+}
+
+/// Quotes a string for a PO file, splitting it over lines after each newline.
+fn po_string(s: &str) -> String {
+    let escape = |s: &str| s
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n");
+
+    if !s.contains('\n') {
+        return format!("\"{}\"", escape(s));
+    }
+
+    let mut quoted = "\"\"".to_owned();
+    for line in s.split_inclusive('\n') {
+        quoted.push_str(&format!("\n\"{}\"", escape(line)));
+    }
+    quoted
+}
+
+fn parse_po(path: &Path, source: &str) -> Result<Vec<(usize, Entry)>, ImportError> {
+    #[derive(Clone, Copy, PartialEq, Eq)]
+    enum Field {
+        Ctxt,
+        Id,
+        Str,
+    }
+
+    let mut entries = Vec::new();
+    let mut entry: Option<(usize, Entry)> = None;
+    let mut field = None;
+
+    // Lines of entries marked `#, fuzzy`, whose translations are guesses awaiting review.
+    let mut fuzzy = Vec::new();
+
+    let malformed = |line_no: usize, line: &str, reason: &str| ImportError::Malformed {
+        span: Span::find(path, source, line_no, line),
+        reason: reason.to_owned(),
+    };
+
+    for (line_idx, line) in source.lines().enumerate() {
+        let line_no = line_idx + 1;
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with('#') {
+            // Comments come before the entry they're about.
+            if field == Some(Field::Str) {
+                entries.extend(entry.take());
+                field = None;
+            }
+
+            if let Some(section) = line.strip_prefix("#. section:") {
+                let entry = entry.get_or_insert_with(|| (line_no, Entry::default()));
+                entry.1.section = parse_section(section);
+            } else if let Some(context) = line.strip_prefix("#:") {
+                let entry = entry.get_or_insert_with(|| (line_no, Entry::default()));
+                entry.1.context = context.trim().to_owned();
+            } else if let Some(flags) = line.strip_prefix("#,") {
+                let entry = entry.get_or_insert_with(|| (line_no, Entry::default()));
+                if flags.split(',').any(|flag| flag.trim() == "fuzzy") {
+                    fuzzy.push(entry.0);
+                }
+            }
+            continue;
+        }
+
+        let (keyword, rest) = match line.find(char::is_whitespace) {
+            Some(idx) if !line.starts_with('"') => (&line[..idx], line[idx..].trim()),
+            _ => ("", line),
+        };
+
+        let value = unquote_po(rest).ok_or_else(|| malformed(line_no, line, "expected a quoted string"))?;
+
+        let next_field = match keyword {
+            "msgctxt" => Some(Field::Ctxt),
+            "msgid" => Some(Field::Id),
+            "msgstr" => Some(Field::Str),
+            "" => None,
+            _ => return Err(malformed(line_no, keyword, "unknown keyword")),
+        };
+
+        if let Some(next_field) = next_field {
+            // A new entry starts with its `msgctxt` or `msgid`, after the previous one's `msgstr`.
+            if field == Some(Field::Str) && next_field != Field::Str {
+                entries.extend(entry.take());
+            }
+            if entry.is_none() {
+                entry = Some((line_no, Entry::default()));
+            }
+            field = Some(next_field);
+        }
+
+        let entry = &mut entry.as_mut()
+            .ok_or_else(|| malformed(line_no, line, "string without a keyword before it"))?
+            .1;
+
+        match field {
+            Some(Field::Ctxt) => entry.name.push_str(&value),
+            Some(Field::Id) => entry.source.push_str(&value),
+            Some(Field::Str) => entry.translation.push_str(&value),
+            None => return Err(malformed(line_no, line, "string without a keyword before it")),
+        }
+    }
+    entries.extend(entry);
+
+    // The header entry has no name, and isn't a string.
+    Ok(entries.into_iter()
+        .filter(|(line_no, entry)| !entry.name.is_empty() && !fuzzy.contains(line_no))
+        .collect())
+}
+
+fn unquote_po(s: &str) -> Option<String> {
+    let inner = s.strip_prefix('"')?.strip_suffix('"')?;
+    let mut unquoted = String::new();
+    let mut chars = inner.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unquoted.push(c);
+            continue;
+        }
+
+        unquoted.push(match chars.next()? {
+            'n' => '\n',
+            't' => '\t',
+            c => c,
+        });
+    }
+
+    Some(unquoted)
+}
+
+const CSV_HEADER: [&str; 5] = ["name", "section", "context", "source", "translation"];
+
+fn write_csv(entries: &[Entry]) -> String {
+    let mut csv = CSV_HEADER.join(",");
+    csv.push_str("\r\n");
+
+    for entry in entries {
+        let fields = [
+            entry.name.as_str(),
+            &section_name(entry.section),
+            &entry.context,
+            &entry.source,
+            &entry.translation,
+        ];
+
+        csv.push_str(&fields.iter().map(|field| csv_field(field)).collect::<Vec<_>>().join(","));
+        csv.push_str("\r\n");
+    }
+
+    csv
+}
+
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_owned()
+    }
+}
+
+fn parse_csv(path: &Path, source: &str) -> Result<Vec<(usize, Entry)>, ImportError> {
+    let records = csv_records(source).map_err(|line_no| ImportError::Malformed {
+        span: Span::find(path, source, line_no, "\""),
+        reason: "unclosed quotes".to_owned(),
+    })?;
+
+    let mut records = records.into_iter();
+    let header = match records.next() {
+        Some((_, header)) => header,
+        None => return Ok(Vec::new()),
+    };
+
+    let column = |name: &str| header.iter().position(|field| field.trim() == name);
+    let missing = |name: &str| ImportError::Malformed {
+        span: Span::find(path, source, 1, header.first().map(String::as_str).unwrap_or_default()),
+        reason: format!("no `{}` column", name),
+    };
+
+    let name_col = column("name").ok_or_else(|| missing("name"))?;
+    let translation_col = column("translation").ok_or_else(|| missing("translation"))?;
+    let section_col = column("section");
+    let context_col = column("context");
+    let source_col = column("source");
+
+    Ok(records
+        .filter(|(_, fields)| fields.iter().any(|field| !field.is_empty()))
+        .map(|(line_no, fields)| {
+            let field = |col: Option<usize>| col
+                .and_then(|col| fields.get(col))
+                .cloned()
+                .unwrap_or_default();
+
+            (line_no, Entry {
+                name: field(Some(name_col)),
+                section: section_col.and_then(|col| fields.get(col)).and_then(|s| parse_section(s)),
+                context: field(context_col),
+                source: field(source_col),
+                translation: field(Some(translation_col)),
+            })
+        })
+        .collect())
+}
+
+/// Splits a CSV file into records, alongside the line each starts on. Fails with the line of an
+/// unclosed quote.
+fn csv_records(source: &str) -> Result<Vec<(usize, Vec<String>)>, usize> {
+    let mut records = Vec::new();
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut line_no = 1;
+    let mut record_line = 1;
+    let mut quote_line = None;
+    let mut chars = source.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match (c, quote_line) {
+            ('"', Some(_)) if chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            },
+            ('"', Some(_)) => quote_line = None,
+            ('"', None) if field.is_empty() => quote_line = Some(line_no),
+            (',', None) => fields.push(std::mem::take(&mut field)),
+            ('\r', None) => (),
+            ('\n', None) => {
+                fields.push(std::mem::take(&mut field));
+                records.push((record_line, std::mem::take(&mut fields)));
+                line_no += 1;
+                record_line = line_no;
+            },
+            (c, _) => {
+                if c == '\n' {
+                    line_no += 1;
+                }
+                if c != '\r' {
+                    field.push(c);
+                }
+            },
+        }
+    }
+
+    if let Some(line) = quote_line {
+        return Err(line);
+    }
+
+    if !field.is_empty() || !fields.is_empty() {
+        fields.push(field);
+        records.push((record_line, fields));
+    }
+
+    Ok(records)
+}
+
+#[derive(Error, Debug)]
+pub enum ImportError {
+    #[error("malformed translation file: {reason}")]
+    Malformed {
+        span: Option<Span>,
+        reason: String,
+    },
+
+    #[error("no string named '{name}' to translate")]
+    UnknownString {
+        span: Option<Span>,
+        name: String,
+    },
+
+    #[error("translation of '{name}' has bad markup: {error}")]
+    BadTranslation {
+        span: Option<Span>,
+        name: String,
+        #[source]
+        error: MarkupError,
+    },
+    #[error("translation of '{name}' doesn't keep the tags of the source text")]
+    MismatchedTags {
+        span: Option<Span>,
+        name: String,
+        missing: Vec<String>,
+        added: Vec<String>,
+    },
+
+    #[error("translation of '{name}' has a line starting with `#`")]
+    DirectiveLine {
+        span: Option<Span>,
+        name: String,
+    },
+}
+
+impl Code for ImportError {
+    fn code(&self) -> Option<&'static str> {
+        match self {
+            ImportError::Malformed { .. } => Some("SP0308"),
+            ImportError::UnknownString { .. } => Some("SP0309"),
+            ImportError::BadTranslation { error, .. } => error.code(),
+            ImportError::MismatchedTags { .. } => Some("SP0315"),
+            ImportError::DirectiveLine { .. } => Some("SP0316"),
+        }
+    }
+}
+
+impl From<ImportError> for Diagnostic {
+    fn from(error: ImportError) -> Diagnostic {
+        let diagnostic = Diagnostic::error(&error).with_code(error.code());
+
+        match error {
+            ImportError::Malformed { span, .. } => diagnostic
+                .with_label(span, "here"),
+            ImportError::UnknownString { span, .. } => diagnostic
+                .with_label(span, "unknown string")
+                .with_help("export the strings again to get their current names"),
+            ImportError::BadTranslation { span, .. } => diagnostic
+                .with_label(span, "translation rejected")
+                .with_help("end with `[END]`, and close every tag"),
+            ImportError::MismatchedTags { span, missing, added, .. } => {
+                let mut diagnostic = diagnostic.with_label(span, "translation rejected");
+                if !missing.is_empty() {
+                    diagnostic = diagnostic.with_note(format!("missing {}", missing.join(", ")));
+                }
+                if !added.is_empty() {
+                    diagnostic = diagnostic.with_note(format!("adds {}", added.join(", ")));
+                }
+                diagnostic.with_help("keep the tags of the source text; only `[BR]`, `[NEXT]` and `[WAIT]` may differ")
+            },
+            ImportError::DirectiveLine { span, .. } => diagnostic
+                .with_label(span, "translation rejected")
+                .with_help("a line starting with `#` would begin a new block; start it with something else"),
+        }
+    }
+}
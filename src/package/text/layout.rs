@@ -0,0 +1,379 @@
+//! Measuring strings as the game lays them out, to catch text that doesn't fit its box.
+//!
+//! A string's lines are those of its source, plus those started by `[BR]`; `[NEXT]` starts a new
+//! page. Lines with no visible text (e.g. only tags) take up no room.
+//!
+//! Expressions are measured as taking up no room, since their text isn't known yet.
+
+/// A font's glyph widths, in pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Font {
+    /// Also used by `[FONT:MENU]`, which shares its glyphs.
+    Standard,
+
+    /// Also used by `[FONT:SUBTITLE]`.
+    Title,
+}
+
+/// Widths of the standard font's glyphs for `' '` to `'~'`, in pixels. `\` is the game's `¥`.
+const STANDARD_WIDTHS: [u8; 95] = [
+//  ' '  !   "   #   $   %   &   '   (   )   *   +   ,   -   .   /
+    6,   5,  7,  10, 9,  11, 10, 4,  6,  6,  8,  9,  4,  7,  4,  8,
+//  0    1   2   3   4   5   6   7   8   9   :   ;   <   =   >   ?
+    9,   6,  9,  9,  9,  9,  9,  9,  9,  9,  4,  4,  8,  9,  8,  9,
+//  @    A   B   C   D   E   F   G   H   I   J   K   L   M   N   O
+    11,  10, 9,  9,  10, 8,  8,  10, 10, 4,  8,  9,  8,  12, 10, 10,
+//  P    Q   R   S   T   U   V   W   X   Y   Z   [   ¥   ]   ^   _
+    9,   10, 9,  9,  9,  10, 10, 13, 9,  9,  9,  6,  10, 6,  8,  9,
+//  `    a   b   c   d   e   f   g   h   i   j   k   l   m   n   o
+    5,   8,  8,  7,  8,  8,  6,  8,  8,  4,  5,  8,  4,  12, 8,  8,
+//  p    q   r   s   t   u   v   w   x   y   z   {   |   }   ~
+    8,   8,  6,  7,  6,  8,  8,  11, 8,  8,  7,  6,  4,  6,  9,
+];
+
+/// Widths of the title font's glyphs for `' '` to `'~'`, in pixels.
+const TITLE_WIDTHS: [u8; 95] = [
+//  ' '  !   "   #   $   %   &   '   (   )   *   +   ,   -   .   /
+    8,   6,  9,  13, 12, 14, 13, 5,  8,  8,  10, 12, 5,  9,  5,  10,
+//  0    1   2   3   4   5   6   7   8   9   :   ;   <   =   >   ?
+    12,  8,  12, 12, 12, 12, 12, 12, 12, 12, 5,  5,  10, 12, 10, 12,
+//  @    A   B   C   D   E   F   G   H   I   J   K   L   M   N   O
+    14,  13, 12, 12, 13, 11, 11, 13, 13, 6,  10, 12, 10, 15, 13, 13,
+//  P    Q   R   S   T   U   V   W   X   Y   Z   [   ¥   ]   ^   _
+    12,  13, 12, 12, 12, 13, 13, 16, 12, 12, 12, 8,  13, 8,  10, 12,
+//  `    a   b   c   d   e   f   g   h   i   j   k   l   m   n   o
+    6,   11, 11, 9,  11, 11, 8,  11, 11, 5,  6,  11, 5,  15, 11, 11,
+//  p    q   r   s   t   u   v   w   x   y   z   {   |   }   ~
+    11,  11, 8,  9,  8,  11, 11, 14, 11, 11, 9,  8,  5,  8,  12,
+];
+
+impl Font {
+    fn from_arg(arg: &str) -> Font {
+        match arg {
+            "TITLE" | "SUBTITLE" => Font::Title,
+            _ => Font::Standard,
+        }
+    }
+
+    fn glyph_width(self, c: char) -> u32 {
+        let widths = match self {
+            Font::Standard => &STANDARD_WIDTHS,
+            Font::Title => &TITLE_WIDTHS,
+        };
+
+        // Accented letters are as wide as the letter they accent, and anything else Star Rod can
+        // encode as a capital `M`.
+        let c = match base_letter(c) {
+            ' '..='~' => base_letter(c),
+            _ => 'M',
+        };
+        u32::from(widths[c as usize - ' ' as usize])
+    }
+}
+
+/// The letter an accented letter accents, or the character itself.
+fn base_letter(c: char) -> char {
+    match c {
+        'À' | 'Á' | 'Â' | 'Ä' => 'A',
+        'à' | 'á' | 'â' | 'ä' | 'ª' => 'a',
+        'Ç' => 'C',
+        'ç' => 'c',
+        'È' | 'É' | 'Ê' | 'Ë' => 'E',
+        'è' | 'é' | 'ê' | 'ë' => 'e',
+        'Ì' | 'Í' | 'Î' | 'Ï' => 'I',
+        'ì' | 'í' | 'î' | 'ï' => 'i',
+        'Ñ' => 'N',
+        'ñ' => 'n',
+        'Ò' | 'Ó' | 'Ô' | 'Ö' => 'O',
+        'ò' | 'ó' | 'ô' | 'ö' => 'o',
+        'Ù' | 'Ú' | 'Û' | 'Ü' => 'U',
+        'ù' | 'ú' | 'û' | 'ü' => 'u',
+        'ß' => 'B',
+        '¡' => '!',
+        '¿' => '?',
+        '¥' => '\\',
+        _ => c,
+    }
+}
+
+/// The room in a `[STYLE]`'s box.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BoxSize {
+    /// What the box is called, for messages.
+    pub name: &'static str,
+    pub width: u32,
+    pub lines: usize,
+}
+
+/// The box strings appear in when they don't give a `[STYLE]`.
+const SPEECH_BUBBLE: BoxSize = BoxSize { name: "a speech bubble", width: 232, lines: 3 };
+
+/// The box for the given `[STYLE]`, or `None` if its size isn't fixed (e.g. `CHOICE`, which grows
+/// to fit its options).
+fn box_size(style: Option<&str>) -> Option<BoxSize> {
+    let (name, width, lines) = match style {
+        None | Some("RIGHT") | Some("LEFT") | Some("CENTER") => return Some(SPEECH_BUBBLE),
+        Some("TATTLE") => ("a tattle box", 232, 3),
+        Some("INSPECT") => ("an inspect box", 256, 3),
+        Some("SIGN") => ("a sign", 200, 4),
+        Some("LAMPPOST") => ("a lamppost sign", 160, 4),
+        Some("POSTCARD") => ("a postcard", 200, 5),
+        Some("POPUP") => ("a popup", 256, 1),
+        Some("UPGRADE") => ("an upgrade box", 200, 3),
+        Some("NARRATE") => ("a narration box", 256, 3),
+        Some("EPILOGUE") => ("an epilogue box", 280, 4),
+        Some(_) => return None,
+    };
+
+    Some(BoxSize { name, width, lines })
+}
+
+/// A line or page that doesn't fit its box.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Overflow {
+    TooWide {
+        /// Index into the lines given to [check].
+        line: usize,
+        width: u32,
+        size: BoxSize,
+    },
+
+    TooManyLines {
+        /// Index into the lines given to [check] of the first line that doesn't fit.
+        line: usize,
+        count: usize,
+        size: BoxSize,
+    },
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Token<'a> {
+    /// A tag's name and argument, without brackets.
+    Tag(&'a str, Option<&'a str>),
+    Text(&'a str),
+}
+
+fn tokens(line: &str) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+    let mut rest = line;
+
+    while !rest.is_empty() {
+        let tag_len = if rest.starts_with('[') { rest.find(']').map(|idx| idx + 1) } else { None };
+
+        match tag_len {
+            Some(len) => {
+                let inner = &rest[1..len - 1];
+                tokens.push(match inner.find(':') {
+                    Some(idx) => Token::Tag(&inner[..idx], Some(&inner[idx + 1..])),
+                    None => Token::Tag(inner, None),
+                });
+                rest = &rest[len..];
+            },
+            None => {
+                // Unbalanced brackets are reported by markup checking; treat them as text here.
+                let len = rest[1..].find('[').map(|idx| idx + 1).unwrap_or_else(|| rest.len());
+                tokens.push(Token::Text(&rest[..len]));
+                rest = &rest[len..];
+            },
+        }
+    }
+
+    tokens
+}
+
+/// Where layout is up to.
+#[derive(Debug, Clone)]
+struct State<'a> {
+    font: Font,
+    style: Option<&'a str>,
+
+    /// Visible lines on the current page so far.
+    page_lines: usize,
+
+    /// The first line of the current page that doesn't fit, if any.
+    page_overflow: Option<usize>,
+}
+
+impl<'a> State<'a> {
+    fn new() -> State<'a> {
+        State {
+            font: Font::Standard,
+            style: None,
+            page_lines: 0,
+            page_overflow: None,
+        }
+    }
+
+    fn box_size(&self) -> Option<BoxSize> {
+        box_size(self.style)
+    }
+
+    fn width(&self, text: &str) -> u32 {
+        let mut in_expression = false;
+
+        text.chars()
+            .filter(|&c| match c {
+                '{' => { in_expression = true; false },
+                '}' if in_expression => { in_expression = false; false },
+                _ => !in_expression,
+            })
+            .map(|c| self.font.glyph_width(c))
+            .sum()
+    }
+}
+
+/// Lays out a string a token at a time, collecting what doesn't fit.
+struct Layout<'a, 's> {
+    state: State<'s>,
+    overflows: &'a mut Vec<Overflow>,
+
+    /// Index of the source line being laid out.
+    line: usize,
+    width: u32,
+    visible: bool,
+}
+
+impl<'a, 's> Layout<'a, 's> {
+    fn text(&mut self, text: &str) {
+        self.width += self.state.width(text);
+        self.visible |= !text.trim().is_empty();
+    }
+
+    fn tag(&mut self, name: &'s str, arg: Option<&'s str>) {
+        match (name, arg) {
+            ("FONT", Some(arg)) => self.state.font = Font::from_arg(arg),
+            // Only a string's first style decides its box.
+            ("STYLE", Some(arg)) if self.state.style.is_none() => self.state.style = Some(arg),
+            ("BR", _) => self.end_line(),
+            ("NEXT", _) => {
+                self.end_line();
+                self.end_page();
+            },
+            _ => (),
+        }
+    }
+
+    fn end_line(&mut self) {
+        if !self.visible {
+            return;
+        }
+
+        if let Some(size) = self.state.box_size() {
+            if self.width > size.width {
+                self.overflows.push(Overflow::TooWide { line: self.line, width: self.width, size });
+            }
+
+            self.state.page_lines += 1;
+            if self.state.page_lines > size.lines && self.state.page_overflow.is_none() {
+                self.state.page_overflow = Some(self.line);
+            }
+        }
+
+        self.width = 0;
+        self.visible = false;
+    }
+
+    fn end_page(&mut self) {
+        if let (Some(line), Some(size)) = (self.state.page_overflow, self.state.box_size()) {
+            self.overflows.push(Overflow::TooManyLines { line, count: self.state.page_lines, size });
+        }
+
+        self.state.page_lines = 0;
+        self.state.page_overflow = None;
+    }
+}
+
+/// Finds every line and page of a string, given line by line, that doesn't fit in its box.
+pub fn check(lines: &[&str]) -> Vec<Overflow> {
+    let mut overflows = Vec::new();
+    let mut layout = Layout {
+        state: State::new(),
+        overflows: &mut overflows,
+        line: 0,
+        width: 0,
+        visible: false,
+    };
+
+    for (line_idx, line) in lines.iter().enumerate() {
+        layout.line = line_idx;
+
+        for token in tokens(line) {
+            match token {
+                Token::Tag(name, arg) => layout.tag(name, arg),
+                Token::Text(text) => layout.text(text),
+            }
+        }
+
+        layout.end_line();
+    }
+    layout.end_page();
+
+    overflows.sort_by_key(|overflow| match overflow {
+        Overflow::TooWide { line, .. } | Overflow::TooManyLines { line, .. } => *line,
+    });
+    overflows
+}
+
+/// Breaks lines of a string, given line by line, at spaces so that they fit the width of its box.
+/// Returns the new lines, each with the index of the line it came from.
+pub fn wrap(lines: &[&str]) -> Vec<(usize, String)> {
+    let mut wrapped = Vec::new();
+    let mut state = State::new();
+
+    for (line_idx, line) in lines.iter().enumerate() {
+        let mut out = String::new();
+        let mut width = 0;
+
+        for token in tokens(line) {
+            let text = match token {
+                Token::Tag(name, arg) => {
+                    match (name, arg) {
+                        ("FONT", Some(arg)) => state.font = Font::from_arg(arg),
+                        ("STYLE", Some(arg)) if state.style.is_none() => state.style = Some(arg),
+                        ("BR", _) | ("NEXT", _) => width = 0,
+                        _ => (),
+                    }
+
+                    out.push_str(&write_tag(name, arg));
+                    continue;
+                },
+                Token::Text(text) => text,
+            };
+
+            let max_width = match state.box_size() {
+                Some(size) => size.width,
+                None => {
+                    out.push_str(text);
+                    continue;
+                },
+            };
+
+            // Each word keeps the spaces before it, which are dropped if it starts a new line.
+            for word in text.split_inclusive(' ') {
+                let word_width = state.width(word.trim_end());
+
+                if width > 0 && width + word_width > max_width {
+                    let trimmed = out.trim_end().len();
+                    out.truncate(trimmed);
+                    wrapped.push((line_idx, std::mem::take(&mut out)));
+                    width = 0;
+                }
+
+                out.push_str(word);
+                width += state.width(word);
+            }
+        }
+
+        wrapped.push((line_idx, out));
+    }
+
+    wrapped
+}
+
+/// Writes a tag back out.
+fn write_tag(name: &str, arg: Option<&str>) -> String {
+    match arg {
+        Some(arg) => format!("[{}:{}]", name, arg),
+        None => format!("[{}]", name),
+    }
+}
@@ -0,0 +1,362 @@
+//! Paper Mario's string markup: `[TAG]` and `[TAG:ARG,ARG]` tags within a string's text.
+
+use crate::prelude::*;
+use crate::diagnostics::Code;
+
+/// What a tag's arguments may be.
+#[derive(Debug, Clone, Copy)]
+enum Args {
+    None,
+
+    /// A single byte, in hex.
+    Byte,
+
+    /// The given number of bytes, in hex and separated by commas.
+    Bytes(usize),
+
+    /// A single number, up to the given maximum.
+    Index(u8),
+
+    /// A single name from a list.
+    OneOf(&'static [&'static str]),
+
+    /// A single name of anything in the game, e.g. an item.
+    Name,
+}
+
+const STYLES: &[&str] = &[
+    "RIGHT", "LEFT", "CENTER", "TATTLE", "CHOICE", "INSPECT", "SIGN", "LAMPPOST", "POSTCARD",
+    "POPUP", "UPGRADE", "NARRATE", "EPILOGUE",
+];
+
+const FONTS: &[&str] = &["STANDARD", "MENU", "TITLE", "SUBTITLE"];
+
+/// Every tag the game understands.
+const TAGS: &[(&str, Args)] = &[
+    // Control.
+    ("END", Args::None),
+    ("BR", Args::None),
+    ("NEXT", Args::None),
+    ("WAIT", Args::None),
+    ("YIELD", Args::None),
+    ("PAUSE", Args::Byte),
+
+    // Appearance.
+    ("STYLE", Args::OneOf(STYLES)),
+    ("FONT", Args::OneOf(FONTS)),
+    ("COLOR", Args::Byte),
+    ("SAVECOLOR", Args::None),
+    ("RESTORECOLOR", Args::None),
+    ("SIZE", Args::Bytes(2)),
+    ("SIZERESET", Args::None),
+    ("SPEED", Args::Bytes(2)),
+    ("DELAY", Args::OneOf(&["ON", "OFF"])),
+    ("SHAKE", Args::None),
+    ("/SHAKE", Args::None),
+    ("WAVE", Args::None),
+    ("/WAVE", Args::None),
+    ("RAINBOW", Args::None),
+    ("/RAINBOW", Args::None),
+
+    // Choices.
+    ("OPTION", Args::Index(5)),
+    ("CURSOR", Args::Index(5)),
+    ("ENDCHOICE", Args::Index(6)),
+
+    // Inserted text and images.
+    ("VAR", Args::Index(7)),
+    ("ITEMICON", Args::Name),
+    ("...", Args::None),
+    ("A", Args::None),
+    ("B", Args::None),
+    ("L", Args::None),
+    ("R", Args::None),
+    ("Z", Args::None),
+    ("START", Args::None),
+    ("C-UP", Args::None),
+    ("C-DOWN", Args::None),
+    ("C-LEFT", Args::None),
+    ("C-RIGHT", Args::None),
+];
+
+/// A markup problem, at a byte offset into one of the string's lines.
+#[derive(Debug)]
+pub struct Problem {
+    /// Index into the lines given to [check].
+    pub line: usize,
+    pub offset: usize,
+
+    /// The offending text.
+    pub text: String,
+    pub error: MarkupError,
+}
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum MarkupError {
+    #[error("unknown markup tag `{tag}`")]
+    UnknownTag {
+        tag: String,
+        suggestion: Option<&'static str>,
+    },
+
+    #[error("bad argument to `{tag}`")]
+    BadArgument {
+        tag: String,
+        expected: String,
+    },
+
+    #[error("`{tag}` is missing its closing `]`")]
+    UnclosedTag {
+        tag: String,
+    },
+
+    #[error("`]` without an opening `[`")]
+    UnopenedTag,
+
+    #[error("string doesn't end with `[END]`")]
+    MissingEnd,
+
+    #[error("text after `[END]`")]
+    TextAfterEnd,
+
+    #[error("`[SAVECOLOR]` is never restored")]
+    UnrestoredColor,
+
+    #[error("`[RESTORECOLOR]` without an earlier `[SAVECOLOR]`")]
+    UnsavedColor,
+}
+
+impl MarkupError {
+    /// Unknown tags are only warned about, as the game may understand tags starpkg doesn't know.
+    pub fn is_warning(&self) -> bool {
+        matches!(self, MarkupError::UnknownTag { .. })
+    }
+}
+
+impl Code for MarkupError {
+    fn code(&self) -> Option<&'static str> {
+        Some(match self {
+            MarkupError::UnknownTag { .. } => "SP0302",
+            MarkupError::BadArgument { .. } => "SP0303",
+            MarkupError::UnclosedTag { .. } => "SP0304",
+            MarkupError::UnopenedTag => "SP0313",
+            MarkupError::MissingEnd => "SP0305",
+            MarkupError::TextAfterEnd => "SP0306",
+            MarkupError::UnrestoredColor => "SP0307",
+            MarkupError::UnsavedColor => "SP0314",
+        })
+    }
+}
+
+/// Checks the markup of a string's text, given line by line, returning every problem found. The
+/// text must end with `[END]`.
+pub fn check(lines: &[&str]) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    let mut end: Option<(usize, usize)> = None;
+    let mut saved_colors: Vec<(usize, usize, String)> = Vec::new();
+
+    for (line_idx, line) in lines.iter().enumerate() {
+        let mut rest = *line;
+        let mut offset = 0;
+        let mut problem = |offset: usize, text: &str, error| problems.push(Problem {
+            line: line_idx,
+            offset,
+            text: text.to_owned(),
+            error,
+        });
+
+        while !rest.is_empty() {
+            let next = rest.find(['[', ']']).unwrap_or(rest.len());
+            let text = &rest[..next];
+
+            if end.is_some() && !text.trim().is_empty() {
+                problem(offset, text.trim_end(), MarkupError::TextAfterEnd);
+                end = None;
+            }
+
+            offset += next;
+            rest = &rest[next..];
+
+            if rest.starts_with(']') {
+                problem(offset, "]", MarkupError::UnopenedTag);
+                offset += 1;
+                rest = &rest[1..];
+                continue;
+            }
+
+            if rest.is_empty() {
+                break;
+            }
+
+            // A tag, which must close before the next one opens.
+            let close = rest[1..].find(['[', ']'])
+                .filter(|&idx| rest.as_bytes()[idx + 1] == b']');
+            let tag = match close {
+                Some(idx) => &rest[..idx + 2],
+                None => {
+                    let unclosed = rest[1..].find('[').map(|idx| &rest[..idx + 1]).unwrap_or(rest);
+                    let tag = unclosed.trim_end();
+                    problem(offset, tag, MarkupError::UnclosedTag { tag: tag.to_owned() });
+                    offset += unclosed.len();
+                    rest = &rest[unclosed.len()..];
+                    continue;
+                },
+            };
+
+            if end.is_some() {
+                problem(offset, tag, MarkupError::TextAfterEnd);
+                end = None;
+            }
+
+            let inner = &tag[1..tag.len() - 1];
+            let (name, args) = match inner.find(':') {
+                Some(idx) => (&inner[..idx], Some(&inner[idx + 1..])),
+                None => (inner, None),
+            };
+
+            match TAGS.iter().find(|(tag_name, _)| *tag_name == name) {
+                None => problem(offset, tag, MarkupError::UnknownTag {
+                    tag: tag.to_owned(),
+                    suggestion: suggest(name),
+                }),
+                Some((_, spec)) => {
+                    if let Some(expected) = check_args(*spec, args) {
+                        problem(offset, tag, MarkupError::BadArgument {
+                            tag: tag.to_owned(),
+                            expected,
+                        });
+                    }
+                },
+            }
+
+            match name {
+                "END" => end = Some((line_idx, offset)),
+                "SAVECOLOR" => saved_colors.push((line_idx, offset, tag.to_owned())),
+                "RESTORECOLOR" if saved_colors.pop().is_none() => {
+                    problem(offset, tag, MarkupError::UnsavedColor);
+                },
+                _ => (),
+            }
+
+            offset += tag.len();
+            rest = &rest[tag.len()..];
+        }
+    }
+
+    for (line, offset, text) in saved_colors {
+        problems.push(Problem {
+            line,
+            offset,
+            text,
+            error: MarkupError::UnrestoredColor,
+        });
+    }
+
+    if end.is_none() && !problems.iter().any(|p| p.error == MarkupError::TextAfterEnd) {
+        let line = lines.len().saturating_sub(1);
+        let last_line = lines.last().copied().unwrap_or_default();
+
+        problems.push(Problem {
+            line,
+            offset: last_line.trim_end().len(),
+            text: String::new(),
+            error: MarkupError::MissingEnd,
+        });
+    }
+
+    problems.sort_by_key(|p| (p.line, p.offset));
+    problems
+}
+
+/// The well-formed tags in a string's text, e.g. `[WAIT]`, in order.
+pub fn tags(text: &str) -> Vec<&str> {
+    let mut tags = Vec::new();
+    let mut rest = text;
+
+    while let Some(open) = rest.find('[') {
+        rest = &rest[open..];
+
+        match rest[1..].find(['[', ']']) {
+            Some(idx) if rest.as_bytes()[idx + 1] == b']' => {
+                tags.push(&rest[..idx + 2]);
+                rest = &rest[idx + 2..];
+            },
+            _ => rest = &rest[1..],
+        }
+    }
+
+    tags
+}
+
+/// Returns what was expected, if the arguments are wrong.
+fn check_args(spec: Args, args: Option<&str>) -> Option<String> {
+    let ok = match (spec, args) {
+        (Args::None, None) => true,
+        (Args::None, Some(_)) => return Some("no arguments".to_owned()),
+        (_, None) => false,
+        (Args::Byte, Some(arg)) => is_hex_byte(arg),
+        (Args::Bytes(count), Some(args)) => {
+            let args: Vec<&str> = args.split(',').collect();
+            args.len() == count && args.iter().all(|arg| is_hex_byte(arg))
+        },
+        (Args::Index(max), Some(arg)) => arg.parse::<u8>().map(|n| n <= max).unwrap_or(false),
+        (Args::OneOf(names), Some(arg)) => names.contains(&arg),
+        (Args::Name, Some(arg)) => !arg.is_empty() && arg.chars().all(|c| c.is_ascii_alphanumeric() || c == '_'),
+    };
+
+    if ok {
+        return None;
+    }
+
+    Some(match spec {
+        Args::None => unreachable!(),
+        Args::Byte => "a hex byte, e.g. `1E`".to_owned(),
+        Args::Bytes(count) => format!("{} hex bytes, e.g. `{}`", count, vec!["1E"; count].join(",")),
+        Args::Index(max) => format!("a number from 0 to {}", max),
+        Args::OneOf(names) => format!("one of: {}", names.join(", ")),
+        Args::Name => "a name, e.g. `Mushroom`".to_owned(),
+    })
+}
+
+fn is_hex_byte(arg: &str) -> bool {
+    arg.len() <= 2 && u8::from_str_radix(arg, 16).is_ok()
+}
+
+/// Finds the known tag closest to a misspelt one, if any is close enough to be a likely typo.
+fn suggest(name: &str) -> Option<&'static str> {
+    let name = name.to_uppercase();
+
+    TAGS.iter()
+        .map(|(tag_name, _)| (*tag_name, edit_distance(&name, tag_name)))
+        .filter(|(tag_name, distance)| *distance <= 2 && *distance < tag_name.len())
+        .min_by_key(|(_, distance)| *distance)
+        .map(|(tag_name, _)| tag_name)
+}
+
+/// The number of single-character insertions, deletions, substitutions and adjacent swaps
+/// needed to turn `a` into `b`.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut d = vec![vec![0; b.len() + 1]; a.len() + 1];
+
+    for (i, row) in d.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in d[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = if a[i - 1] == b[j - 1] { 0 } else { 1 };
+            d[i][j] = (d[i - 1][j] + 1).min(d[i][j - 1] + 1).min(d[i - 1][j - 1] + cost);
+
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1);
+            }
+        }
+    }
+
+    d[a.len()][b.len()]
+}
@@ -0,0 +1,868 @@
+use crate::prelude::*;
+use crate::logger;
+use crate::sanitize;
+use crate::diagnostics::{Diagnostics, Diagnostic, Severity, Span, Code};
+use super::Package;
+use super::id::{Identify, Identifier};
+use super::script::{Script, BlockKind, ResolveError};
+use super::actor::{ActorMap, ActorId};
+use regex::{Regex, Captures};
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+
+mod exchange;
+mod layout;
+mod markup;
+
+pub use markup::MarkupError;
+pub use exchange::{Entry as StringEntry, Format as StringFormat, ImportError};
+pub use exchange::{write as write_entries, parse as parse_entries};
+
+pub type TextMap = HashMap<TextId, Text>;
+
+/// String indices are three hex digits.
+const MAX_STRINGS_PER_SECTION: u16 = 0x1000;
+
+/// Sections after the base game's, which strings can be given without clashing with its own.
+const FIRST_NEW_SECTION: u8 = 0x30;
+
+#[derive(Hash, Clone, PartialEq, Eq)]
+pub struct TextId(Identifier);
+
+impl Identify for TextId {
+    type T = Text;
+
+    fn new(pkg_name: &str, text_name: &str) -> Self {
+        Self(Identifier::new(pkg_name, text_name))
+    }
+
+    fn identify(pkg: &Package, text: &Text) -> Self {
+        Self(Identifier::from_package(pkg, &text.name()))
+    }
+}
+
+impl TextId {
+    pub fn package(&self) -> &str {
+        self.0.package()
+    }
+}
+
+impl fmt::Display for TextId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl fmt::Debug for TextId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", logger::style(Color::Fixed(12)).paint(format!("{{String:{:?}}}", self.0)))
+    }
+}
+
+/// A '#string:XX:(Name)' block. See also: [script::BlockKind::StringNamed].
+/// Note that Star Rod calls these 'strings' - therefore, so do we, externally.
+#[derive(Clone, Debug)]
+pub struct Text {
+    /// `None` if the block leaves it to us to pick one.
+    section: Option<u8>,
+    name: String,
+    string: String,
+
+    /// The file the string is in, and the lines its block starts and ends on.
+    src: PathBuf,
+    lines: (usize, usize),
+
+    /// Where each line of `string` is, which is in another file once translated.
+    string_src: PathBuf,
+    string_lines: Vec<usize>,
+
+    /// Section and index.
+    assembled_id: Option<(u8, u16)>,
+}
+
+impl Text {
+    /// Loads every string in the given file. Bad blocks are reported to `diagnostics` and
+    /// skipped; only failing to read the file at all is an error. With `wrap`, lines too wide for
+    /// their box are broken to fit.
+    pub fn load_many(
+        src_pkg_name: &str,
+        str_file_path: PathBuf,
+        wrap: bool,
+        diagnostics: &mut Diagnostics,
+    ) -> Result<Vec<Text>, LoadError> {
+        let script = Script::load(src_pkg_name, str_file_path)?;
+        let mut texts = Vec::new();
+
+        for block in &script.blocks {
+            match &block.kind {
+                BlockKind::StringNamed { section, name } => {
+                    if let Err(error) = sanitize::export_name(name) {
+                        diagnostics.report(LoadError::BadName {
+                            span: script.span(block.start_line(), &format!("({})", name))
+                                .map(|span| Span::new(&span.file, span.line, span.column + 1, span.length - 2)),
+                            error,
+                        });
+                        continue;
+                    }
+
+                    texts.push(Text::new(
+                        name,
+                        *section,
+                        &script.path,
+                        script.source(),
+                        (block.start_line(), block.end_line()),
+                        &block.lines[1..],
+                        wrap,
+                        diagnostics,
+                    ));
+                },
+                _ => diagnostics.report(LoadError::DisallowedBlockKind {
+                    span: script.span(block.start_line(), &block.lines[0].1),
+                }),
+            }
+        }
+
+        Ok(texts)
+    }
+
+    /// Loads every string in the given TOML file, which maps export names to either text or a table
+    /// with `text` and, optionally, `section`. Like [Text::load_many], bad entries are reported to
+    /// `diagnostics` and skipped.
+    pub fn load_toml(
+        toml_file_path: PathBuf,
+        wrap: bool,
+        diagnostics: &mut Diagnostics,
+    ) -> Result<Vec<Text>, LoadError> {
+        let source = fs::read_to_string(&toml_file_path)
+            .with_context(|| format!("string file not found: {}", toml_file_path.display()))?;
+        let table: toml::value::Table = match toml::from_str(&source) {
+            Ok(table) => table,
+            Err(error) => {
+                diagnostics.report(Diagnostic::toml(&toml_file_path, &error));
+                return Ok(Vec::new());
+            },
+        };
+
+        let mut texts = Vec::new();
+
+        for (name, value) in table {
+            let span = Span::toml_key(&toml_file_path, &source, &name);
+
+            if let Err(error) = sanitize::export_name(&name) {
+                diagnostics.report(LoadError::BadName { span, error });
+                continue;
+            }
+
+            let entry = match value {
+                toml::Value::String(text) => Ok((text, None, None)),
+                toml::Value::Table(mut table) => match (table.remove("text"), table.remove("section")) {
+                    (Some(toml::Value::String(text)), section) => match section {
+                        None => Ok((text, None, Some("text"))),
+                        Some(toml::Value::String(section)) if section == "auto" => Ok((text, None, Some("text"))),
+                        Some(toml::Value::String(section)) => match u8::from_str_radix(&section, 16) {
+                            Ok(section) => Ok((text, Some(section), Some("text"))),
+                            Err(_) => Err("`section` must be a hex byte, e.g. \"1C\", or \"auto\""),
+                        },
+                        Some(toml::Value::Integer(section)) if (0..=0xFF).contains(&section) => {
+                            Ok((text, Some(section as u8), Some("text")))
+                        },
+                        Some(_) => Err("`section` must be a hex byte, e.g. \"1C\", or \"auto\""),
+                    },
+                    _ => Err("a table string needs `text`, as a string"),
+                },
+                _ => Err("expected text, or a table with `text`"),
+            };
+
+            let (text, section, text_key) = match entry {
+                Ok(entry) => entry,
+                Err(reason) => {
+                    diagnostics.report(LoadError::BadTomlString {
+                        span,
+                        name,
+                        reason: reason.to_owned(),
+                    });
+                    continue;
+                },
+            };
+
+            let (key_line, first_line, end_line) = toml_text_lines(&source, &name, text_key);
+            let body: Vec<(usize, String)> = text.trim_end_matches('\n')
+                .lines()
+                .enumerate()
+                .map(|(idx, line)| (first_line + idx, line.trim().to_owned()))
+                .collect();
+
+            texts.push(Text::new(
+                &name,
+                section,
+                &toml_file_path,
+                &source,
+                (key_line, end_line),
+                &body,
+                wrap,
+                diagnostics,
+            ));
+        }
+
+        Ok(texts)
+    }
+
+    /// Makes a string from the lines of its text, given with the lines they're on in `source`.
+    /// Problems with its markup are reported, but the string is kept so references to it resolve.
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        name: &str,
+        section: Option<u8>,
+        src: &Path,
+        source: &str,
+        lines: (usize, usize),
+        body: &[(usize, String)],
+        wrap: bool,
+        diagnostics: &mut Diagnostics,
+    ) -> Text {
+        let body_lines: Vec<&str> = body.iter().map(|(_, line)| line.as_str()).collect();
+
+        let problems = markup::check(&body_lines);
+        let markup_ok = problems.iter().all(|problem| problem.error.is_warning());
+
+        for problem in problems {
+            let (line_no, line) = body.get(problem.line)
+                .map(|(line_no, line)| (*line_no, line.as_str()))
+                .unwrap_or((lines.0, ""));
+
+            diagnostics.report(LoadError::BadMarkup {
+                span: Span::find_in(src, source, line_no, line, problem.offset, &problem.text),
+                error: problem.error,
+            });
+        }
+
+        // Lines from wrapping keep the number of the line they came from.
+        let body: Vec<(usize, String)> = if wrap && markup_ok {
+            layout::wrap(&body_lines)
+                .into_iter()
+                .map(|(idx, line)| (body[idx].0, line))
+                .collect()
+        } else {
+            body.to_vec()
+        };
+
+        if markup_ok {
+            check_layout(src, source, name, &body, diagnostics);
+        }
+
+        Text {
+            section,
+            name: name.to_owned(),
+            src: src.to_owned(),
+            lines,
+            string_src: src.to_owned(),
+            string_lines: body.iter().map(|(line_no, _)| *line_no).collect(),
+            string: body
+                .iter()
+                .map(|(_, line)| line.as_str())
+                .collect::<Vec<&str>>()
+                .join("\n"),
+            assembled_id: None,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The section the string's block asks for, if any.
+    pub fn string_section(&self) -> Option<u8> {
+        self.section
+    }
+
+    /// Uses the text of a translation of this string. The section is kept, so that the string
+    /// is assembled to the same place whichever language it is in.
+    pub fn translate(&mut self, translation: &Text) {
+        self.string = translation.string.clone();
+        self.string_src = translation.string_src.clone();
+        self.string_lines = translation.string_lines.clone();
+    }
+
+    /// The file and line the string is on.
+    pub fn location(&self) -> (&Path, usize) {
+        (&self.src, self.lines.0)
+    }
+
+    /// This string as an entry for translators, with its translation if it has one.
+    pub fn entry(&self, translation: Option<&Text>) -> StringEntry {
+        StringEntry {
+            name: self.name.clone(),
+            section: self.section,
+            context: format!("{}:{}", self.src.strip_prefix(".").unwrap_or(&self.src).display(), self.lines.0),
+            source: self.string.clone(),
+            translation: translation.map(|text| text.string.clone()).unwrap_or_default(),
+        }
+    }
+
+    pub fn assembled_hex_id(&self) -> Option<String> {
+        self.assembled_id.map(|(section, index)| format!("{:04X}{:04X}", section, index))
+    }
+
+    pub fn assemble(&mut self, out_dir: &Path, section: u8, index: u16) -> Result<()> {
+        fs::write(out_dir.join(format!("{:04X}{:04X}.str", section, index)), {
+            let mut source = format!("#string:{:02X}:{:03X}\n", section, index);
+            source.push_str(&self.string);
+            source
+        })?;
+
+        self.assembled_id = Some((section, index));
+
+        Ok(())
+    }
+}
+
+/// Resolves expressions in the text of every string:
+///
+/// - `{String:id}` - the text of another string, without its `[END]`
+/// - `{Actor:id:field}` - a field of an actor's `$Actor` struct, e.g. `MaxHP`
+///
+/// Expressions that fail to resolve are reported to `diagnostics` and left as-is.
+pub fn resolve_expressions(texts: &mut TextMap, actors: &ActorMap, diagnostics: &mut Diagnostics) {
+    let mut ids: Vec<TextId> = texts.keys().cloned().collect();
+    ids.sort_by_key(|id| id.to_string());
+
+    let mut resolved = HashMap::new();
+    let mut actor_fields = HashMap::new();
+    for id in &ids {
+        resolve_text(id, texts, actors, &mut resolved, &mut actor_fields, &mut Vec::new(), diagnostics);
+    }
+
+    for (id, string) in resolved {
+        texts.get_mut(&id).unwrap().string = string;
+    }
+}
+
+/// Resolves the expressions in one string, and those of the strings it includes first. `stack`
+/// holds the strings being resolved that include this one. Each actor's fields are parsed once and
+/// kept in `actor_fields`, or `None` if they couldn't be, which has been reported.
+#[allow(clippy::too_many_arguments)]
+fn resolve_text(
+    id: &TextId,
+    texts: &TextMap,
+    actors: &ActorMap,
+    resolved: &mut HashMap<TextId, String>,
+    actor_fields: &mut HashMap<ActorId, Option<Vec<(String, String)>>>,
+    stack: &mut Vec<TextId>,
+    diagnostics: &mut Diagnostics,
+) -> String {
+    use ResolveError::*;
+
+    lazy_static! {
+        static ref EXPRESSION: Regex = Regex::new(
+            r"\{String:([^:}]*)\}|\{Actor:([^:}]*):([^:}]*)\}"
+        ).unwrap();
+    }
+
+    if let Some(string) = resolved.get(id) {
+        return string.clone();
+    }
+
+    let text = &texts[id];
+    stack.push(id.clone());
+
+    // Only needed to point at expressions, so only read if there are any.
+    let source = if EXPRESSION.is_match(&text.string) {
+        fs::read_to_string(&text.string_src).unwrap_or_default()
+    } else {
+        String::new()
+    };
+
+    let mut lines = Vec::new();
+    for (idx, line) in text.string.split('\n').enumerate() {
+        let line_no = text.string_lines.get(idx).copied().unwrap_or(text.lines.0);
+
+        // Finds the span of capture group `group` of the expression `g`.
+        let span = |g: &Captures, group: usize| {
+            let expr = g.get(0).unwrap();
+            let part = g.get(group).unwrap();
+
+            match Span::find(&text.string_src, &source, line_no, expr.as_str()) {
+                Some(mut span) => {
+                    span.column += expr.as_str()[..part.start() - expr.start()].chars().count();
+                    span.length = part.as_str().chars().count();
+                    span
+                },
+                None => Span::new(&text.string_src, line_no, 1, 0),
+            }
+        };
+
+        let mut new = String::with_capacity(line.len());
+        let mut last_match = 0;
+        for g in EXPRESSION.captures_iter(line) {
+            let m = g.get(0).unwrap();
+            new.push_str(&line[last_match..m.start()]);
+            last_match = m.end();
+
+            let replacement: Result<String, Diagnostic> = if let Some(other) = g.get(1) {
+                // {String:id}
+                match TextId::parse(other.as_str(), id.package()) {
+                    Err(err) => Err(IdParseError {
+                        span: span(&g, 1),
+                        id_string: other.as_str().to_owned(),
+                        parse_error: err,
+                    }.into()),
+                    Ok(other_id) if stack.contains(&other_id) => Err(RecursiveText {
+                        span: span(&g, 1),
+                        id: other_id,
+                    }.into()),
+                    Ok(other_id) if texts.contains_key(&other_id) => {
+                        let string = resolve_text(&other_id, texts, actors, resolved, actor_fields, stack, diagnostics);
+                        let string = string.trim_end();
+                        Ok(string.strip_suffix("[END]").unwrap_or(string).to_owned())
+                    },
+                    Ok(other_id) => Err(UnknownText { span: span(&g, 1), id: other_id }.into()),
+                }
+            } else {
+                // {Actor:id:field}
+                let (actor, field) = (g.get(2).unwrap(), g.get(3).unwrap().as_str());
+
+                ActorId::parse(actor.as_str(), id.package())
+                    .map_err(|err| IdParseError {
+                        span: span(&g, 2),
+                        id_string: actor.as_str().to_owned(),
+                        parse_error: err,
+                    }.into())
+                    .and_then(|actor_id| match actor_id.resolve(actors) {
+                        Some(actor) => Ok((actor_id, actor)),
+                        None => Err(UnknownActor { span: span(&g, 2), id: actor_id }.into()),
+                    })
+                    .and_then(|(actor_id, actor)| {
+                        let name = format!("{:?}", actor_id);
+                        let fields = match actor_fields.entry(actor_id) {
+                            Entry::Occupied(entry) => entry.into_mut(),
+                            Entry::Vacant(entry) => match actor.fields() {
+                                Ok(fields) => entry.insert(Some(fields)),
+                                Err(error) => {
+                                    entry.insert(None);
+                                    return Err(error.into());
+                                },
+                            },
+                        };
+
+                        // Reported when first parsed, so just leave the expression.
+                        let fields = match fields {
+                            Some(fields) => fields,
+                            None => return Ok(m.as_str().to_owned()),
+                        };
+
+                        match fields.iter().find(|(f, _)| f == field) {
+                            Some((_, value)) => Ok(value.clone()),
+                            None => Err(ActorLacksField {
+                                span: span(&g, 3),
+                                actor: name,
+                                field: field.to_owned(),
+                                fields: fields.iter().map(|(f, _)| f.clone()).collect(),
+                            }.into()),
+                        }
+                    })
+            };
+
+            match replacement {
+                Ok(replacement) => new.push_str(&replacement),
+                Err(error) => {
+                    diagnostics.report(error);
+                    new.push_str(m.as_str());
+                },
+            }
+        }
+        new.push_str(&line[last_match..]);
+
+        lines.push(new);
+    }
+
+    stack.pop();
+
+    let string = lines.join("\n");
+    resolved.insert(id.clone(), string.clone());
+    string
+}
+
+/// Checks that a translation's markup is sound, so it can be used in place of the original.
+pub fn check_translation(translation: &str) -> Result<(), MarkupError> {
+    let lines: Vec<&str> = translation.lines().collect();
+
+    match markup::check(&lines).into_iter().find(|problem| !problem.error.is_warning()) {
+        Some(problem) => Err(problem.error),
+        None => Ok(()),
+    }
+}
+
+/// Line and page breaks, which a translation needn't keep, as it can run to a different length.
+const BREAK_TAGS: [&str; 3] = ["[BR]", "[NEXT]", "[WAIT]"];
+
+/// Compares the tags of a translation with those of the string it translates, in any order.
+/// Returns the tags it is missing and those it adds, which are both empty if it keeps them all.
+pub fn tag_differences(text: &Text, translation: &str) -> (Vec<String>, Vec<String>) {
+    let kept = |tags: Vec<&str>| -> Vec<String> {
+        let mut tags: Vec<String> = tags.into_iter()
+            .filter(|tag| !BREAK_TAGS.contains(tag))
+            .map(str::to_owned)
+            .collect();
+        tags.sort();
+        tags
+    };
+    let mut missing = kept(markup::tags(&text.string));
+    let mut added = Vec::new();
+
+    for tag in kept(markup::tags(translation)) {
+        match missing.iter().position(|source_tag| *source_tag == tag) {
+            Some(idx) => { missing.remove(idx); },
+            None => added.push(tag),
+        }
+    }
+
+    (missing, added)
+}
+
+/// Writes translations of strings into a locale's string files. Each is given with the string it
+/// translates and its existing translation, if any, which it replaces. New translations go in a
+/// `.str` file named after that of the string they translate.
+pub fn save_translations(locale_dir: &Path, translations: &[(&Text, Option<&Text>, String)]) -> Result<()> {
+    /// Changes to make to one file.
+    #[derive(Default)]
+    struct Edits<'a> {
+        /// Lines blocks start and end on, and their new text.
+        replacements: Vec<(usize, usize, &'a str)>,
+
+        /// Names and text of new blocks.
+        additions: Vec<(&'a str, &'a str)>,
+    }
+
+    let mut files: HashMap<PathBuf, Edits> = HashMap::new();
+
+    for (text, existing, translation) in translations {
+        match existing {
+            Some(existing) => files.entry(existing.src.clone()).or_default().replacements
+                .push((existing.lines.0, existing.lines.1, translation)),
+            None => {
+                let file_name = text.src.with_extension("str");
+                let file_name = file_name.file_name().expect("string file has no name");
+                files.entry(locale_dir.join(file_name)).or_default().additions
+                    .push((&text.name, translation));
+            },
+        }
+    }
+
+    for (path, Edits { mut replacements, additions }) in files {
+        let source = fs::read_to_string(&path).unwrap_or_default();
+        let mut lines: Vec<String> = source.lines().map(str::to_owned).collect();
+
+        // Replace from the bottom up, so the line numbers of blocks above stay correct.
+        let is_toml = path.extension().map(|ext| ext == "toml").unwrap_or(false);
+        replacements.sort_by_key(|(start, _, _)| std::cmp::Reverse(*start));
+        for (start, end, translation) in replacements {
+            if is_toml {
+                // Replace the whole `key = value`, which may span several lines.
+                let key = lines[start - 1].split('=').next().unwrap_or_default().trim_end().to_owned();
+                let value = toml::Value::String(translation.to_owned());
+                lines.splice(start - 1..end, std::iter::once(format!("{} = {}", key, value)));
+            } else {
+                lines.splice(start..end.max(start), translation.lines().map(str::to_owned));
+            }
+        }
+
+        for (name, translation) in additions {
+            if lines.last().map(|line| !line.trim().is_empty()).unwrap_or(false) {
+                lines.push(String::new());
+            }
+            lines.push(format!("#string:({})", name));
+            lines.extend(translation.lines().map(str::to_owned));
+        }
+
+        fs::create_dir_all(path.parent().unwrap())?;
+        fs::write(&path, lines.join("\n") + "\n")
+            .with_context(|| format!("unable to write {}", path.display()))?;
+    }
+
+    Ok(())
+}
+
+/// Finds the lines a string's text is on in a TOML file: the line of its key, the line its text
+/// starts on, and the line its value ends on. `text_key` is `Some("text")` for table strings.
+fn toml_text_lines(source: &str, name: &str, text_key: Option<&str>) -> (usize, usize, usize) {
+    let lines: Vec<&str> = source.lines().collect();
+    let is_key = |line: &str, key: &str| {
+        let line = line.trim_start();
+        let quoted = format!("\"{}\"", key);
+        line.strip_prefix(key)
+            .or_else(|| line.strip_prefix(quoted.as_str()))
+            .map(|rest| rest.trim_start().starts_with('='))
+            .unwrap_or(false)
+    };
+    let is_header = |line: &str| line.trim().replace('"', "") == format!("[{}]", name);
+
+    let key_idx = match text_key {
+        None => lines.iter().position(|line| is_key(line, name)),
+        Some(text_key) => lines.iter().position(|line| is_header(line)).and_then(|header_idx| {
+            lines[header_idx..].iter()
+                .position(|line| is_key(line, text_key))
+                .map(|idx| header_idx + idx)
+        }),
+    };
+
+    let key_idx = match key_idx {
+        Some(idx) => idx,
+        None => return (1, 1, 1),
+    };
+
+    // Multi-line strings run until their closing quotes. A newline straight after the opening
+    // quotes isn't part of the string.
+    let value = lines[key_idx].split_once('=').map(|(_, value)| value.trim()).unwrap_or_default();
+    for quotes in &[r#"""""#, "'''"] {
+        if let Some(rest) = value.strip_prefix(quotes) {
+            if rest.contains(quotes) {
+                break;
+            }
+
+            let first_idx = if rest.trim().is_empty() { key_idx + 1 } else { key_idx };
+            let end_idx = lines[key_idx + 1..].iter()
+                .position(|line| line.contains(quotes))
+                .map(|idx| key_idx + 1 + idx)
+                .unwrap_or(key_idx);
+
+            return (key_idx + 1, first_idx + 1, end_idx + 1);
+        }
+    }
+
+    (key_idx + 1, key_idx + 1, key_idx + 1)
+}
+
+/// Warns about lines and pages of a string that don't fit in its box.
+fn check_layout(
+    src: &Path,
+    source: &str,
+    name: &str,
+    lines: &[(usize, String)],
+    diagnostics: &mut Diagnostics,
+) {
+    let line_strs: Vec<&str> = lines.iter().map(|(_, line)| line.as_str()).collect();
+    let span = |line: usize| {
+        let (line_no, line) = &lines[line];
+        Span::find_in(src, source, *line_no, line, 0, line)
+    };
+
+    for overflow in layout::check(&line_strs) {
+        diagnostics.report(match overflow {
+            layout::Overflow::TooWide { line, width, size } => LoadError::TooWide {
+                span: span(line),
+                name: name.to_owned(),
+                width,
+                size,
+            },
+            layout::Overflow::TooManyLines { line, count, size } => LoadError::TooManyLines {
+                span: span(line),
+                name: name.to_owned(),
+                count,
+                size,
+            },
+        });
+    }
+}
+
+/// What a string is used for, which decides its section when its block doesn't give one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Usage {
+    ActorName,
+    Tattle,
+    Dialogue,
+}
+
+impl Usage {
+    /// Whether each package's strings of this kind get a section of their own. Dialogue does, so
+    /// that packages don't crowd one section between them; actor names and tattles are few, so all
+    /// packages share a section for each.
+    fn per_package(self) -> bool {
+        self == Usage::Dialogue
+    }
+}
+
+/// Hands out section and index pairs to strings as they're assembled. Strings that don't give a
+/// section only go in new ones, since the base game's sections are already full of its strings.
+pub struct SectionAllocator {
+    /// Strings so far in each section.
+    counts: [u16; 256],
+
+    /// The section strings of each usage go in, and the package they're from if they're per-package.
+    usage_sections: HashMap<(Usage, Option<String>), u8>,
+
+    /// Where strings go once the section they would go in is full.
+    overflow_sections: HashMap<u8, u8>,
+}
+
+impl SectionAllocator {
+    pub fn new() -> SectionAllocator {
+        SectionAllocator {
+            counts: [0; 256],
+            usage_sections: HashMap::new(),
+            overflow_sections: HashMap::new(),
+        }
+    }
+
+    /// Takes the next index in the given section. Strings that give their own section should
+    /// all be allocated before any that don't, so that new sections can avoid theirs.
+    pub fn next_in(&mut self, section: u8) -> u16 {
+        let index = self.counts[section as usize];
+        self.counts[section as usize] += 1;
+        index
+    }
+
+    /// Picks a section for a string from the given package, and takes the next index in it.
+    pub fn allocate(&mut self, pkg_name: &str, usage: Usage) -> Result<(u8, u16)> {
+        let key = (usage, Some(pkg_name.to_owned()).filter(|_| usage.per_package()));
+
+        let mut section = match self.usage_sections.get(&key) {
+            Some(&section) => section,
+            None => {
+                let section = self.new_section()?;
+                self.usage_sections.insert(key, section);
+                section
+            },
+        };
+
+        while self.counts[section as usize] >= MAX_STRINGS_PER_SECTION {
+            section = match self.overflow_sections.get(&section) {
+                Some(&overflow) => overflow,
+                None => {
+                    let overflow = self.new_section()?;
+                    self.overflow_sections.insert(section, overflow);
+                    overflow
+                },
+            };
+        }
+
+        Ok((section, self.next_in(section)))
+    }
+
+    fn new_section(&mut self) -> Result<u8> {
+        let taken: Vec<u8> = self.usage_sections.values()
+            .chain(self.overflow_sections.values())
+            .copied()
+            .collect();
+
+        (FIRST_NEW_SECTION..=0xFF)
+            .find(|section| self.counts[*section as usize] == 0 && !taken.contains(section))
+            .ok_or_else(|| anyhow!("too many strings to fit in the available string sections"))
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum LoadError {
+    #[error("only `#string:(export_name)` blocks allowed in string files")]
+    DisallowedBlockKind {
+        span: Option<Span>,
+    },
+
+    #[error("{error}")]
+    BadName {
+        span: Option<Span>,
+        #[source]
+        error: sanitize::ExportNameError,
+    },
+
+    #[error("malformed string '{name}': {reason}")]
+    BadTomlString {
+        span: Option<Span>,
+        name: String,
+        reason: String,
+    },
+
+    #[error("{error}")]
+    BadMarkup {
+        span: Option<Span>,
+        #[source]
+        error: MarkupError,
+    },
+
+    /// A warning, as the game still shows the string.
+    #[error("line of string '{name}' is {width}px wide, but {} fits {}px", size.name, size.width)]
+    TooWide {
+        span: Option<Span>,
+        name: String,
+        width: u32,
+        size: layout::BoxSize,
+    },
+
+    /// A warning, as the game still shows the string.
+    #[error("page of string '{name}' has {count} lines, but {} fits {}", size.name, size.lines)]
+    TooManyLines {
+        span: Option<Span>,
+        name: String,
+        count: usize,
+        size: layout::BoxSize,
+    },
+
+    #[error(transparent)]
+    Other(#[from] Error),
+}
+
+impl Code for LoadError {
+    fn code(&self) -> Option<&'static str> {
+        match self {
+            LoadError::DisallowedBlockKind { .. } => Some("SP0301"),
+            LoadError::BadName { error, .. } => error.code(),
+            LoadError::BadTomlString { .. } => Some("SP0310"),
+            LoadError::BadMarkup { error, .. } => error.code(),
+            LoadError::TooWide { .. } => Some("SP0311"),
+            LoadError::TooManyLines { .. } => Some("SP0312"),
+            LoadError::Other(_) => None,
+        }
+    }
+}
+
+impl From<LoadError> for Diagnostic {
+    fn from(error: LoadError) -> Diagnostic {
+        let diagnostic = Diagnostic::error(&error).with_code(error.code());
+
+        match error {
+            LoadError::DisallowedBlockKind { span } => diagnostic
+                .with_label(span, "expected a string block")
+                .with_help("string blocks look like `#string:(export_name)`, or `#string:01:(export_name)` to choose the section"),
+            LoadError::BadName { span, .. } => diagnostic
+                .with_label(span, "bad export name"),
+            LoadError::BadTomlString { span, .. } => diagnostic
+                .with_label(span, "bad string")
+                .with_help("strings look like `name = \"Text[END]\"`, or a `[name]` table with `text` and `section`"),
+            LoadError::BadMarkup { span, error } => match error {
+                MarkupError::UnknownTag { suggestion: Some(suggestion), .. } => diagnostic
+                    .with_severity(Severity::Warning)
+                    .with_label(span, "unknown tag")
+                    .with_help(format!("did you mean `[{}]`?", suggestion)),
+                MarkupError::UnknownTag { suggestion: None, .. } => diagnostic
+                    .with_severity(Severity::Warning)
+                    .with_label(span, "unknown tag"),
+                MarkupError::BadArgument { expected, .. } => diagnostic
+                    .with_label(span, format!("expected {}", expected)),
+                MarkupError::UnclosedTag { .. } => diagnostic
+                    .with_label(span, "unclosed tag"),
+                MarkupError::UnopenedTag => diagnostic
+                    .with_label(span, "unopened tag"),
+                MarkupError::MissingEnd => diagnostic
+                    .with_label(span, "expected `[END]` here")
+                    .with_help("the game reads past the end of strings without `[END]`, usually crashing"),
+                MarkupError::TextAfterEnd => diagnostic
+                    .with_label(span, "never shown")
+                    .with_help("move it before `[END]`, or into a string of its own"),
+                MarkupError::UnrestoredColor => diagnostic
+                    .with_label(span, "saved here")
+                    .with_help("add `[RESTORECOLOR]` where the coloured text ends"),
+                MarkupError::UnsavedColor => diagnostic
+                    .with_label(span, "nothing to restore")
+                    .with_help("add `[SAVECOLOR]` before changing the colour"),
+            },
+            LoadError::TooWide { span, width, size, .. } => diagnostic
+                .with_severity(Severity::Warning)
+                .with_label(span, format!("{}px too wide", width - size.width))
+                .with_help("break the line, or set `wrap = true` under `[strings]` in the package manifest"),
+            LoadError::TooManyLines { span, .. } => diagnostic
+                .with_severity(Severity::Warning)
+                .with_label(span, "doesn't fit")
+                .with_help("start a new page with `[NEXT]`"),
+            LoadError::Other(error) => error.into(),
+        }
+    }
+}
@@ -1,4 +1,5 @@
 use crate::prelude::*;
+use crate::diagnostics::Code;
 use regex::Regex;
 
 fn name(s: &str) -> Result<(), GenericNameError> {
@@ -56,6 +57,19 @@ pub enum GenericNameError {
     EndUnderscore(String),
 }
 
+impl Code for GenericNameError {
+    fn code(&self) -> Option<&'static str> {
+        Some(match self {
+            GenericNameError::TooLong(_) => "SP0001",
+            GenericNameError::TooShort(_) => "SP0002",
+            GenericNameError::BadInternalChars(_) => "SP0003",
+            GenericNameError::JustUnderscores(_) => "SP0004",
+            GenericNameError::BeginNumber(_) => "SP0005",
+            GenericNameError::EndUnderscore(_) => "SP0006",
+        })
+    }
+}
+
 pub fn package_name(s: &str) -> Result<(), PackageNameError> {
     lazy_static! {
         static ref RESERVED_PACKAGE_NAMES: Vec<&'static str> = vec![ "pm64" ];
@@ -84,6 +98,16 @@ pub enum PackageNameError {
     Generic(#[from] GenericNameError)
 }
 
+impl Code for PackageNameError {
+    fn code(&self) -> Option<&'static str> {
+        match self {
+            PackageNameError::Reserved(_) => Some("SP0010"),
+            PackageNameError::BeginsUnderscore(_) => Some("SP0011"),
+            PackageNameError::Generic(error) => error.code(),
+        }
+    }
+}
+
 pub fn export_name(s: &str) -> Result<(), ExportNameError> {
     lazy_static! {
         static ref UPPER_ALPHA_BEGIN: Regex = Regex::new(r"^[A-Z]").unwrap();
@@ -102,6 +126,14 @@ pub enum ExportNameError {
     Generic(#[from] GenericNameError)
 }
 
+impl Code for ExportNameError {
+    fn code(&self) -> Option<&'static str> {
+        match self {
+            ExportNameError::Generic(error) => error.code(),
+        }
+    }
+}
+
 pub fn dependency_name(s: &str, current_pkg_name: &str) -> Result<(), DependencyNameError> {
     if s == current_pkg_name {
         return Err(DependencyNameError::SameAsCurrentPackage(s.to_string()))
@@ -118,3 +150,35 @@ pub enum DependencyNameError {
     #[error("invalid dependency name: {0}")]
     Generic(#[from] GenericNameError)
 }
+
+impl Code for DependencyNameError {
+    fn code(&self) -> Option<&'static str> {
+        match self {
+            DependencyNameError::SameAsCurrentPackage(_) => Some("SP0020"),
+            DependencyNameError::Generic(error) => error.code(),
+        }
+    }
+}
+
+/// Locales name directories, so only plain tags such as `fr` or `pt-BR` are allowed.
+pub fn locale(s: &str) -> Result<(), LocaleError> {
+    lazy_static! {
+        static ref LOCALE: Regex = Regex::new(r"^[a-zA-Z]{2,3}([-_][a-zA-Z0-9]{1,8})*$").unwrap();
+    }
+
+    if LOCALE.is_match(s) {
+        Ok(())
+    } else {
+        Err(LocaleError(s.to_string()))
+    }
+}
+
+#[derive(Error, Debug)]
+#[error("invalid locale: '{0}' is not a locale tag such as 'fr' or 'pt-BR'")]
+pub struct LocaleError(String);
+
+impl Code for LocaleError {
+    fn code(&self) -> Option<&'static str> {
+        Some("SP0030")
+    }
+}
@@ -17,6 +17,21 @@ impl StarRod {
         }
     }
 
+    /// Where Star Rod dumped the base game's assets, if it has. The `STARPKG_DUMP_DIR` environment
+    /// variable overrides this.
+    pub fn dump_dir() -> Option<PathBuf> {
+        let dir = match std::env::var_os("STARPKG_DUMP_DIR") {
+            Some(dir) => PathBuf::from(dir),
+            None => StarRod::new()?.dir.join("dump"),
+        };
+
+        if dir.is_dir() {
+            Some(dir)
+        } else {
+            None
+        }
+    }
+
     pub fn new_or_download() -> io::Result<StarRod> {
         const DOWNLOAD: &str = "https://github.com/nanaian/star-rod/archive/v0.2.0";
 
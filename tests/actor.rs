@@ -0,0 +1,519 @@
+mod common;
+
+use assert_fs::prelude::*;
+use predicates::prelude::*;
+use indoc::indoc;
+
+use common::{starpkg, package_with_actor};
+
+/// What an actor's script needs when its TOML has `[stats]`.
+const SCRIPT: &str = indoc!(r#"
+    #new:SpriteTable $SpriteTable
+    00800000 01000000 001400FF 00000000 00000000 00000000 00000000 00F60000 00000000
+    00800000 02000000 001400FF 00000000 00000000 00000000 00000000 00F60000 00000000
+
+    #new:Script $Script_Init
+    Return
+    End
+"#);
+
+/// A hand-written actor script.
+const STRUCT: &str = indoc!(r#"
+    #new:Actor $Actor
+    [Index]       {Actor:blob}
+    [MaxHP]       5`b
+    [StatusTable] $StatusTable
+    [SpriteCount] 1`s
+    [SpriteTable] $SpriteTable
+    [Script]      $Script_Init
+
+    #new:StatusTable $StatusTable
+    .Status:End
+
+    #new:SpriteTable $SpriteTable
+    00800000 01000000 001400FF 00000000 00000000 00000000 00000000 00F60000 00000000
+
+    #new:Script $Script_Init
+    Return
+    End
+"#);
+
+#[test]
+fn generates_actor_struct() {
+    let dir = package_with_actor(indoc!(r#"
+        [stats]
+        level = 9
+        max_hp = 12
+        flags = 0x200
+
+        [effectiveness]
+        escape = 90
+
+        [ui]
+        size = [32, 24]
+
+        [status]
+    "#), SCRIPT);
+
+    starpkg()
+        .arg("build")
+        .arg("--no-compile")
+        .current_dir(dir.path())
+        .assert()
+        .success();
+
+    dir.child(".build/battle/formation/import/actor/00_blob.bpat").assert(predicate::str::starts_with(indoc!(r#"
+        #new:Actor $Actor
+        [Index] 00
+        [Level] 9`b
+        [MaxHP] 12`b
+        [Coins] 0`b
+        [Flags] 00000200
+        [StatusTable] $StatusTable
+        [SpriteCount] 2`s
+        [SpriteTable] $SpriteTable
+        [Script] $Script_Init
+        [Escape] 90`b
+        [Item] 100`b
+    "#)));
+
+    dir.child(".build/battle/formation/import/actor/00_blob.bpat")
+        .assert(predicate::str::contains("[Size] 32`b 24`b\n[HealthBar] 0`b 0`b\n"));
+
+    dir.child(".build/strings/00310000.str").assert(predicate::str::contains("Max HP: 12[END]"));
+}
+
+#[test]
+fn checks_stat_ranges() {
+    let dir = package_with_actor(indoc!(r#"
+        [stats]
+        max_hp = 300
+    "#), SCRIPT);
+
+    starpkg()
+        .arg("build")
+        .arg("--no-compile")
+        .current_dir(dir.path())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("error[SP0501]: actor max_hp is 300, but must be from 1 to 255"))
+        .stderr(predicate::str::contains("blob.toml:5:10"));
+
+    let dir = package_with_actor(indoc!(r#"
+        [stats]
+        max_hp = 5
+
+        [effectiveness]
+        hurricane = 101
+    "#), SCRIPT);
+
+    starpkg()
+        .arg("build")
+        .arg("--no-compile")
+        .current_dir(dir.path())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("actor hurricane is 101, but must be from 0 to 100"));
+}
+
+#[test]
+fn stats_need_generated_struct() {
+    let dir = package_with_actor(indoc!(r#"
+        [ui]
+        size = [32, 24]
+    "#), SCRIPT);
+
+    starpkg()
+        .arg("build")
+        .arg("--no-compile")
+        .current_dir(dir.path())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("error[SP0503]: actor has [ui], but no [stats]"));
+
+    let dir = package_with_actor(indoc!(r#"
+        [stats]
+        max_hp = 5
+    "#), indoc!(r#"
+        #new:Actor $Actor
+        [Index] {Actor:blob}
+    "#));
+
+    starpkg()
+        .arg("build")
+        .arg("--no-compile")
+        .current_dir(dir.path())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "error[SP0502]: actor 'blob' has [stats], but its script also defines $Actor"
+        ))
+        .stderr(predicate::str::contains("blob.bscr:1:1"));
+}
+
+#[test]
+fn generates_status_and_defense_tables() {
+    let dir = package_with_actor(indoc!(r#"
+        [stats]
+        max_hp = 5
+
+        [status]
+        presets = ["immune_to_all"]
+        sleep = 50
+        sleep_turns = -1
+
+        [defense]
+        fire = 99
+        jump = 1
+    "#), indoc!(r#"
+        #new:SpriteTable $SpriteTable
+        00800000 01000000 001400FF 00000000 $DefenseTable 00000000 00000000 00F60000 00000000
+
+        #new:Script $Script_Init
+        Return
+        End
+    "#));
+
+    starpkg()
+        .arg("build")
+        .arg("--no-compile")
+        .current_dir(dir.path())
+        .assert()
+        .success();
+
+    dir.child(".build/battle/formation/import/actor/00_blob.bpat").assert(predicate::str::contains(indoc!(r#"
+        #new:StatusTable $StatusTable
+        .Status:Normal 0`
+        .Status:Default 0`
+        .Status:Sleep 50`
+        .Status:Poison 0`
+    "#)));
+
+    dir.child(".build/battle/formation/import/actor/00_blob.bpat")
+        .assert(predicate::str::contains(".Status:SleepTurnMod -1`\n.Status:PoisonTurnMod 0`\n"));
+
+    dir.child(".build/battle/formation/import/actor/00_blob.bpat").assert(predicate::str::contains(indoc!(r#"
+        #new:DefenseTable $DefenseTable
+        .Element:Normal 0`
+        .Element:Fire 99`
+        .Element:Jump 1`
+        .Element:End
+    "#)));
+}
+
+#[test]
+fn checks_status_and_defense_tables() {
+    let dir = package_with_actor(indoc!(r#"
+        [stats]
+        max_hp = 5
+
+        [status]
+        presets = ["immune_to_slep"]
+    "#), SCRIPT);
+
+    starpkg()
+        .arg("build")
+        .arg("--no-compile")
+        .current_dir(dir.path())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("error[SP0505]: unknown status preset 'immune_to_slep'"))
+        .stderr(predicate::str::contains("blob.toml:8:13"))
+        .stderr(predicate::str::contains("immune_to_sleep"));
+
+    let dir = package_with_actor(indoc!(r#"
+        [defense]
+        fire = 99
+        lightning = 1
+    "#), SCRIPT);
+
+    starpkg()
+        .arg("build")
+        .arg("--no-compile")
+        .current_dir(dir.path())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("error[SP0505]: unknown element 'lightning'"));
+
+    // The generated $Actor points at a $StatusTable, which nothing defines.
+    let dir = package_with_actor(indoc!(r#"
+        [stats]
+        max_hp = 5
+    "#), SCRIPT);
+
+    starpkg()
+        .arg("build")
+        .arg("--no-compile")
+        .current_dir(dir.path())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("error[SP0504]: actor 'blob' uses $StatusTable, but doesn't define it"))
+        .stderr(predicate::str::contains("add a [status] table to blob.toml"));
+
+    let dir = package_with_actor("", indoc!(r#"
+        #new:SpriteTable $SpriteTable
+        00800000 01000000 001400FF 00000000 $DefenseTable 00000000 00000000 00F60000 00000000
+    "#));
+
+    starpkg()
+        .arg("build")
+        .arg("--no-compile")
+        .current_dir(dir.path())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("actor 'blob' uses $DefenseTable, but doesn't define it"))
+        .stderr(predicate::str::contains("blob.bscr:2:37"));
+}
+
+#[test]
+fn writes_actor_type_offsets() {
+    let dir = package_with_actor(indoc!(r#"
+        tattle_cam_offset = [0, -10, 5]
+        shadow_offset = 4
+    "#), STRUCT);
+
+    starpkg()
+        .arg("build")
+        .arg("--no-compile")
+        .current_dir(dir.path())
+        .assert()
+        .success();
+
+    dir.child(".build/battle/ActorTypes.xml").assert(predicate::str::is_match(
+        r#"<Actor id="00" name="[0-9A-F]+" tattle="[0-9A-F]+" tattleCamOffset="0,-10,5" shadowOffset="4"/>"#
+    ).unwrap());
+
+    let dir = package_with_actor("tattle_cam_offset = [0, 200, 0]\n", STRUCT);
+
+    starpkg()
+        .arg("build")
+        .arg("--no-compile")
+        .current_dir(dir.path())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("error[SP0501]: actor tattle_cam_offset is 200, but must be from -128 to 127"));
+}
+
+#[test]
+fn generates_parts() {
+    let dir = package_with_actor(indoc!(r#"
+        [stats]
+        max_hp = 5
+
+        [status]
+
+        [defense]
+        normal = 1
+
+        [[parts]]
+        name = "body"
+        target_offset = [0, 20]
+        projectile_target_offset = [0, -10]
+
+        [parts.idle]
+        normal = "blob:idle"
+        sleep = "blob:idle"
+
+        [[parts]]
+        name = "legs"
+        flags = 0x100
+        offset = [-4, 0, 1]
+        opacity = 128
+
+        [parts.idle]
+        normal = "blob:idle"
+
+        [parts.defense]
+        smash = 99
+    "#), indoc!(r#"
+        #new:Script $Script_Init
+        Call SetAnimation ( .Actor:Self {ActorPart:blob:legs} 00000000 )
+        Return
+        End
+    "#));
+
+    dir.child("src/sprite/blob/SpriteSheet.xml").write_str(indoc!(r#"
+        <SpriteSheet>
+            <PaletteList>
+                <Palette id="0" src="0.png" front="true"/>
+            </PaletteList>
+            <RasterList/>
+            <AnimationList>
+                <Animation name="idle"/>
+            </AnimationList>
+        </SpriteSheet>
+    "#)).unwrap();
+
+    starpkg()
+        .arg("build")
+        .arg("--no-compile")
+        .current_dir(dir.path())
+        .assert()
+        .success();
+
+    let bpat = dir.child(".build/battle/formation/import/actor/00_blob.bpat");
+
+    bpat.assert(predicate::str::contains("[SpriteCount] 2`s\n"));
+    bpat.assert(predicate::str::contains(indoc!(r#"
+        #new:SpriteTable $SpriteTable
+        00800000 01000000 001400FF $IdleAnimations_body $DefenseTable 00000000 00000000 00F60000 00000000
+        00000100 02FC0001 00000080 $IdleAnimations_legs $DefenseTable_legs 00000000 00000000 00000000 00000000
+
+        #new:IdleAnimations $IdleAnimations_body
+        .Status:Normal 00010000
+        .Status:Sleep 00010000
+        .Status:End
+    "#)));
+    bpat.assert(predicate::str::contains(indoc!(r#"
+        #new:DefenseTable $DefenseTable_legs
+        .Element:Normal 0`
+        .Element:Smash 99`
+        .Element:End
+    "#)));
+    bpat.assert(predicate::str::contains("Call SetAnimation ( .Actor:Self 02 00000000 )"));
+}
+
+#[test]
+fn checks_parts() {
+    let dir = package_with_actor(indoc!(r#"
+        [[parts]]
+        name = "body"
+        idle = { normal = "blob:idle" }
+
+        [[parts]]
+        name = "body"
+        idle = { normal = "blob:idle" }
+    "#), "");
+
+    starpkg()
+        .arg("build")
+        .arg("--no-compile")
+        .current_dir(dir.path())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "error[SP0506]: bad actor part 'body': another part has the same name"
+        ));
+
+    let dir = package_with_actor(indoc!(r#"
+        [[parts]]
+        name = "body"
+        idle = { sleep = "blob:idle" }
+    "#), "");
+
+    starpkg()
+        .arg("build")
+        .arg("--no-compile")
+        .current_dir(dir.path())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("bad actor part 'body': the part has no `normal` idle animation"));
+
+    let dir = package_with_actor("", &STRUCT.replace("Return", "Call SetAnimation ( .Actor:Self {ActorPart:blob:legs} 00000000 )"));
+
+    starpkg()
+        .arg("build")
+        .arg("--no-compile")
+        .current_dir(dir.path())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("error[SP0210]: actor {Actor:test_pkg/blob} has no part 'legs'"))
+        .stderr(predicate::str::contains("blob.bscr:16:49"))
+        .stderr(predicate::str::contains("declares no parts"));
+}
+
+#[test]
+fn validates_actor_struct() {
+    let dir = package_with_actor("", indoc!(r#"
+        #new:Script $Script_Init
+        Return
+        End
+    "#));
+
+    starpkg()
+        .arg("build")
+        .arg("--no-compile")
+        .current_dir(dir.path())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("error[SP0507]: actor 'blob' has no $Actor struct"));
+
+    let dir = package_with_actor("", &STRUCT.replace("[SpriteCount] 1`s\n", ""));
+
+    starpkg()
+        .arg("build")
+        .arg("--no-compile")
+        .current_dir(dir.path())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("error[SP0508]: actor 'blob' is missing the [SpriteCount] field"));
+
+    let dir = package_with_actor("", &STRUCT.replace("{Actor:blob}", "{Actor:goomba}"));
+
+    starpkg()
+        .arg("build")
+        .arg("--no-compile")
+        .current_dir(dir.path())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("error[SP0509]"))
+        .stderr(predicate::str::contains("blob.bscr:2:15"))
+        .stderr(predicate::str::contains("use `{Actor:blob}`"));
+
+    let dir = package_with_actor("", &STRUCT
+        .replace("5`b", "0`b")
+        .replace("[Script]      $Script_Init", "[Script]      $Script_Init\n[Speed]       3`b"));
+
+    starpkg()
+        .arg("build")
+        .arg("--no-compile")
+        .current_dir(dir.path())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("error[SP0510]: bad actor field [MaxHP]: expected a byte from 1 to 255"))
+        .stderr(predicate::str::contains("bad actor field [Speed]: no such field"))
+        .stderr(predicate::str::contains("aborting due to 2 previous errors"));
+
+    // Offsets are signed, whether decimal or hex.
+    let dir = package_with_actor("", &STRUCT.replace(
+        "[Script]      $Script_Init",
+        "[Script]      $Script_Init\n[HealthBar]   -10`b F6\n[Size]        -1`b 24`b",
+    ));
+
+    starpkg()
+        .arg("build")
+        .arg("--no-compile")
+        .current_dir(dir.path())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("bad actor field [Size]: expected two bytes from 0 to 255"))
+        .stderr(predicate::str::contains("[HealthBar]").not());
+
+    // Pointers are local to the script, so every one used must be defined in it.
+    let dir = package_with_actor("", &STRUCT
+        .replace("[Script]      $Script_Init", "[Script]      $Script_Main")
+        .replace("Return", "Call BindTakeTurn ( .Actor:Self $Script_TakeTurn )"));
+
+    starpkg()
+        .arg("build")
+        .arg("--no-compile")
+        .current_dir(dir.path())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("error[SP0511]: actor 'blob' uses $Script_Main, but its script doesn't define it"))
+        .stderr(predicate::str::contains("blob.bscr:7:15"))
+        .stderr(predicate::str::contains("error[SP0511]: actor 'blob' uses $Script_TakeTurn, but its script doesn't define it"))
+        .stderr(predicate::str::contains("blob.bscr:16:33"))
+        .stderr(predicate::str::contains("aborting due to 2 previous errors"));
+
+    let dir = package_with_actor("", &format!("{}\n\n#new:Script $Script_Init\nReturn\nEnd\n", STRUCT));
+
+    starpkg()
+        .arg("build")
+        .arg("--no-compile")
+        .current_dir(dir.path())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("error[SP0512]: actor 'blob' defines $Script_Init more than once"))
+        .stderr(predicate::str::contains("blob.bscr:19:13"));
+}
@@ -0,0 +1,71 @@
+//! Fixtures shared by the integration tests. Each test binary uses only some of them.
+#![allow(dead_code)]
+
+use assert_cmd::Command;
+use assert_fs::prelude::*;
+use indoc::indoc;
+
+pub const FIZZLIT: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/examples/fizzlit/src/sprite/fizzlit");
+
+pub fn tempdir() -> assert_fs::TempDir {
+    assert_fs::TempDir::new().unwrap()
+}
+
+pub fn starpkg() -> Command {
+    Command::cargo_bin("starpkg").unwrap()
+}
+
+/// A package `test_pkg` whose only source is `strings.str`.
+pub fn package_with_strings(strings: &str) -> assert_fs::TempDir {
+    let dir = tempdir();
+
+    dir.child("starpkg.toml").write_str(indoc!(r#"
+        name = "test_pkg"
+        version = "0.1.0"
+    "#)).unwrap();
+
+    dir.child("src/string/strings.str").write_str(strings).unwrap();
+
+    dir
+}
+
+/// A package with an actor `blob`, whose TOML ends with `toml` and whose script is `bscr`.
+pub fn package_with_actor(toml: &str, bscr: &str) -> assert_fs::TempDir {
+    let dir = package_with_strings(indoc!(r#"
+        #string:(blob_name)
+        Blob[END]
+
+        #string:(blob_tattle)
+        This is a Blob. Max HP: {Actor:blob:MaxHP}[END]
+    "#));
+
+    dir.child("src/actor/blob/blob.toml").write_str(&format!(
+        "name = \"blob_name\"\ntattle = \"blob_tattle\"\n\n{}",
+        toml,
+    )).unwrap();
+
+    dir.child("src/actor/blob/blob.bscr").write_str(bscr).unwrap();
+
+    dir
+}
+
+/// A package with a sprite `blob`, made from two of Fizzlit's rasters and palettes.
+pub fn package_with_sprite_toml(sprite_toml: &str) -> assert_fs::TempDir {
+    let dir = tempdir();
+
+    dir.child("starpkg.toml").write_str(indoc!(r#"
+        name = "test_pkg"
+        version = "0.1.0"
+    "#)).unwrap();
+
+    let sprite_dir = dir.child("src/sprite/blob");
+    sprite_dir.copy_from(FIZZLIT, &[
+        "Palette_00.png",
+        "Palette_01.png",
+        "fizzlit_purple1.png",
+        "fizzlit_purple2.png",
+    ]).unwrap();
+    sprite_dir.child("sprite.toml").write_str(sprite_toml).unwrap();
+
+    dir
+}
@@ -0,0 +1,364 @@
+use assert_cmd::Command;
+use assert_fs::prelude::*;
+use predicates::prelude::*;
+use indoc::indoc;
+
+fn tempdir() -> assert_fs::TempDir {
+    assert_fs::TempDir::new().unwrap()
+}
+
+fn starpkg() -> Command {
+    Command::cargo_bin("starpkg").unwrap()
+}
+
+#[test]
+fn reports_every_load_error() {
+    let dir = tempdir();
+
+    dir.child("starpkg.toml").write_str(indoc!(r#"
+        name = "test_pkg"
+        version = "0.1.0"
+    "#)).unwrap();
+
+    dir.child("src/string/naughty.str").write_str(indoc!(r#"
+        #string:01:(this is naughty)
+        Sample text[END]
+
+        #string:01:(so is this)
+        Sample text[END]
+    "#)).unwrap();
+
+    dir.child("src/sprite/empty/README.md").touch().unwrap();
+
+    starpkg()
+        .arg("build")
+        .arg("--no-compile")
+        .current_dir(dir.path())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("'this is naughty'"))
+        .stderr(predicate::str::contains("'so is this'"))
+        .stderr(predicate::str::contains("missing SpriteSheet.xml"))
+        .stderr(predicate::str::contains("aborting due to 3 previous errors"));
+
+    dir.child(".build").assert(predicate::path::missing());
+}
+
+#[test]
+fn reports_every_resolve_error() {
+    let dir = tempdir();
+
+    dir.child("starpkg.toml").write_str(indoc!(r#"
+        name = "test_pkg"
+        version = "0.1.0"
+    "#)).unwrap();
+
+    dir.child("src/actor/cool_actor/cool_actor.toml").write_str(indoc!(r#"
+        name = "namestring"
+        tattle = "tattlestring"
+    "#)).unwrap();
+
+    dir.child("src/actor/cool_actor/cool_actor.bscr").write_str(indoc!(r#"
+        #new:Actor $Actor
+        [Index]       {Actor:cool_actor}
+        [MaxHP]       5`b
+        [StatusTable] $StatusTable
+        [SpriteCount] 1`s
+        [SpriteTable] $SpriteTable
+        [Script]      $Script_Init
+
+        #new:IdleAnimations $IdleAnimations
+        .Status:Normal {Sprite:nope:idle}
+        .Status:Sleep  {Sprite:nada:sleep}
+        .Status:End
+
+        #new:Data $Data
+        {String:missing} {Actor:ghost}
+
+        #new:StatusTable $StatusTable
+        .Status:End
+
+        #new:SpriteTable $SpriteTable
+        00800000 01000000 001400FF $IdleAnimations 00000000 00000000 00000000 00F60000 00000000
+
+        #new:Script $Script_Init
+        Return
+        End
+    "#)).unwrap();
+
+    dir.child("src/string/strings.str").write_str(indoc!(r#"
+        #string:01:(namestring)
+        [END]
+
+        #string:01:(tattlestring)
+        [END]
+    "#)).unwrap();
+
+    starpkg()
+        .arg("build")
+        .arg("--no-compile")
+        .current_dir(dir.path())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("unknown sprite"))
+        .stderr(predicate::str::contains("test_pkg/nope"))
+        .stderr(predicate::str::contains("test_pkg/nada"))
+        .stderr(predicate::str::contains("unknown string"))
+        .stderr(predicate::str::contains("unknown actor"))
+        .stderr(predicate::str::contains("aborting due to 4 previous errors"));
+}
+
+#[test]
+fn points_at_offending_expression() {
+    let dir = tempdir();
+
+    dir.child("starpkg.toml").write_str(indoc!(r#"
+        name = "test_pkg"
+        version = "0.1.0"
+    "#)).unwrap();
+
+    dir.child("src/actor/cool_actor/cool_actor.toml").write_str(indoc!(r#"
+        name = "namestring"
+        tattle = "namestring"
+    "#)).unwrap();
+
+    dir.child("src/actor/cool_actor/cool_actor.bscr").write_str(indoc!(r#"
+        % comments shouldn't throw off line numbers
+        #new:Actor $Actor
+        [Index]       {Actor:cool_actor}
+        [MaxHP]       5`b
+        [StatusTable] $StatusTable
+        [SpriteCount] 1`s
+        [SpriteTable] $SpriteTable
+        [Script]      $Script_Init
+
+        /% nor should
+           block comments %/
+        #new:Data $Data
+        {Actor:cool_actor} {String:missing}
+
+        #new:StatusTable $StatusTable
+        .Status:End
+
+        #new:SpriteTable $SpriteTable
+        00800000 01000000 001400FF 00000000 00000000 00000000 00000000 00F60000 00000000
+
+        #new:Script $Script_Init
+        Return
+        End
+    "#)).unwrap();
+
+    dir.child("src/string/strings.str").write_str(indoc!(r#"
+        #string:01:(namestring)
+        [END]
+    "#)).unwrap();
+
+    starpkg()
+        .arg("build")
+        .arg("--no-compile")
+        .current_dir(dir.path())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("cool_actor.bscr:13:28"))
+        .stderr(predicate::str::contains(indoc!("
+            13 | {Actor:cool_actor} {String:missing}
+               |                            ^^^^^^^ no such string
+        ")))
+        .stderr(predicate::str::contains("\x1b[").not());
+}
+
+#[test]
+fn suggests_animations() {
+    let dir = tempdir();
+
+    dir.child("starpkg.toml").write_str(indoc!(r#"
+        name = "test_pkg"
+        version = "0.1.0"
+    "#)).unwrap();
+
+    dir.child("src/sprite/blob/SpriteSheet.xml").write_str(indoc!(r#"
+        <SpriteSheet>
+            <PaletteList>
+                <Palette id="0" name="green" src="Palette_00.png"/>
+            </PaletteList>
+            <AnimationList>
+                <Animation name="idle"/>
+                <Animation name="walk"/>
+            </AnimationList>
+        </SpriteSheet>
+    "#)).unwrap();
+
+    dir.child("src/actor/cool_actor/cool_actor.toml").write_str(indoc!(r#"
+        name = "namestring"
+        tattle = "namestring"
+    "#)).unwrap();
+
+    dir.child("src/actor/cool_actor/cool_actor.bscr").write_str(indoc!(r#"
+        #new:Actor $Actor
+        [Index]       {Actor:cool_actor}
+        [MaxHP]       5`b
+        [StatusTable] $StatusTable
+        [SpriteCount] 1`s
+        [SpriteTable] $SpriteTable
+        [Script]      $Script_Init
+
+        #new:IdleAnimations $IdleAnimations
+        .Status:Normal {Sprite:blob:run}
+        .Status:Sleep  {Sprite:blob:idle:red}
+        .Status:End
+
+        #new:StatusTable $StatusTable
+        .Status:End
+
+        #new:SpriteTable $SpriteTable
+        00800000 01000000 001400FF $IdleAnimations 00000000 00000000 00000000 00F60000 00000000
+
+        #new:Script $Script_Init
+        Return
+        End
+    "#)).unwrap();
+
+    dir.child("src/string/strings.str").write_str(indoc!(r#"
+        #string:01:(namestring)
+        [END]
+    "#)).unwrap();
+
+    starpkg()
+        .arg("build")
+        .arg("--no-compile")
+        .current_dir(dir.path())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("error[SP0203]: sprite {Sprite:test_pkg/blob} has no animation 'run'"))
+        .stderr(predicate::str::contains("has animations: idle, walk"))
+        .stderr(predicate::str::contains("has no palette 'red'"))
+        .stderr(predicate::str::contains("has palettes: green"));
+}
+
+#[test]
+fn points_at_manifest_key() {
+    let dir = tempdir();
+
+    dir.child("starpkg.toml").write_str(indoc!(r#"
+        name = "test_pkg"
+        version = "0.1.0"
+
+        [dependencies]
+        bad_ = { path = "bad" }
+    "#)).unwrap();
+
+    starpkg()
+        .arg("build")
+        .arg("--no-compile")
+        .current_dir(dir.path())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("starpkg.toml:5:1"))
+        .stderr(predicate::str::contains("^^^^ invalid name"));
+}
+
+#[test]
+fn json_message_format() {
+    let dir = tempdir();
+
+    dir.child("starpkg.toml").write_str(indoc!(r#"
+        name = "test_pkg"
+        version = "0.1.0"
+    "#)).unwrap();
+
+    dir.child("src/string/strings.str").write_str(indoc!(r#"
+        #string:01:(bad name)
+        [END]
+    "#)).unwrap();
+
+    let output = starpkg()
+        .arg("build")
+        .arg("--no-compile")
+        .arg("--message-format")
+        .arg("json")
+        .current_dir(dir.path())
+        .output()
+        .unwrap();
+
+    assert!(!output.status.success());
+    assert!(output.stderr.is_empty());
+
+    let events: Vec<serde_json::Value> = String::from_utf8(output.stdout)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+
+    let diagnostic = events.iter()
+        .find(|event| event["type"] == "diagnostic")
+        .unwrap();
+    assert_eq!(diagnostic["severity"], "error");
+    assert_eq!(diagnostic["code"], "SP0003");
+    assert_eq!(diagnostic["line"], 1);
+    assert_eq!(diagnostic["column"], 13);
+    assert!(diagnostic["file"].as_str().unwrap().ends_with("strings.str"));
+    assert!(diagnostic["message"].as_str().unwrap().contains("invalid export name"));
+
+    assert!(events.iter().any(|event| {
+        event["type"] == "build-finished" && event["success"] == false
+    }));
+}
+
+#[test]
+fn json_warnings_and_fatal_errors() {
+    let dir = tempdir();
+
+    dir.child("starpkg.toml").write_str(indoc!(r#"
+        name = "test_pkg"
+        version = "0.1.0"
+    "#)).unwrap();
+
+    dir.child("src/string/strings.str").write_str(indoc!(r#"
+        #string:01:(greeting)
+        Hello[END]
+    "#)).unwrap();
+
+    dir.child("src/string/fr/strings.str").write_str(indoc!(r#"
+        #string:01:(greting)
+        Bonjour[END]
+    "#)).unwrap();
+
+    let events = |args: &[&str]| -> Vec<serde_json::Value> {
+        let output = starpkg()
+            .args(args)
+            .arg("--message-format")
+            .arg("json")
+            .current_dir(dir.path())
+            .output()
+            .unwrap();
+        assert!(output.stderr.is_empty());
+
+        String::from_utf8(output.stdout)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    };
+
+    // Warnings don't stop the build.
+    let build = events(&["build", "--no-compile"]);
+    let warnings: Vec<&serde_json::Value> = build.iter()
+        .filter(|event| event["type"] == "diagnostic" && event["severity"] == "warning")
+        .collect();
+    assert_eq!(warnings.len(), 2);
+    assert!(warnings[0]["message"].as_str().unwrap().contains("is missing 1 of 1 strings"));
+    let warning = warnings[1];
+    assert!(warning["message"].as_str().unwrap().contains("translates {String:test_pkg/greting}"));
+    assert_eq!(warning["line"], 1);
+    assert!(warning["file"].as_str().unwrap().ends_with("strings.str"));
+    assert!(build.iter().any(|event| event["type"] == "build-finished" && event["success"] == true));
+
+    // Fatal errors and the summary are diagnostics too, not log messages.
+    let build = events(&["build", "--no-compile", "--locale", "de"]);
+    assert!(build.iter().all(|event| event["type"] != "log" || event["level"] != "error"));
+    let errors: Vec<&str> = build.iter()
+        .filter(|event| event["type"] == "diagnostic" && event["severity"] == "error")
+        .map(|event| event["message"].as_str().unwrap())
+        .collect();
+    assert_eq!(errors, ["no strings are translated to locale 'de'", "aborting due to 1 previous error"]);
+}
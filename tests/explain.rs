@@ -0,0 +1,57 @@
+use assert_cmd::Command;
+use predicates::prelude::*;
+
+use std::fs;
+
+fn starpkg() -> Command {
+    Command::cargo_bin("starpkg").unwrap()
+}
+
+#[test]
+fn explains_code() {
+    starpkg()
+        .arg("explain")
+        .arg("SP0203")
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with("## SP0203: sprite has no such animation"));
+}
+
+#[test]
+fn code_is_case_insensitive() {
+    starpkg()
+        .arg("explain")
+        .arg("sp0203")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("SP0203"));
+}
+
+#[test]
+fn unknown_code() {
+    starpkg()
+        .arg("explain")
+        .arg("SP9999")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("no such error code: SP9999"));
+}
+
+#[test]
+fn every_documented_code_is_explained() {
+    let reference = fs::read_to_string("docs/reference/errors.md").unwrap();
+
+    for entry in fs::read_dir("docs/reference/errors").unwrap() {
+        let path = entry.unwrap().path();
+        let code = path.file_stem().unwrap().to_str().unwrap();
+
+        assert!(reference.contains(&format!("{{{{#include errors/{}.md}}}}", code)));
+
+        starpkg()
+            .arg("explain")
+            .arg(code)
+            .assert()
+            .success()
+            .stdout(fs::read_to_string(&path).unwrap());
+    }
+}
@@ -0,0 +1,159 @@
+mod common;
+
+use assert_fs::prelude::*;
+use predicates::prelude::*;
+use indoc::indoc;
+
+use std::fs::File;
+
+use common::{starpkg, package_with_sprite_toml};
+
+/// A sprite `blob` with two palettes and two animations.
+fn package_with_blob() -> assert_fs::TempDir {
+    package_with_sprite_toml(indoc!(r#"
+        [[palette]]
+        name = "purple"
+        src = "Palette_00.png"
+
+        [[palette]]
+        name = "yellow"
+        src = "Palette_01.png"
+
+        [[raster]]
+        src = "fizzlit_purple1.png"
+
+        [[raster]]
+        src = "fizzlit_purple2.png"
+
+        [[animation]]
+        name = "idle"
+        frames = [
+            { raster = "fizzlit_purple1", duration = 30 },
+            { raster = "fizzlit_purple2", duration = 2 },
+        ]
+
+        [[animation]]
+        name = "still"
+        frames = [
+            { raster = "fizzlit_purple1", duration = 1 },
+        ]
+    "#))
+}
+
+#[test]
+fn previews_gif() {
+    let dir = package_with_blob();
+
+    starpkg()
+        .args(["preview", "sprite", "blob", "--anim", "idle"])
+        .current_dir(dir.path())
+        .assert()
+        .success();
+
+    let gif = File::open(dir.child(".build/preview/blob_idle.gif").path()).unwrap();
+    let mut decoder = gif::DecodeOptions::new().read_info(gif).unwrap();
+
+    // 30 and 2 frames at 30fps, in hundredths of a second.
+    let mut delays = Vec::new();
+    while let Some(frame) = decoder.read_next_frame().unwrap() {
+        assert!(frame.buffer.iter().any(|&index| Some(index) != frame.transparent));
+        delays.push(frame.delay);
+    }
+    assert_eq!(delays, vec![100, 7]);
+}
+
+#[test]
+fn previews_apng() {
+    let dir = package_with_blob();
+
+    starpkg()
+        .args(["preview", "sprite", "blob", "--palette", "yellow", "--scale", "2", "-o", "idle.png"])
+        .current_dir(dir.path())
+        .assert()
+        .success();
+
+    let png = File::open(dir.child("idle.png").path()).unwrap();
+    let reader = png::Decoder::new(png).read_info().unwrap();
+    let info = reader.info();
+
+    assert_eq!(info.animation_control.unwrap().num_frames, 2);
+    assert_eq!(info.color_type, png::ColorType::Rgba);
+    assert_eq!(info.width % 2, 0);
+}
+
+#[test]
+fn palette_overrides_animation() {
+    let dir = package_with_blob();
+
+    let sprite_toml = dir.child("src/sprite/blob/sprite.toml");
+    let contents = std::fs::read_to_string(sprite_toml.path()).unwrap();
+    sprite_toml.write_str(&(contents + indoc!(r#"
+
+        [[animation]]
+        name = "still_yellow"
+        frames = [
+            { raster = "fizzlit_purple1", duration = 1, palette = "yellow" },
+        ]
+    "#))).unwrap();
+
+    let preview = |args: &[&str], output: &str| {
+        starpkg()
+            .args(["preview", "sprite", "blob", "-o", output])
+            .args(args)
+            .current_dir(dir.path())
+            .assert()
+            .success();
+        std::fs::read(dir.child(output).path()).unwrap()
+    };
+
+    let purple = preview(&["--anim", "still"], "purple.gif");
+    let yellow = preview(&["--anim", "still_yellow"], "yellow.gif");
+    assert_ne!(purple, yellow);
+
+    // Indices are in hex, and win over the animation's `SetPalette`.
+    assert_eq!(preview(&["--anim", "still_yellow", "--palette", "0"], "overridden.gif"), purple);
+    assert_eq!(preview(&["--anim", "still", "--palette", "yellow"], "named.gif"), yellow);
+}
+
+#[test]
+fn previews_contact_sheet() {
+    let dir = package_with_blob();
+
+    starpkg()
+        .args(["preview", "sprite", "blob", "--contact-sheet"])
+        .current_dir(dir.path())
+        .assert()
+        .success();
+
+    let png = File::open(dir.child(".build/preview/blob.png").path()).unwrap();
+    let reader = png::Decoder::new(png).read_info().unwrap();
+    let info = reader.info();
+
+    // Two rows, the first with two frames. The rasters are 64x48 and cells are 2px apart.
+    assert!(info.animation_control.is_none());
+    assert_eq!((info.width, info.height), (64 + 2 + 64, 48 + 2 + 48));
+}
+
+#[test]
+fn unknown_animation() {
+    let dir = package_with_blob();
+
+    starpkg()
+        .args(["preview", "sprite", "blob", "--anim", "walk"])
+        .current_dir(dir.path())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("sprite 'blob' has no animation 'walk' (it has: idle, still)"));
+}
+
+#[test]
+fn unknown_sprite() {
+    let dir = package_with_blob();
+
+    starpkg()
+        .args(["preview", "sprite", "fizzlit"])
+        .current_dir(dir.path())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("no such sprite: fizzlit"));
+}
@@ -156,11 +156,24 @@ fn using_private_export_locally() {
         tattle = "tattlestring"
     "#)).unwrap();
 
-    // Note: this is technically an invalid actor script so this test may fail in the future if
-    // decent script sanitization is added!
     dir.child("src/actor/_cool_actor/_cool_actor.bscr").write_str(indoc!(r#"
         #new:Actor $Actor
-        [Index] {Actor:_cool_actor}
+        [Index]       {Actor:_cool_actor}
+        [MaxHP]       5`b
+        [StatusTable] $StatusTable
+        [SpriteCount] 1`s
+        [SpriteTable] $SpriteTable
+        [Script]      $Script_Init
+
+        #new:StatusTable $StatusTable
+        .Status:End
+
+        #new:SpriteTable $SpriteTable
+        00800000 01000000 001400FF 00000000 00000000 00000000 00000000 00F60000 00000000
+
+        #new:Script $Script_Init
+        Return
+        End
     "#)).unwrap();
 
     dir.child("src/string/strings.str").write_str(indoc!(r#"
@@ -0,0 +1,1161 @@
+mod common;
+
+use assert_fs::prelude::*;
+use predicates::prelude::*;
+use indoc::indoc;
+
+use std::path::Path;
+
+use common::{FIZZLIT, tempdir, starpkg, package_with_sprite_toml};
+
+#[test]
+fn generates_spritesheet_from_toml() {
+    let dir = package_with_sprite_toml(indoc!(r#"
+        [[palette]]
+        name = "purple"
+        src = "Palette_00.png"
+
+        [[palette]]
+        name = "yellow"
+        src = "Palette_01.png"
+
+        [[raster]]
+        src = "fizzlit_purple1.png"
+
+        [[raster]]
+        name = "squished"
+        src = "fizzlit_purple2.png"
+        palette = "yellow"
+
+        [[animation]]
+        name = "idle"
+        frames = [
+            { raster = "fizzlit_purple1", duration = 30 },
+            { raster = "squished", duration = 2, offset = [0, -2, 0] },
+        ]
+
+        [[animation]]
+        name = "squish"
+        loop = false
+        frames = [
+            { raster = "squished", duration = 4, palette = "yellow" },
+        ]
+    "#));
+
+    dir.child("src/actor/blob/blob.toml").write_str(indoc!(r#"
+        name = "blob_name"
+        tattle = "blob_name"
+
+        [stats]
+        max_hp = 5
+
+        [status]
+    "#)).unwrap();
+
+    dir.child("src/actor/blob/blob.bscr").write_str(indoc!(r#"
+        #new:IdleAnimations $IdleAnimations
+        .Status:Normal {Sprite:blob:squish:yellow}
+        .Status:End
+
+        #new:SpriteTable $SpriteTable
+        00800000 01000000 001400FF $IdleAnimations 00000000 00000000 00000000 00F60000 00000000
+
+        #new:Script $Script_Init
+        Return
+        End
+    "#)).unwrap();
+
+    dir.child("src/string/strings.str").write_str(indoc!(r#"
+        #string:01:(blob_name)
+        [END]
+    "#)).unwrap();
+
+    starpkg()
+        .arg("build")
+        .arg("--no-compile")
+        .current_dir(dir.path())
+        .assert()
+        .success();
+
+    let sprite_dir = dir.child(".build/sprite/npc/src/01");
+    sprite_dir.child("sprite.toml").assert(predicate::path::missing());
+    sprite_dir.child("fizzlit_purple1.png").assert(predicate::path::exists());
+    sprite_dir.child("SpriteSheet.xml").assert(indoc!(r#"
+        <?xml version="1.0" encoding="UTF-8" standalone="no"?>
+        <SpriteSheet a="1" b="1">
+        	<PaletteList>
+        		<Palette id="0" name="purple" src="Palette_00.png"/>
+        		<Palette id="1" name="yellow" src="Palette_01.png"/>
+        	</PaletteList>
+        	<RasterList>
+        		<Raster id="0" palette="0" src="fizzlit_purple1.png"/>
+        		<Raster id="1" palette="1" src="fizzlit_purple2.png"/>
+        	</RasterList>
+        	<AnimationList>
+        		<Animation name="idle">
+        			<Component xyz="0,0,0">
+        				<Command val="3000"/>
+        				<Command val="0"/>
+        				<Command val="0"/>
+        				<Command val="0"/>
+        				<Command val="1000"/>
+        				<Command val="1E"/>
+        				<Command val="3000"/>
+        				<Command val="0"/>
+        				<Command val="FFFE"/>
+        				<Command val="0"/>
+        				<Command val="1001"/>
+        				<Command val="2"/>
+        				<Command val="2000"/>
+        			</Component>
+        		</Animation>
+        		<Animation name="squish">
+        			<Component xyz="0,0,0">
+        				<Command val="6001"/>
+        				<Command val="1001"/>
+        				<Command val="4"/>
+        				<Command val="2000"/>
+        			</Component>
+        		</Animation>
+        	</AnimationList>
+        </SpriteSheet>
+    "#));
+
+    dir.child(".build/battle/formation/import/actor/00_blob.bpat")
+        .assert(predicate::str::contains(".Status:Normal 00010101"));
+}
+
+#[test]
+fn escapes_names_in_spritesheet() {
+    let dir = package_with_sprite_toml(indoc!(r#"
+        [[palette]]
+        name = "R&D \"<1>\""
+        src = "Palette_00.png"
+
+        [[raster]]
+        src = "fizzlit_purple1.png"
+
+        [[animation]]
+        name = "<idle>"
+        frames = [{ raster = "fizzlit_purple1", duration = 2 }]
+    "#));
+
+    starpkg()
+        .arg("build")
+        .arg("--no-compile")
+        .current_dir(dir.path())
+        .assert()
+        .success();
+
+    dir.child(".build/sprite/npc/src/01/SpriteSheet.xml")
+        .assert(predicate::str::contains(r#"<Palette id="0" name="R&amp;D &quot;&lt;1&gt;&quot;" src="Palette_00.png"/>"#))
+        .assert(predicate::str::contains(r#"<Animation name="&lt;idle&gt;">"#));
+}
+
+#[test]
+fn unknown_raster_in_toml() {
+    let dir = package_with_sprite_toml(indoc!(r#"
+        [[palette]]
+        name = "purple"
+        src = "Palette_00.png"
+
+        [[raster]]
+        src = "fizzlit_purple1.png"
+
+        [[animation]]
+        name = "idle"
+        frames = [
+            { raster = "fizzlit_purple9", duration = 30 },
+        ]
+    "#));
+
+    starpkg()
+        .arg("build")
+        .arg("--no-compile")
+        .current_dir(dir.path())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("error[SP0406]: unknown raster 'fizzlit_purple9'"))
+        .stderr(predicate::str::contains("sprite.toml:11:16"))
+        .stderr(predicate::str::contains("rasters are: fizzlit_purple1"));
+}
+
+#[test]
+fn both_toml_and_spritesheet() {
+    let dir = package_with_sprite_toml(indoc!(r#"
+        [[palette]]
+        name = "purple"
+        src = "Palette_00.png"
+    "#));
+
+    dir.child("src/sprite/blob/SpriteSheet.xml").write_str("<SpriteSheet/>").unwrap();
+
+    starpkg()
+        .arg("build")
+        .arg("--no-compile")
+        .current_dir(dir.path())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("error[SP0409]"));
+}
+
+#[test]
+fn imports_aseprite() {
+    let dir = tempdir();
+
+    dir.child("starpkg.toml").write_str(indoc!(r#"
+        name = "test_pkg"
+        version = "0.1.0"
+    "#)).unwrap();
+
+    let sprite_dir = dir.child("src/sprite/blob");
+    sprite_dir.copy_from(FIZZLIT, &["*.ase"]).unwrap();
+    sprite_dir.child("sprite.toml").write_str(indoc!(r#"
+        [aseprite]
+        src = "fizzlit_purple.ase"
+        palette = "purple"
+
+        [[palette]]
+        name = "yellow"
+        src = "fizzlit_yellow.ase"
+    "#)).unwrap();
+
+    dir.child("src/actor/blob/blob.toml").write_str(indoc!(r#"
+        name = "blob_name"
+        tattle = "blob_name"
+
+        [stats]
+        max_hp = 5
+
+        [status]
+    "#)).unwrap();
+
+    dir.child("src/actor/blob/blob.bscr").write_str(indoc!(r#"
+        #new:IdleAnimations $IdleAnimations
+        .Status:Normal {Sprite:blob:up_idle:yellow}
+        .Status:End
+
+        #new:SpriteTable $SpriteTable
+        00800000 01000000 001400FF $IdleAnimations 00000000 00000000 00000000 00F60000 00000000
+
+        #new:Script $Script_Init
+        Return
+        End
+    "#)).unwrap();
+
+    dir.child("src/string/strings.str").write_str(indoc!(r#"
+        #string:01:(blob_name)
+        [END]
+    "#)).unwrap();
+
+    starpkg()
+        .arg("build")
+        .arg("--no-compile")
+        .current_dir(dir.path())
+        .assert()
+        .success();
+
+    let sprite_dir = dir.child(".build/sprite/npc/src/01");
+    sprite_dir.child("fizzlit_purple.ase").assert(predicate::path::missing());
+    sprite_dir.child("fizzlit_purple.png").assert(predicate::path::exists());
+    sprite_dir.child("fizzlit_yellow.png").assert(predicate::path::exists());
+    sprite_dir.child("fizzlit_purple_10.png").assert(predicate::path::exists());
+
+    // Frames 4 and 11 are the same as earlier frames, so are shared with them.
+    sprite_dir.child("fizzlit_purple_11.png").assert(predicate::path::missing());
+    sprite_dir.child("SpriteSheet.xml")
+        .assert(predicate::str::contains(r#"<Palette id="1" name="yellow" src="fizzlit_yellow.png"/>"#))
+        .assert(predicate::str::contains(r#"<Raster id="8" palette="0" src="fizzlit_purple_10.png"/>"#))
+        .assert(predicate::str::contains(r#"<Animation name="up_idle">"#));
+
+    // The imported frames should be identical to those the artist exported by hand.
+    for n in (1..=10).filter(|&n| n != 4) {
+        assert_eq!(
+            decode_png(sprite_dir.child(format!("fizzlit_purple_{}.png", n)).path()),
+            decode_png(&Path::new(FIZZLIT).join(format!("fizzlit_purple{}.png", n))),
+        );
+    }
+
+    dir.child(".build/battle/formation/import/actor/00_blob.bpat")
+        .assert(predicate::str::contains(".Status:Normal 00010100"));
+}
+
+#[test]
+fn aseprite_sources_not_copied() {
+    let dir = tempdir();
+
+    dir.child("starpkg.toml").write_str(indoc!(r#"
+        name = "test_pkg"
+        version = "0.1.0"
+    "#)).unwrap();
+
+    dir.child("src/sprite/fizzlit").copy_from(FIZZLIT, &["*"]).unwrap();
+
+    starpkg()
+        .arg("build")
+        .arg("--no-compile")
+        .current_dir(dir.path())
+        .assert()
+        .success();
+
+    let sprite_dir = dir.child(".build/sprite/npc/src/01");
+    sprite_dir.child("SpriteSheet.xml").assert(predicate::path::exists());
+    sprite_dir.child("fizzlit_purple.ase").assert(predicate::path::missing());
+}
+
+#[test]
+fn only_referenced_files_copied() {
+    let dir = tempdir();
+
+    dir.child("starpkg.toml").write_str(indoc!(r#"
+        name = "test_pkg"
+        version = "0.1.0"
+    "#)).unwrap();
+
+    let sprite_dir = dir.child("src/sprite/fizzlit");
+    sprite_dir.copy_from(FIZZLIT, &["*"]).unwrap();
+    std::fs::remove_file(sprite_dir.child("fizzlit_purple3.png").path()).unwrap();
+    sprite_dir.child("notes.txt").write_str("todo: more frames").unwrap();
+    sprite_dir.child("old/fizzlit_old.png").write_str("").unwrap();
+
+    starpkg()
+        .arg("build")
+        .arg("--no-compile")
+        .current_dir(dir.path())
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("warning: sprite 'fizzlit' is missing fizzlit_purple3.png"))
+        .stderr(predicate::str::contains("SpriteSheet.xml:10:35"))
+        .stderr(predicate::str::contains("^^^^^^^^^^^^^^^^^^^ file not found"))
+        .stderr(predicate::str::contains("warning: sprite 'fizzlit' does not use notes.txt"))
+        .stderr(predicate::str::contains("does not use fizzlit_purple4.png").not());
+
+    let out_dir = dir.child(".build/sprite/npc/src/01");
+    out_dir.child("SpriteSheet.xml").assert(predicate::path::exists());
+    out_dir.child("fizzlit_purple1.png").assert(predicate::path::exists());
+    out_dir.child("notes.txt").assert(predicate::path::missing());
+    out_dir.child("old").assert(predicate::path::missing());
+}
+
+#[test]
+fn shares_identical_rasters() {
+    let dir = package_with_sprite_toml(indoc!(r#"
+        [[palette]]
+        name = "purple"
+        src = "Palette_00.png"
+
+        [[raster]]
+        src = "fizzlit_purple1.png"
+
+        [[raster]]
+        src = "copy.png"
+
+        [[raster]]
+        src = "fizzlit_purple2.png"
+
+        [[animation]]
+        name = "idle"
+        frames = [
+            { raster = "fizzlit_purple1", duration = 2 },
+            { raster = "copy", duration = 2 },
+            { raster = "fizzlit_purple2", duration = 2 },
+        ]
+    "#));
+
+    let sprite_dir = dir.child("src/sprite/blob");
+    std::fs::copy(sprite_dir.child("fizzlit_purple1.png").path(), sprite_dir.child("copy.png").path()).unwrap();
+    dir.child("src/sprite/blob2").copy_from(sprite_dir.path(), &["*"]).unwrap();
+
+    // Each 64x48 raster is 1536 bytes, and each palette 32.
+    starpkg()
+        .arg("build")
+        .arg("--no-compile")
+        .current_dir(dir.path())
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("shared identical palettes and rasters within sprites, saving 3072 bytes"))
+        .stderr(predicate::str::contains("Palette_00.png in {Sprite:test_pkg/blob} is identical to Palette_00.png in {Sprite:test_pkg/blob2}"))
+        .stderr(predicate::str::contains("fizzlit_purple2.png in {Sprite:test_pkg/blob} is identical to fizzlit_purple2.png in {Sprite:test_pkg/blob2}"))
+        .stderr(predicate::str::contains("sprites have 3104 bytes of images identical to those in other sprites"));
+
+    for index in &["01", "02"] {
+        let out_dir = dir.child(".build/sprite/npc/src").child(index);
+        out_dir.child("copy.png").assert(predicate::path::missing());
+        out_dir.child("SpriteSheet.xml")
+            .assert(predicate::str::contains(r#"<Raster id="1" palette="0" src="fizzlit_purple2.png"/>"#))
+            .assert(predicate::str::contains(r#"<Raster id="2""#).not())
+            .assert(predicate::str::contains(
+                "<Command val=\"1000\"/>\n\t\t\t\t<Command val=\"2\"/>\n\t\t\t\t<Command val=\"1000\"/>\n\t\t\t\t<Command val=\"2\"/>\n\t\t\t\t<Command val=\"1001\"/>"
+            ));
+    }
+}
+
+#[test]
+fn shares_identical_palettes() {
+    let dir = package_with_sprite_toml(indoc!(r#"
+        [[palette]]
+        name = "purple"
+        src = "Palette_00.png"
+
+        [[palette]]
+        name = "copy"
+        src = "copy_palette.png"
+
+        [[raster]]
+        src = "fizzlit_purple1.png"
+
+        [[raster]]
+        src = "copy.png"
+        palette = "copy"
+
+        [[animation]]
+        name = "idle"
+        frames = [
+            { raster = "fizzlit_purple1", duration = 2 },
+            { raster = "copy", duration = 2, palette = "copy" },
+        ]
+    "#));
+
+    let sprite_dir = dir.child("src/sprite/blob");
+    std::fs::copy(sprite_dir.child("Palette_00.png").path(), sprite_dir.child("copy_palette.png").path()).unwrap();
+    std::fs::copy(sprite_dir.child("fizzlit_purple1.png").path(), sprite_dir.child("copy.png").path()).unwrap();
+
+    dir.child("src/actor/blob/blob.toml").write_str(indoc!(r#"
+        name = "blob_name"
+        tattle = "blob_name"
+
+        [stats]
+        max_hp = 5
+
+        [status]
+    "#)).unwrap();
+
+    dir.child("src/actor/blob/blob.bscr").write_str(indoc!(r#"
+        #new:IdleAnimations $IdleAnimations
+        .Status:Normal {Sprite:blob:idle:copy}
+        .Status:End
+
+        #new:SpriteTable $SpriteTable
+        00800000 01000000 001400FF $IdleAnimations 00000000 00000000 00000000 00F60000 00000000
+
+        #new:Script $Script_Init
+        Return
+        End
+    "#)).unwrap();
+
+    dir.child("src/string/strings.str").write_str(indoc!(r#"
+        #string:01:(blob_name)
+        [END]
+    "#)).unwrap();
+
+    // The copied palette is 32 bytes, and the raster shown with it 1536.
+    starpkg()
+        .arg("build")
+        .arg("--no-compile")
+        .current_dir(dir.path())
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("shared identical palettes and rasters within sprites, saving 1568 bytes"));
+
+    let out_dir = dir.child(".build/sprite/npc/src/01");
+    out_dir.child("copy_palette.png").assert(predicate::path::missing());
+    out_dir.child("copy.png").assert(predicate::path::missing());
+    out_dir.child("SpriteSheet.xml")
+        .assert(predicate::str::contains(r#"<Palette id="1""#).not())
+        .assert(predicate::str::contains(r#"<Raster id="1""#).not())
+        .assert(predicate::str::contains(r#"<Command val="6000"/>"#))
+        .assert(predicate::str::contains(r#"<Command val="6001"/>"#).not());
+
+    // The removed palette's name still refers to the identical one.
+    dir.child(".build/battle/formation/import/actor/00_blob.bpat")
+        .assert(predicate::str::contains(".Status:Normal 00010000"));
+}
+
+#[test]
+fn vanilla_palettes_not_shared() {
+    let dir = package_with_sprite_toml(indoc!(r#"
+        [[palette]]
+        name = "purple"
+        src = "Palette_00.png"
+
+        [[palette]]
+        name = "copy"
+        src = "copy_palette.png"
+
+        [[raster]]
+        src = "fizzlit_purple1.png"
+
+        [[animation]]
+        name = "idle"
+        frames = [{ raster = "fizzlit_purple1", duration = 2 }]
+    "#));
+
+    let sprite_dir = dir.child("src/sprite/blob");
+    std::fs::copy(sprite_dir.child("Palette_00.png").path(), sprite_dir.child("copy_palette.png").path()).unwrap();
+
+    // As a replacement for Mario, the base game refers to the sprite's palettes by index.
+    std::fs::create_dir(dir.child("src/player_sprite").path()).unwrap();
+    std::fs::rename(sprite_dir.path(), dir.child("src/player_sprite/mario_1").path()).unwrap();
+
+    starpkg()
+        .arg("build")
+        .arg("--no-compile")
+        .current_dir(dir.path())
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("shared identical palettes").not());
+
+    dir.child(".build/sprite/player/src/01/SpriteSheet.xml")
+        .assert(predicate::str::contains(r#"<Palette id="1" name="copy" src="copy_palette.png"/>"#));
+}
+
+#[test]
+fn unchanged_files_not_rewritten() {
+    let dir = tempdir();
+
+    dir.child("starpkg.toml").write_str(indoc!(r#"
+        name = "test_pkg"
+        version = "0.1.0"
+    "#)).unwrap();
+
+    dir.child("src/sprite/fizzlit").copy_from(FIZZLIT, &["*"]).unwrap();
+
+    let build = || starpkg()
+        .arg("build")
+        .arg("--no-compile")
+        .current_dir(dir.path())
+        .assert()
+        .success();
+
+    build();
+
+    // Files are recorded by SHA-256, which is stable across Rust releases.
+    dir.child(".build/cache.json").assert(predicate::str::contains(
+        r#""sprite/npc/src/01/fizzlit_purple1.png":"77b3869fd93c98b121f0b68874d22c649c1fb51d3d4c83e85a1153f50c68511a""#,
+    ));
+
+    // Tamper with the build output; an unchanged source shouldn't overwrite it.
+    let out_dir = dir.child(".build/sprite/npc/src/01");
+    out_dir.child("fizzlit_purple1.png").write_str("unchanged").unwrap();
+    out_dir.child("fizzlit_purple2.png").write_str("changed").unwrap();
+    dir.child("src/sprite/fizzlit/fizzlit_purple2.png")
+        .write_binary(&std::fs::read(Path::new(FIZZLIT).join("fizzlit_purple3.png")).unwrap())
+        .unwrap();
+
+    build();
+
+    out_dir.child("fizzlit_purple1.png").assert("unchanged");
+    out_dir.child("fizzlit_purple2.png").assert(predicate::path::eq_file(Path::new(FIZZLIT).join("fizzlit_purple3.png")));
+}
+
+#[test]
+fn derives_palette_variants() {
+    let dir = package_with_sprite_toml(indoc!(r##"
+        [[palette]]
+        name = "purple"
+        src = "Palette_00.png"
+
+        [[palette]]
+        name = "gold"
+        base = "purple"
+        remap = { 1 = 3 }
+        hue_shift = 120
+        swap = { "#3c1555" = "#ffcc00" }
+
+        [[raster]]
+        src = "fizzlit_purple1.png"
+
+        [[animation]]
+        name = "idle"
+        frames = [
+            { raster = "fizzlit_purple1", duration = 30, palette = "gold" },
+        ]
+    "##));
+
+    starpkg()
+        .arg("build")
+        .arg("--no-compile")
+        .current_dir(dir.path())
+        .assert()
+        .success();
+
+    let sprite_dir = dir.child(".build/sprite/npc/src/01");
+    sprite_dir.child("SpriteSheet.xml")
+        .assert(predicate::str::contains(r#"<Palette id="1" name="gold" src="gold.png"/>"#));
+
+    // Base colours are 000000, 150621, 3c1555, 4d1b76, ...
+    let (_, palette) = decode_png(sprite_dir.child("gold.png").path());
+    assert_eq!(&palette.unwrap()[..12], &[
+        0x00, 0x00, 0x00, // Greys keep their hue.
+        0x76, 0x4D, 0x1B, // Remapped to 4d1b76, then hue shifted.
+        0xFF, 0xCC, 0x00, // Swapped.
+        0x76, 0x4D, 0x1B, // Hue shifted.
+    ]);
+}
+
+#[test]
+fn unmatched_color_swap() {
+    let dir = package_with_sprite_toml(indoc!(r##"
+        [[palette]]
+        name = "purple"
+        src = "Palette_00.png"
+
+        [[palette]]
+        name = "gold"
+        base = "purple"
+        swap = { "#3C1556" = "#ffcc00" }
+    "##));
+
+    starpkg()
+        .arg("build")
+        .arg("--no-compile")
+        .current_dir(dir.path())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "error[SP0418]: palette 'gold' swaps #3c1556, but its base palette 'purple' doesn't have that colour"
+        ))
+        .stderr(predicate::str::contains("sprite.toml:8:10"));
+}
+
+#[test]
+fn palette_rules_need_base() {
+    let dir = package_with_sprite_toml(indoc!(r#"
+        [[palette]]
+        name = "purple"
+        src = "Palette_00.png"
+        hue_shift = 90
+    "#));
+
+    starpkg()
+        .arg("build")
+        .arg("--no-compile")
+        .current_dir(dir.path())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("palette 'purple' has colour rules, so needs a `base` palette"));
+}
+
+fn package_with_player_sprites(dir: &assert_fs::fixture::ChildPath, name: &str, player_sprites: &[&str]) {
+    dir.child("starpkg.toml").write_str(&format!("name = \"{}\"\nversion = \"0.1.0\"\n", name)).unwrap();
+
+    for player_sprite in player_sprites {
+        dir.child("src/player_sprite").child(player_sprite)
+            .copy_from(FIZZLIT, &["*.png", "SpriteSheet.xml"])
+            .unwrap();
+    }
+}
+
+#[test]
+fn replaces_and_adds_player_sprites() {
+    let dir = tempdir();
+    package_with_player_sprites(&dir.child("."), "test_pkg", &["mario_1", "lantern"]);
+
+    dir.child("src/actor/blob/blob.toml").write_str(indoc!(r#"
+        name = "blob_name"
+        tattle = "blob_name"
+
+        [stats]
+        max_hp = 5
+
+        [status]
+    "#)).unwrap();
+
+    dir.child("src/actor/blob/blob.bscr").write_str(indoc!(r#"
+        #new:Script $Script_Init
+        SetPlayerAnimation {PlayerSprite:mario_1:idle}
+        SetPlayerAnimation {PlayerSprite:lantern:stop:yellow}
+        End
+
+        #new:SpriteTable $SpriteTable
+        00800000 01000000 001400FF 00000000 00000000 00000000 00000000 00F60000 00000000
+    "#)).unwrap();
+
+    dir.child("src/string/strings.str").write_str(indoc!(r#"
+        #string:01:(blob_name)
+        [END]
+    "#)).unwrap();
+
+    starpkg()
+        .arg("build")
+        .arg("--no-compile")
+        .current_dir(dir.path())
+        .assert()
+        .success();
+
+    let sprite_table = dir.child(".build/sprite/SpriteTable.xml");
+    sprite_table.assert(predicate::str::contains(r#"<Sprite id="1" src="01" name="Mario 1"/>"#));
+    sprite_table.assert(predicate::str::contains(r#"<Sprite id="D" src="0D" name="Peach 4"/>"#));
+    sprite_table.assert(predicate::str::contains(r#"<Sprite id="E" src="0E" name="test_pkg_lantern"/>"#));
+
+    dir.child(".build/sprite/player/src/01/SpriteSheet.xml").assert(predicate::path::exists());
+    dir.child(".build/sprite/player/src/02").assert(predicate::path::missing());
+    dir.child(".build/sprite/player/src/0E/SpriteSheet.xml").assert(predicate::path::exists());
+
+    dir.child(".build/battle/formation/import/actor/00_blob.bpat")
+        .assert(predicate::str::contains("SetPlayerAnimation 00010001"))
+        .assert(predicate::str::contains("SetPlayerAnimation 000E0100"));
+}
+
+#[test]
+fn conflicting_player_sprites() {
+    let dir = tempdir();
+
+    dir.child("starpkg.toml").write_str(indoc!(r#"
+        name = "test_pkg"
+        version = "0.1.0"
+
+        [dependencies]
+        a = { path = "a" }
+        b = { path = "b" }
+    "#)).unwrap();
+
+    package_with_player_sprites(&dir.child("a"), "a", &["mario_1"]);
+    package_with_player_sprites(&dir.child("b"), "b", &["mario_1", "peach_1"]);
+
+    starpkg()
+        .arg("build")
+        .arg("--no-compile")
+        .current_dir(dir.path())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "error[SP0107]: player sprite 'Mario 1' is replaced by more than one package: a, b"
+        ))
+        .stderr(predicate::str::contains("aborting due to 1 previous error"));
+}
+
+#[test]
+fn unknown_player_sprite() {
+    let dir = tempdir();
+    package_with_player_sprites(&dir.child("."), "test_pkg", &[]);
+
+    dir.child("src/actor/blob/blob.toml").write_str(indoc!(r#"
+        name = "blob_name"
+        tattle = "blob_name"
+
+        [stats]
+        max_hp = 5
+
+        [status]
+    "#)).unwrap();
+
+    dir.child("src/actor/blob/blob.bscr").write_str(indoc!(r#"
+        #new:Script $Script_Init
+        SetPlayerAnimation {PlayerSprite:mario_1:idle}
+        End
+
+        #new:SpriteTable $SpriteTable
+        00800000 01000000 001400FF 00000000 00000000 00000000 00000000 00F60000 00000000
+    "#)).unwrap();
+
+    dir.child("src/string/strings.str").write_str(indoc!(r#"
+        #string:01:(blob_name)
+        [END]
+    "#)).unwrap();
+
+    starpkg()
+        .arg("build")
+        .arg("--no-compile")
+        .current_dir(dir.path())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("error[SP0207]: unknown player sprite"))
+        .stderr(predicate::str::contains("no such player sprite"));
+}
+
+fn package_with_sprite_patch(dir: &assert_fs::TempDir) {
+    dir.child("starpkg.toml").write_str(indoc!(r#"
+        name = "test_pkg"
+        version = "0.1.0"
+    "#)).unwrap();
+
+    // Stands in for Star Rod's dump of the base game.
+    dir.child("dump/sprite/SpriteTable.xml").write_str(indoc!(r#"
+        <SpriteTable>
+            <NpcSprites>
+                <Sprite id="1" src="01" name="Kooper"/>
+                <Sprite id="2" src="02" name="Goomba"/>
+            </NpcSprites>
+        </SpriteTable>
+    "#)).unwrap();
+    dir.child("dump/sprite/npc/src/02").copy_from(FIZZLIT, &["*.png", "SpriteSheet.xml"]).unwrap();
+
+    let patch_dir = dir.child("src/sprite_patch/goomba");
+    patch_dir.copy_from(FIZZLIT, &["Palette_01.png", "fizzlit_yellow1.png"]).unwrap();
+    patch_dir.child("sprite.toml").write_str(indoc!(r#"
+        [[palette]]
+        name = "amber"
+        src = "Palette_01.png"
+
+        [[palette]]
+        name = "gold"
+        base = "amber"
+        hue_shift = 30
+
+        [[raster]]
+        src = "fizzlit_yellow1.png"
+        palette = "gold"
+
+        [[animation]]
+        name = "wave"
+        frames = [{ raster = "fizzlit_yellow1", duration = 4 }]
+    "#)).unwrap();
+}
+
+#[test]
+fn patches_vanilla_sprite() {
+    let dir = tempdir();
+    package_with_sprite_patch(&dir);
+
+    dir.child("src/actor/blob/blob.toml").write_str(indoc!(r#"
+        name = "blob_name"
+        tattle = "blob_name"
+
+        [stats]
+        max_hp = 5
+
+        [status]
+    "#)).unwrap();
+
+    dir.child("src/actor/blob/blob.bscr").write_str(indoc!(r#"
+        #new:Script $Script_Init
+        SetAnimation {Sprite:pm64/goomba:wave}
+        SetAnimation {Sprite:pm64/goomba:idle:gold}
+        End
+
+        #new:SpriteTable $SpriteTable
+        00800000 01000000 001400FF 00000000 00000000 00000000 00000000 00F60000 00000000
+    "#)).unwrap();
+
+    dir.child("src/string/strings.str").write_str(indoc!(r#"
+        #string:01:(blob_name)
+        [END]
+    "#)).unwrap();
+
+    starpkg()
+        .arg("build")
+        .arg("--no-compile")
+        .env("STARPKG_DUMP_DIR", dir.child("dump").path())
+        .current_dir(dir.path())
+        .assert()
+        .success();
+
+    // The patched sprite replaces the base game's, so its Goombas show it too.
+    dir.child(".build/sprite/SpriteTable.xml")
+        .assert(predicate::str::contains(r#"<Sprite id="1" src="01" name="Kooper"/>"#))
+        .assert(predicate::str::contains(r#"<Sprite id="2" src="02" name="Goomba"/>"#));
+    dir.child(".build/sprite/npc/src/01").assert(predicate::path::missing());
+
+    // The base game has 2 palettes, 23 rasters and 8 animations, which keep their indices even
+    // where identical; the patch's come after them. Its first palette is the base game's yellow,
+    // so only the variant is kept.
+    let sprite_dir = dir.child(".build/sprite/npc/src/02");
+    sprite_dir.child("SpriteSheet.xml")
+        .assert(predicate::str::contains(r#"<Palette id="2" name="gold" src="test_pkg_gold.png"/>"#))
+        .assert(predicate::str::contains(r#"name="amber""#).not())
+        .assert(predicate::str::contains(r#"<Raster id="17" palette="2" src="test_pkg_fizzlit_yellow1.png"/>"#))
+        .assert(predicate::str::contains(r#"<Command val="1017"/>"#));
+    sprite_dir.child("fizzlit_purple1.png").assert(predicate::path::exists());
+    sprite_dir.child("test_pkg_fizzlit_yellow1.png").assert(predicate::path::exists());
+
+    dir.child(".build/battle/formation/import/actor/00_blob.bpat")
+        .assert(predicate::str::contains("SetAnimation 00020008"))
+        .assert(predicate::str::contains("SetAnimation 00020201"));
+}
+
+#[test]
+fn patch_name_clash() {
+    let dir = tempdir();
+    package_with_sprite_patch(&dir);
+
+    let sprite_toml = dir.child("src/sprite_patch/goomba/sprite.toml");
+    let contents = std::fs::read_to_string(sprite_toml.path()).unwrap();
+    sprite_toml.write_str(&contents.replace("wave", "idle")).unwrap();
+
+    starpkg()
+        .arg("build")
+        .arg("--no-compile")
+        .env("STARPKG_DUMP_DIR", dir.child("dump").path())
+        .current_dir(dir.path())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "error[SP0421]: unable to load sprite ./src/sprite_patch/goomba: package 'test_pkg' adds animation 'idle', but the sprite already has one by that name"
+        ));
+}
+
+#[test]
+fn unknown_vanilla_sprite() {
+    let dir = tempdir();
+    package_with_sprite_patch(&dir);
+
+    std::fs::rename(
+        dir.child("src/sprite_patch/goomba").path(),
+        dir.child("src/sprite_patch/koopa").path(),
+    ).unwrap();
+
+    starpkg()
+        .arg("build")
+        .arg("--no-compile")
+        .env("STARPKG_DUMP_DIR", dir.child("dump").path())
+        .current_dir(dir.path())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("error[SP0420]"))
+        .stderr(predicate::str::contains("the base game has no sprite named 'koopa'"));
+}
+
+/// A package that adds an animation to the base game's `mario_1`, with a stand-in for Star Rod's
+/// dump of it. The animation's palette is shifted by `hue_shift`, so it isn't merged with others.
+fn package_with_player_sprite_patch(
+    dir: &assert_fs::fixture::ChildPath,
+    name: &str,
+    animation: &str,
+    hue_shift: u32,
+) {
+    dir.child("starpkg.toml").write_str(&format!("name = \"{}\"\nversion = \"0.1.0\"\n", name)).unwrap();
+
+    let patch_dir = dir.child("src/player_sprite_patch/mario_1");
+    patch_dir.copy_from(FIZZLIT, &["Palette_01.png", "fizzlit_yellow1.png"]).unwrap();
+    patch_dir.child("sprite.toml").write_str(&indoc!(r#"
+        [[palette]]
+        name = "ANIMATION_base"
+        src = "Palette_01.png"
+
+        [[palette]]
+        name = "ANIMATION"
+        base = "ANIMATION_base"
+        hue_shift = HUE_SHIFT
+
+        [[raster]]
+        src = "fizzlit_yellow1.png"
+        palette = "ANIMATION"
+
+        [[animation]]
+        name = "ANIMATION"
+        frames = [{ raster = "fizzlit_yellow1", duration = 4 }]
+    "#).replace("ANIMATION", animation).replace("HUE_SHIFT", &hue_shift.to_string())).unwrap();
+}
+
+fn dump_player_sprites(dir: &assert_fs::TempDir) {
+    dir.child("dump/sprite/SpriteTable.xml").write_str(indoc!(r#"
+        <SpriteTable>
+            <PlayerSprites>
+                <Sprite id="1" src="01" name="Mario 1"/>
+            </PlayerSprites>
+        </SpriteTable>
+    "#)).unwrap();
+    dir.child("dump/sprite/player/src/01").copy_from(FIZZLIT, &["*.png", "SpriteSheet.xml"]).unwrap();
+}
+
+#[test]
+fn patches_vanilla_player_sprite() {
+    let dir = tempdir();
+    dump_player_sprites(&dir);
+
+    dir.child("starpkg.toml").write_str(indoc!(r#"
+        name = "test_pkg"
+        version = "0.1.0"
+
+        [dependencies]
+        a = { path = "a" }
+        b = { path = "b" }
+    "#)).unwrap();
+
+    package_with_player_sprite_patch(&dir.child("a"), "a", "wave", 30);
+    package_with_player_sprite_patch(&dir.child("b"), "b", "jump", 60);
+
+    dir.child("src/actor/blob/blob.toml").write_str(indoc!(r#"
+        name = "blob_name"
+        tattle = "blob_name"
+
+        [stats]
+        max_hp = 5
+
+        [status]
+    "#)).unwrap();
+
+    dir.child("src/actor/blob/blob.bscr").write_str(indoc!(r#"
+        #new:Script $Script_Init
+        SetPlayerAnimation {PlayerSprite:pm64/mario_1:idle}
+        SetPlayerAnimation {PlayerSprite:pm64/mario_1:wave}
+        SetPlayerAnimation {PlayerSprite:pm64/mario_1:jump}
+        End
+
+        #new:SpriteTable $SpriteTable
+        00800000 01000000 001400FF 00000000 00000000 00000000 00000000 00F60000 00000000
+    "#)).unwrap();
+
+    dir.child("src/string/strings.str").write_str(indoc!(r#"
+        #string:01:(blob_name)
+        [END]
+    "#)).unwrap();
+
+    starpkg()
+        .arg("build")
+        .arg("--no-compile")
+        .env("STARPKG_DUMP_DIR", dir.child("dump").path())
+        .current_dir(dir.path())
+        .assert()
+        .success();
+
+    dir.child(".build/sprite/SpriteTable.xml")
+        .assert(predicate::str::contains(r#"<Sprite id="1" src="01" name="Mario 1"/>"#));
+
+    // Both packages' animations come after the base game's 8, in package name order.
+    let sprite_dir = dir.child(".build/sprite/player/src/01");
+    sprite_dir.child("SpriteSheet.xml")
+        .assert(predicate::str::contains(r#"src="a_fizzlit_yellow1.png""#))
+        .assert(predicate::str::contains(r#"src="b_fizzlit_yellow1.png""#));
+    sprite_dir.child("fizzlit_purple1.png").assert(predicate::path::exists());
+
+    dir.child(".build/battle/formation/import/actor/00_blob.bpat")
+        .assert(predicate::str::contains("SetPlayerAnimation 00010001"))
+        .assert(predicate::str::contains("SetPlayerAnimation 00010008"))
+        .assert(predicate::str::contains("SetPlayerAnimation 00010009"));
+}
+
+#[test]
+fn patched_player_sprite_replaced() {
+    let dir = tempdir();
+    dump_player_sprites(&dir);
+    package_with_player_sprites(&dir.child("."), "test_pkg", &["mario_1"]);
+    package_with_player_sprite_patch(&dir.child("a"), "a", "wave", 30);
+
+    dir.child("starpkg.toml").write_str(indoc!(r#"
+        name = "test_pkg"
+        version = "0.1.0"
+
+        [dependencies]
+        a = { path = "a" }
+    "#)).unwrap();
+
+    starpkg()
+        .arg("build")
+        .arg("--no-compile")
+        .env("STARPKG_DUMP_DIR", dir.child("dump").path())
+        .current_dir(dir.path())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "error[SP0108]: player sprite 'mario_1' is replaced by package 'test_pkg', so package 'a' can't patch it"
+        ));
+}
+
+fn decode_png(path: &Path) -> (Vec<u8>, Option<Vec<u8>>) {
+    let decoder = png::Decoder::new(std::fs::File::open(path).unwrap());
+    let mut reader = decoder.read_info().unwrap();
+    let mut pixels = vec![0; reader.output_buffer_size()];
+    reader.next_frame(&mut pixels).unwrap();
+    (pixels, reader.info().palette.as_ref().map(|p| p.to_vec()))
+}
+
+fn encode_png(path: &Path, width: u32, height: u32, color: png::ColorType, data: &[u8]) {
+    let file = std::fs::File::create(path).unwrap();
+    let mut encoder = png::Encoder::new(file, width, height);
+    encoder.set_color(color);
+    encoder.set_depth(png::BitDepth::Eight);
+    if color == png::ColorType::Indexed {
+        encoder.set_palette(vec![0; 3 * 32]);
+    }
+    encoder.write_header().unwrap().write_image_data(data).unwrap();
+}
+
+fn package_with_rasters() -> assert_fs::TempDir {
+    let dir = tempdir();
+
+    dir.child("starpkg.toml").write_str(indoc!(r#"
+        name = "test_pkg"
+        version = "0.1.0"
+    "#)).unwrap();
+
+    dir.child("src/sprite/blob/SpriteSheet.xml").write_str(indoc!(r#"
+        <SpriteSheet>
+            <PaletteList>
+                <Palette id="0" name="green" src="Palette_00.png"/>
+            </PaletteList>
+            <RasterList>
+                <Raster id="0" palette="0" src="truecolor.png"/>
+                <Raster id="1" palette="0" src="big.png"/>
+                <Raster id="2" palette="5" src="fine.png"/>
+            </RasterList>
+            <AnimationList>
+                <Animation name="idle">
+                    <Component xyz="0,0,0">
+                        <Command val="1000"/>
+                        <Command val="2"/>
+                        <Command val="1001"/>
+                        <Command val="2"/>
+                        <Command val="2000"/>
+                    </Component>
+                </Animation>
+                <Animation name="walk">
+                    <Component xyz="0,0,0">
+                        <Command val="3000"/>
+                        <Command val="1001"/>
+                        <Command val="0"/>
+                        <Command val="0"/>
+                        <Command val="1001"/>
+                        <Command val="2"/>
+                        <Command val="2000"/>
+                    </Component>
+                </Animation>
+            </AnimationList>
+        </SpriteSheet>
+    "#)).unwrap();
+
+    dir
+}
+
+#[test]
+fn validates_rasters() {
+    let dir = package_with_rasters();
+    let sprite_dir = dir.child("src/sprite/blob");
+
+    encode_png(sprite_dir.child("Palette_00.png").path(), 2, 2, png::ColorType::Indexed, &[0; 4]);
+    encode_png(sprite_dir.child("truecolor.png").path(), 2, 2, png::ColorType::Rgba, &[0; 16]);
+    encode_png(sprite_dir.child("big.png").path(), 3, 300, png::ColorType::Indexed, &[20; 900]);
+    encode_png(sprite_dir.child("fine.png").path(), 2, 2, png::ColorType::Indexed, &[1; 4]);
+
+    starpkg()
+        .arg("build")
+        .arg("--no-compile")
+        .current_dir(dir.path())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("error[SP0411]: truecolor.png: not a colour-indexed PNG"))
+        .stderr(predicate::str::contains("SpriteSheet.xml:6:41"))
+        .stderr(predicate::str::contains("used by animation 'idle'"))
+        .stderr(predicate::str::contains("error[SP0414]: big.png is 3x300"))
+        .stderr(predicate::str::contains("error[SP0413]: big.png uses colour index 20"))
+        .stderr(predicate::str::contains("used by animations: idle, walk"))
+        .stderr(predicate::str::contains("error[SP0415]: raster fine.png uses palette 5"))
+        .stderr(predicate::str::contains("aborting due to 4 previous errors"));
+}
+
+#[test]
+fn validates_palette_size() {
+    let dir = package_with_rasters();
+    let sprite_dir = dir.child("src/sprite/blob");
+
+    let file = std::fs::File::create(sprite_dir.child("Palette_00.png").path()).unwrap();
+    let mut encoder = png::Encoder::new(file, 2, 2);
+    encoder.set_color(png::ColorType::Indexed);
+    encoder.set_palette(vec![0; 3 * 4]);
+    encoder.write_header().unwrap().write_image_data(&[0; 4]).unwrap();
+
+    encode_png(sprite_dir.child("truecolor.png").path(), 2, 2, png::ColorType::Indexed, &[0; 4]);
+    encode_png(sprite_dir.child("big.png").path(), 2, 2, png::ColorType::Indexed, &[9; 4]);
+
+    starpkg()
+        .arg("build")
+        .arg("--no-compile")
+        .current_dir(dir.path())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "error[SP0416]: big.png uses colour index 9, but its palette 'green' only has 4 colours"
+        ))
+        .stderr(predicate::str::contains("aborting due to 1 previous error"));
+}
@@ -0,0 +1,817 @@
+mod common;
+
+use assert_fs::prelude::*;
+use predicates::prelude::*;
+use indoc::indoc;
+
+use common::{tempdir, starpkg, package_with_strings, package_with_actor};
+
+#[test]
+fn allocates_sections() {
+    let dir = tempdir();
+
+    dir.child("starpkg.toml").write_str(indoc!(r#"
+        name = "test_pkg"
+        version = "0.1.0"
+    "#)).unwrap();
+
+    dir.child("src/actor/blob/blob.toml").write_str(indoc!(r#"
+        name = "blob_name"
+        tattle = "blob_tattle"
+    "#)).unwrap();
+
+    dir.child("src/actor/blob/blob.bscr").write_str(indoc!(r#"
+        #new:Data $Data
+        {String:blob_name} {String:blob_tattle} {String:greeting} {String:farewell}
+
+        #new:Actor $Actor
+        [Index]       {Actor:blob}
+        [MaxHP]       5`b
+        [StatusTable] $StatusTable
+        [SpriteCount] 1`s
+        [SpriteTable] $SpriteTable
+        [Script]      $Script_Init
+
+        #new:StatusTable $StatusTable
+        .Status:End
+
+        #new:SpriteTable $SpriteTable
+        00800000 01000000 001400FF 00000000 00000000 00000000 00000000 00F60000 00000000
+
+        #new:Script $Script_Init
+        Return
+        End
+    "#)).unwrap();
+
+    dir.child("src/string/strings.str").write_str(indoc!(r#"
+        #string:(blob_name)
+        Blob[END]
+
+        #string:auto:(blob_tattle)
+        This is a Blob.[END]
+
+        #string:(greeting)
+        Hello![END]
+
+        #string:30:(farewell)
+        Bye![END]
+    "#)).unwrap();
+
+    starpkg()
+        .arg("build")
+        .arg("--no-compile")
+        .current_dir(dir.path())
+        .assert()
+        .success();
+
+    // Names, tattles and dialogue each go in a new section, avoiding those already used.
+    dir.child(".build/strings/00300000.str").assert(predicate::str::contains("Bye!"));
+    dir.child(".build/strings/00310000.str").assert(predicate::str::contains("#string:31:000\nBlob[END]"));
+    dir.child(".build/strings/00320000.str").assert(predicate::str::contains("This is a Blob."));
+    dir.child(".build/strings/00330000.str").assert(predicate::str::contains("Hello!"));
+
+    dir.child(".build/battle/formation/import/actor/00_blob.bpat")
+        .assert(predicate::str::contains("00310000 00320000 00330000 00300000"));
+}
+
+#[test]
+fn packages_get_own_dialogue_sections() {
+    let dir = tempdir();
+
+    dir.child("starpkg.toml").write_str(indoc!(r#"
+        name = "test_pkg"
+        version = "0.1.0"
+
+        [dependencies]
+        dep = { path = "dep" }
+    "#)).unwrap();
+
+    dir.child("src/string/strings.str").write_str(indoc!(r#"
+        #string:(greeting)
+        Hello![END]
+    "#)).unwrap();
+
+    dir.child("dep/starpkg.toml").write_str(indoc!(r#"
+        name = "dep"
+        version = "0.1.0"
+    "#)).unwrap();
+
+    dir.child("dep/src/string/strings.str").write_str(indoc!(r#"
+        #string:(greeting)
+        Hi![END]
+
+        #string:(farewell)
+        Bye![END]
+    "#)).unwrap();
+
+    starpkg()
+        .arg("build")
+        .arg("--no-compile")
+        .current_dir(dir.path())
+        .assert()
+        .success();
+
+    dir.child(".build/strings/00300000.str").assert(predicate::str::contains("Bye!"));
+    dir.child(".build/strings/00300001.str").assert(predicate::str::contains("Hi!"));
+    dir.child(".build/strings/00310000.str").assert(predicate::str::contains("Hello!"));
+}
+
+#[test]
+fn keeps_out_of_base_game_sections() {
+    let dir = tempdir();
+
+    dir.child("starpkg.toml").write_str(indoc!(r#"
+        name = "test_pkg"
+        version = "0.1.0"
+
+        [dependencies]
+        dep = { path = "dep" }
+    "#)).unwrap();
+
+    dir.child("dep/starpkg.toml").write_str(indoc!(r#"
+        name = "dep"
+        version = "0.1.0"
+    "#)).unwrap();
+
+    for (pkg_dir, actor) in &[(".", "blob"), ("dep", "slime")] {
+        dir.child(format!("{}/src/actor/{}/{}.toml", pkg_dir, actor, actor)).write_str(&format!(
+            "name = \"{0}_name\"\ntattle = \"{0}_tattle\"\n\n[stats]\nmax_hp = 5\n\n[status]\n",
+            actor,
+        )).unwrap();
+
+        dir.child(format!("{}/src/actor/{}/{}.bscr", pkg_dir, actor, actor)).write_str(indoc!(r#"
+            #new:SpriteTable $SpriteTable
+            00800000 01000000 001400FF 00000000 00000000 00000000 00000000 00F60000 00000000
+
+            #new:Script $Script_Init
+            Return
+            End
+        "#)).unwrap();
+
+        dir.child(format!("{}/src/string/strings.str", pkg_dir)).write_str(&format!(
+            "#string:({0}_name)\n{0}[END]\n\n#string:({0}_tattle)\nThis is a {0}.[END]\n",
+            actor,
+        )).unwrap();
+    }
+
+    starpkg()
+        .arg("build")
+        .arg("--no-compile")
+        .current_dir(dir.path())
+        .assert()
+        .success();
+
+    // The base game's own strings fill sections 00 to 2F, so using any of those would replace them.
+    for entry in std::fs::read_dir(dir.child(".build/strings").path()).unwrap() {
+        let file_name = entry.unwrap().file_name().into_string().unwrap();
+        let section = u8::from_str_radix(&file_name[2..4], 16).unwrap();
+
+        assert!(section >= 0x30, "string {} is in a base game section", file_name);
+    }
+
+    // Actor names and tattles from every package share a section each.
+    dir.child(".build/strings/00300000.str").assert(predicate::str::contains("slime[END]"));
+    dir.child(".build/strings/00310000.str").assert(predicate::str::contains("This is a slime."));
+    dir.child(".build/strings/00300001.str").assert(predicate::str::contains("blob[END]"));
+    dir.child(".build/strings/00310001.str").assert(predicate::str::contains("This is a blob."));
+}
+
+#[test]
+fn unknown_markup_tag() {
+    let dir = package_with_strings(indoc!(r#"
+        #string:(greeting)
+        Hello there![WIAT]
+        Press [A] to [SHAKE]jump[/SHAKE]![SIZE:10,10]
+        [OPTION:0]Yes[OPTION:1]No[ENDCHOICE:2][ITEMICON:Mushroom][END]
+    "#));
+
+    // The game may know tags starpkg doesn't, so the string is still built.
+    starpkg()
+        .arg("build")
+        .arg("--no-compile")
+        .current_dir(dir.path())
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("warning[SP0302]: unknown markup tag `[WIAT]`"))
+        .stderr(predicate::str::contains("strings.str:2:13"))
+        .stderr(predicate::str::contains("did you mean `[WAIT]`?"))
+        .stderr(predicate::str::contains("SP0302").count(1));
+}
+
+#[test]
+fn missing_end() {
+    let dir = package_with_strings(indoc!(r#"
+        #string:(greeting)
+        Hello!
+
+        #string:(farewell)
+        Bye![END]
+    "#));
+
+    starpkg()
+        .arg("build")
+        .arg("--no-compile")
+        .current_dir(dir.path())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("error[SP0305]: string doesn't end with `[END]`"))
+        .stderr(predicate::str::contains("strings.str:2:7"))
+        .stderr(predicate::str::contains("SP0305").count(1));
+}
+
+#[test]
+fn bad_markup_arguments() {
+    let dir = package_with_strings(indoc!(r#"
+        #string:(greeting)
+        [STYLE:MIDDLE]Hello![WAIT[END]
+    "#));
+
+    starpkg()
+        .arg("build")
+        .arg("--no-compile")
+        .current_dir(dir.path())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("error[SP0303]: bad argument to `[STYLE:MIDDLE]`"))
+        .stderr(predicate::str::contains("expected one of: RIGHT, LEFT"))
+        .stderr(predicate::str::contains("error[SP0304]: `[WAIT` is missing its closing `]`"))
+        .stderr(predicate::str::contains("strings.str:2:21"));
+}
+
+#[test]
+fn unmatched_markup() {
+    let dir = package_with_strings(indoc!(r#"
+        #string:(greeting)
+        Hello!WAIT][SAVECOLOR][COLOR:0A]
+        There![RESTORECOLOR][RESTORECOLOR][SAVECOLOR][END]
+    "#));
+
+    starpkg()
+        .arg("build")
+        .arg("--no-compile")
+        .current_dir(dir.path())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("error[SP0313]: `]` without an opening `[`"))
+        .stderr(predicate::str::contains("strings.str:2:11"))
+        .stderr(predicate::str::contains("error[SP0314]: `[RESTORECOLOR]` without an earlier `[SAVECOLOR]`"))
+        .stderr(predicate::str::contains("strings.str:3:21"))
+        .stderr(predicate::str::contains("error[SP0307]: `[SAVECOLOR]` is never restored"))
+        .stderr(predicate::str::contains("strings.str:3:35"))
+        .stderr(predicate::str::contains("aborting due to 3 previous errors"));
+}
+
+#[test]
+fn warns_about_overflowing_text() {
+    let dir = package_with_strings(indoc!(r#"
+        #string:(greeting)
+        Hello there! This line is far too long to fit in a speech bubble.
+        [NEXT]One
+        Two
+        Three
+        Four[END]
+    "#));
+
+    starpkg()
+        .arg("build")
+        .arg("--no-compile")
+        .current_dir(dir.path())
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("warning[SP0311]: line of string 'greeting' is 430px wide, but a speech bubble fits 232px"))
+        .stderr(predicate::str::contains("strings.str:2:1"))
+        .stderr(predicate::str::contains("^ 198px too wide"))
+        .stderr(predicate::str::contains("warning[SP0312]: page of string 'greeting' has 4 lines, but a speech bubble fits 3"))
+        .stderr(predicate::str::contains("strings.str:6:1"))
+        .stderr(predicate::str::contains("^^^^^^^^^ doesn't fit"));
+}
+
+#[test]
+fn measures_font_widths() {
+    let dir = package_with_strings(indoc!(r#"
+        #string:(greeting)
+        The quick brown fox jumps over the lazy dog, twice.
+        [FONT:TITLE]The quick brown fox jumps over the lazy dog, twice.[END]
+    "#));
+
+    starpkg()
+        .arg("build")
+        .arg("--no-compile")
+        .current_dir(dir.path())
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("line of string 'greeting' is 364px wide"))
+        .stderr(predicate::str::contains("line of string 'greeting' is 487px wide"));
+}
+
+#[test]
+fn wraps_text() {
+    let dir = package_with_strings(indoc!(r#"
+        #string:(greeting)
+        [STYLE:SIGN]Hello there! This line is far too long to fit on a sign, so it wraps.[END]
+    "#));
+
+    dir.child("starpkg.toml").write_str(indoc!(r#"
+        name = "test_pkg"
+        version = "0.1.0"
+
+        [strings]
+        wrap = true
+    "#)).unwrap();
+
+    starpkg()
+        .arg("build")
+        .arg("--no-compile")
+        .current_dir(dir.path())
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("px wide").not());
+
+    dir.child(".build/strings/00300000.str").assert(
+        "#string:30:000\n[STYLE:SIGN]Hello there! This line is far\ntoo long to fit on a sign, so\nit wraps.[END]",
+    );
+}
+
+fn package_with_translations() -> assert_fs::TempDir {
+    let dir = package_with_strings(indoc!(r#"
+        #string:(greeting)
+        Hello![END]
+
+        #string:(farewell)
+        Bye![END]
+    "#));
+
+    dir.child("src/string/fr/strings.str").write_str(indoc!(r#"
+        #string:(greeting)
+        Bonjour ![END]
+
+        #string:(farewel)
+        Au revoir ![END]
+    "#)).unwrap();
+
+    dir
+}
+
+#[test]
+fn translates_strings() {
+    let dir = package_with_translations();
+
+    starpkg()
+        .arg("build")
+        .arg("--no-compile")
+        .arg("--locale")
+        .arg("fr")
+        .current_dir(dir.path())
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("locale 'fr' is missing 1 of 2 strings: {String:test_pkg/farewell}"))
+        .stderr(predicate::str::contains("locale 'fr' translates {String:test_pkg/farewel}, which isn't a string in the base language"));
+
+    // Untranslated strings fall back to the base language.
+    dir.child(".build/strings/00300000.str").assert(predicate::str::contains("Bye!"));
+    dir.child(".build/strings/00300001.str").assert(predicate::str::contains("Bonjour !"));
+}
+
+#[test]
+fn default_locale() {
+    let dir = package_with_translations();
+
+    dir.child("starpkg.toml").write_str(indoc!(r#"
+        name = "test_pkg"
+        version = "0.1.0"
+
+        [strings]
+        locale = "fr"
+    "#)).unwrap();
+
+    starpkg()
+        .arg("build")
+        .arg("--no-compile")
+        .current_dir(dir.path())
+        .assert()
+        .success();
+
+    dir.child(".build/strings/00300001.str").assert(predicate::str::contains("Bonjour !"));
+
+    starpkg()
+        .arg("build")
+        .arg("--no-compile")
+        .arg("--locale")
+        .arg("de")
+        .current_dir(dir.path())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("no strings are translated to locale 'de'"));
+}
+
+#[test]
+fn exports_strings() {
+    let dir = package_with_translations();
+
+    starpkg()
+        .arg("strings")
+        .arg("export")
+        .arg("--locale")
+        .arg("fr")
+        .current_dir(dir.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(indoc!(r#"
+            #: src/string/strings.str:1
+            #. section: auto
+            msgctxt "greeting"
+            msgid "Hello![END]"
+            msgstr "Bonjour ![END]"
+        "#)));
+
+    starpkg()
+        .arg("strings")
+        .arg("export")
+        .arg("--format")
+        .arg("csv")
+        .current_dir(dir.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with("name,section,context,source,translation\r\n"))
+        .stdout(predicate::str::contains("farewell,auto,src/string/strings.str:4,Bye![END],\r\n"));
+}
+
+#[test]
+fn imports_strings() {
+    let dir = package_with_translations();
+
+    dir.child("fr.po").write_str(indoc!(r#"
+        msgid ""
+        msgstr ""
+        "Language: fr\n"
+
+        #: src/string/strings.str:1
+        msgctxt "greeting"
+        msgid "Hello![END]"
+        msgstr ""
+        "Salut !\n"
+        "Ça va ?[END]"
+
+        msgctxt "farewell"
+        msgid "Bye![END]"
+        msgstr "Au revoir ![END]"
+    "#)).unwrap();
+
+    starpkg()
+        .arg("strings")
+        .arg("import")
+        .arg("fr.po")
+        .arg("--locale")
+        .arg("fr")
+        .current_dir(dir.path())
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("imported 2 translations into locale 'fr'"));
+
+    // Existing translations are replaced in place, and new ones added.
+    dir.child("src/string/fr/strings.str").assert(indoc!(r#"
+        #string:(greeting)
+        Salut !
+        Ça va ?[END]
+
+        #string:(farewel)
+        Au revoir ![END]
+
+        #string:(farewell)
+        Au revoir ![END]
+    "#));
+}
+
+#[test]
+fn rejects_bad_translations() {
+    let dir = package_with_translations();
+
+    dir.child("fr.csv").write_str(indoc!(r#"
+        name,translation
+        greeting,Salut !
+        farewell,Au revoir ![END]
+        hello,Allô ![END]
+    "#)).unwrap();
+
+    starpkg()
+        .arg("strings")
+        .arg("import")
+        .arg("fr.csv")
+        .arg("--locale")
+        .arg("fr")
+        .current_dir(dir.path())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("error[SP0305]: translation of 'greeting' has bad markup: string doesn't end with `[END]`"))
+        .stderr(predicate::str::contains("fr.csv:2:1"))
+        .stderr(predicate::str::contains("error[SP0309]: no string named 'hello' to translate"))
+        .stderr(predicate::str::contains("imported").not());
+
+    // Nothing is written, not even the good translation.
+    dir.child("src/string/fr/strings.str").assert(indoc!(r#"
+        #string:(greeting)
+        Bonjour ![END]
+
+        #string:(farewel)
+        Au revoir ![END]
+    "#));
+}
+
+#[test]
+fn rejects_translations_changing_tags() {
+    let dir = package_with_strings(indoc!(r#"
+        #string:(greeting)
+        [STYLE:RIGHT]Hello![WAIT][END]
+
+        #string:(farewell)
+        Bye![END]
+
+        #string:(question)
+        Why?[END]
+    "#));
+
+    // Line and page breaks may differ, but not other tags.
+    dir.child("fr.csv").write_str(indoc!(r#"
+        name,translation
+        greeting,"Salut !
+        [NEXT][END]"
+        farewell,"Au revoir ![BR]
+        #1 ![END]"
+        question,[COLOR:0A]Pourquoi ?[END]
+    "#)).unwrap();
+
+    starpkg()
+        .arg("strings")
+        .arg("import")
+        .arg("fr.csv")
+        .arg("--locale")
+        .arg("fr")
+        .current_dir(dir.path())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("error[SP0315]: translation of 'greeting' doesn't keep the tags of the source text"))
+        .stderr(predicate::str::contains("fr.csv:2:1"))
+        .stderr(predicate::str::contains("missing [STYLE:RIGHT]"))
+        .stderr(predicate::str::contains("error[SP0316]: translation of 'farewell' has a line starting with `#`"))
+        .stderr(predicate::str::contains("error[SP0315]: translation of 'question'"))
+        .stderr(predicate::str::contains("adds [COLOR:0A]"))
+        .stderr(predicate::str::contains("aborting due to 3 previous errors"));
+
+    dir.child("src/string/fr").assert(predicate::path::missing());
+}
+
+#[test]
+fn skips_fuzzy_translations() {
+    let dir = package_with_translations();
+
+    dir.child("fr.po").write_str(indoc!(r#"
+        #, fuzzy
+        msgid ""
+        msgstr ""
+        "Language: fr\n"
+
+        #: src/string/strings.str:1
+        #, fuzzy, no-wrap
+        msgctxt "greeting"
+        msgid "Hello![END]"
+        msgstr "Salut ![END]"
+
+        #, no-wrap
+        msgctxt "farewell"
+        msgid "Bye![END]"
+        msgstr "Au revoir ![END]"
+    "#)).unwrap();
+
+    starpkg()
+        .arg("strings")
+        .arg("import")
+        .arg("fr.po")
+        .arg("--locale")
+        .arg("fr")
+        .current_dir(dir.path())
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("imported 1 translations into locale 'fr'"));
+
+    dir.child("src/string/fr/strings.str")
+        .assert(predicate::str::contains("#string:(greeting)\nBonjour ![END]"))
+        .assert(predicate::str::contains("#string:(farewell)\nAu revoir ![END]"));
+}
+
+#[test]
+fn rejects_bad_locale() {
+    let dir = package_with_translations();
+
+    dir.child("fr.csv").write_str(indoc!(r#"
+        name,translation
+        farewell,Au revoir ![END]
+    "#)).unwrap();
+
+    starpkg()
+        .arg("strings")
+        .arg("import")
+        .arg("fr.csv")
+        .arg("--locale")
+        .arg("../fr")
+        .current_dir(dir.path())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("error[SP0030]: invalid locale: '../fr' is not a locale tag such as 'fr' or 'pt-BR'"));
+
+    dir.child("src/fr").assert(predicate::path::missing());
+}
+
+#[test]
+fn loads_toml_strings() {
+    let dir = tempdir();
+
+    dir.child("starpkg.toml").write_str(indoc!(r#"
+        name = "test_pkg"
+        version = "0.1.0"
+    "#)).unwrap();
+
+    dir.child("src/actor/blob/blob.toml").write_str(indoc!(r#"
+        name = "blob_name"
+        tattle = "blob_tattle"
+    "#)).unwrap();
+
+    dir.child("src/actor/blob/blob.bscr").write_str(indoc!(r#"
+        #new:Data $Data
+        {String:blob_name} {String:blob_tattle} {String:greeting}
+
+        #new:Actor $Actor
+        [Index]       {Actor:blob}
+        [MaxHP]       5`b
+        [StatusTable] $StatusTable
+        [SpriteCount] 1`s
+        [SpriteTable] $SpriteTable
+        [Script]      $Script_Init
+
+        #new:StatusTable $StatusTable
+        .Status:End
+
+        #new:SpriteTable $SpriteTable
+        00800000 01000000 001400FF 00000000 00000000 00000000 00000000 00F60000 00000000
+
+        #new:Script $Script_Init
+        Return
+        End
+    "#)).unwrap();
+
+    dir.child("src/string/blob.toml").write_str(indoc!(r#"
+        blob_name = "Blob[END]"
+        greeting = """
+        Hello!
+        [WAIT][END]"""
+
+        [blob_tattle]
+        section = "1C"
+        text = "This is a Blob.[END]"
+    "#)).unwrap();
+
+    starpkg()
+        .arg("build")
+        .arg("--no-compile")
+        .current_dir(dir.path())
+        .assert()
+        .success();
+
+    dir.child(".build/strings/00300000.str").assert("#string:30:000\nBlob[END]");
+    dir.child(".build/strings/001C0000.str").assert("#string:1C:000\nThis is a Blob.[END]");
+    dir.child(".build/strings/00310000.str").assert("#string:31:000\nHello!\n[WAIT][END]");
+
+    dir.child(".build/battle/formation/import/actor/00_blob.bpat")
+        .assert(predicate::str::contains("00300000 001C0000 00310000"));
+}
+
+#[test]
+fn bad_toml_strings() {
+    let dir = package_with_strings("");
+
+    dir.child("src/string/strings.toml").write_str(indoc!(r#"
+        greeting = """
+        Hello![WIAT]
+        [END]"""
+        farewell = 5
+
+        [question]
+        section = "XY"
+        text = "Why?[END]"
+    "#)).unwrap();
+
+    starpkg()
+        .arg("build")
+        .arg("--no-compile")
+        .current_dir(dir.path())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("warning[SP0302]: unknown markup tag `[WIAT]`"))
+        .stderr(predicate::str::contains("strings.toml:2:7"))
+        .stderr(predicate::str::contains("error[SP0310]: malformed string 'farewell': expected text, or a table with `text`"))
+        .stderr(predicate::str::contains("strings.toml:4:1"))
+        .stderr(predicate::str::contains("error[SP0310]: malformed string 'question': `section` must be a hex byte"));
+}
+
+#[test]
+fn imports_into_toml_strings() {
+    let dir = package_with_strings("");
+
+    dir.child("src/string/strings.toml").write_str(indoc!(r#"
+        greeting = "Hello![END]"
+        farewell = "Bye![END]"
+    "#)).unwrap();
+
+    dir.child("src/string/fr/strings.toml").write_str(indoc!(r#"
+        greeting = """
+        Bonjour ![END]"""
+        farewell = "Au revoir ![END]"
+    "#)).unwrap();
+
+    dir.child("fr.csv").write_str("name,translation\ngreeting,Salut ![END]\n").unwrap();
+
+    starpkg()
+        .arg("strings")
+        .arg("import")
+        .arg("fr.csv")
+        .arg("--locale")
+        .arg("fr")
+        .current_dir(dir.path())
+        .assert()
+        .success();
+
+    dir.child("src/string/fr/strings.toml").assert(indoc!(r#"
+        greeting = "Salut ![END]"
+        farewell = "Au revoir ![END]"
+    "#));
+}
+
+/// An actor `blob` whose struct sets a few fields for strings to interpolate.
+const BLOB: &str = indoc!(r#"
+    #new:Actor $Actor
+    [Index]       {Actor:blob}
+    [MaxHP]       12`b
+    [Flags]       00000010
+    [StatusTable] $StatusTable
+    [SpriteCount] 1`s
+    [SpriteTable] $SpriteTable
+    [Script]      $Script_Init
+
+    #new:StatusTable $StatusTable
+    .Status:End
+
+    #new:SpriteTable $SpriteTable
+    00800000 01000000 001400FF 00000000 00000000 00000000 00000000 00F60000 00000000
+
+    #new:Script $Script_Init
+    Return
+    End
+"#);
+
+#[test]
+fn interpolates_strings() {
+    let dir = package_with_actor("", BLOB);
+    dir.child("src/string/strings.str").write_str(indoc!(r#"
+        #string:(blob_name)
+        Blob[END]
+
+        #string:(blob_tattle)
+        This is a {String:blob_name}.
+        Max HP: {Actor:blob:MaxHP}, flags: {Actor:blob:Flags}[END]
+    "#)).unwrap();
+
+    starpkg()
+        .arg("build")
+        .arg("--no-compile")
+        .current_dir(dir.path())
+        .assert()
+        .success();
+
+    dir.child(".build/strings/00310000.str").assert("#string:31:000\nThis is a Blob.\nMax HP: 12, flags: 16[END]");
+}
+
+#[test]
+fn bad_string_expressions() {
+    let dir = package_with_actor("", BLOB);
+    dir.child("src/string/strings.str").write_str(indoc!(r#"
+        #string:(blob_name)
+        Blob {String:blob_tattle}[END]
+
+        #string:(blob_tattle)
+        A {String:blob_name}, with {Actor:blob:MaxFP} FP.
+        Or {Actor:blob:Speed}.[END]
+    "#)).unwrap();
+
+    starpkg()
+        .arg("build")
+        .arg("--no-compile")
+        .current_dir(dir.path())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("error[SP0209]: string {String:test_pkg/blob_name} includes itself"))
+        .stderr(predicate::str::contains("strings.str:5:11"))
+        .stderr(predicate::str::contains("error[SP0208]: actor {Actor:test_pkg/blob} has no field 'MaxFP'"))
+        .stderr(predicate::str::contains("strings.str:5:40"))
+        .stderr(predicate::str::contains("actor {Actor:test_pkg/blob} has fields: Index, MaxHP, Flags"))
+        .stderr(predicate::str::contains("error[SP0208]: actor {Actor:test_pkg/blob} has no field 'Speed'"))
+        .stderr(predicate::str::contains("strings.str:6:16"));
+}